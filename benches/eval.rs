@@ -0,0 +1,45 @@
+// Run with `cargo bench`, each program is evaluated a few
+// times and the mean time printed.
+use lisp_rs::lisp_rs_eval;
+use std::time::Instant;
+
+const PROGRAMS: [(&str, &str); 3] = [
+  (
+    "fib",
+    "(begin
+       (define (fib n)
+         (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))
+       (fib 20))",
+  ),
+  (
+    "loop",
+    "(begin
+       (define (loop i acc)
+         (if (= i 0) acc (loop (- i 1) (+ acc i))))
+       (loop 50000 0))",
+  ),
+  (
+    "closures",
+    "(begin
+       (define (adder n) (lambda (x) (+ x n)))
+       (define add1 (adder 1))
+       (fold-left (lambda (acc x) (add1 acc)) 0 (iota 50000)))",
+  ),
+];
+
+fn main() {
+  let runs = 5;
+  for (name, program) in PROGRAMS {
+    let result = lisp_rs_eval(program);
+    let start = Instant::now();
+    for _ in 0..runs {
+      assert_eq!(lisp_rs_eval(program), result);
+    }
+    let mean =
+      start.elapsed().as_secs_f64() * 1000.0 / runs as f64;
+    println!(
+      "{:<10} {:>10.2} ms  = {}",
+      name, mean, result
+    );
+  }
+}
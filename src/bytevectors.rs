@@ -0,0 +1,379 @@
+use crate::env::*;
+use crate::eval::{alloc_vec, EvalError};
+use crate::numbers::{normalize, to_bigint};
+use crate::object::*;
+use crate::strings::{check_args, index_arg, string_arg};
+use num_bigint::{BigInt, Sign};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub fn new_bytevector(bytes: Vec<u8>) -> Object {
+  Object::Bytevector(Rc::new(RefCell::new(bytes)))
+}
+
+fn charged(
+  bytes: Vec<u8>,
+  env: &Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  env.borrow().charge(bytes.len())?;
+  Ok(new_bytevector(bytes))
+}
+
+fn bytevector_arg(
+  obj: &Object,
+) -> Result<&Rc<RefCell<Vec<u8>>>, EvalError> {
+  match obj {
+    Object::Bytevector(b) => Ok(b),
+    _ => Err(format!("{} is not a bytevector", obj).into()),
+  }
+}
+
+pub fn byte_arg(obj: &Object) -> Result<u8, EvalError> {
+  match obj {
+    Object::Integer(n) => u8::try_from(*n).ok(),
+    _ => None,
+  }
+  .ok_or_else(|| format!("{} is not a byte", obj).into())
+}
+
+// The optional start and end arguments at list[from] and
+// after, defaulting to the whole of len.
+fn range_args(
+  list: &[Object],
+  from: usize,
+  len: usize,
+) -> Result<(usize, usize), EvalError> {
+  let start = match list.get(from) {
+    Some(start) => index_arg(start)?,
+    None => 0,
+  };
+  let end = match list.get(from + 1) {
+    Some(end) => index_arg(end)?,
+    None => len,
+  };
+  if start > end || end > len {
+    return Err(
+      format!(
+        "Invalid range {} {} for bytevector of length {}",
+        start, end, len
+      )
+      .into(),
+    );
+  }
+  Ok((start, end))
+}
+
+pub fn eval_bytevector(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  let bytes = list[1..].iter().map(byte_arg);
+  charged(bytes.collect::<Result<_, _>>()?, env)
+}
+
+pub fn eval_make_bytevector(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 2)?;
+  let k = index_arg(&list[1])?;
+  let fill = match list.get(2) {
+    Some(fill) => byte_arg(fill)?,
+    None => 0,
+  };
+  env.borrow().charge(k)?;
+  let mut bytes = alloc_vec(k)?;
+  bytes.resize(k, fill);
+  Ok(new_bytevector(bytes))
+}
+
+pub fn eval_is_bytevector(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 1)?;
+  Ok(Object::Bool(matches!(list[1], Object::Bytevector(_))))
+}
+
+pub fn eval_bytevector_length(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 1)?;
+  let b = bytevector_arg(&list[1])?;
+  Ok(Object::Integer(b.borrow().len() as i64))
+}
+
+pub fn eval_bytevector_u8_ref(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 2, 2)?;
+  let b = bytevector_arg(&list[1])?.borrow();
+  let k = index_arg(&list[2])?;
+  match b.get(k) {
+    Some(byte) => Ok(Object::Integer(*byte as i64)),
+    None => Err(
+      format!(
+        "Index {} out of range for bytevector of length {}",
+        k,
+        b.len()
+      )
+      .into(),
+    ),
+  }
+}
+
+pub fn eval_bytevector_u8_set(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 3, 3)?;
+  let mut b = bytevector_arg(&list[1])?.borrow_mut();
+  let k = index_arg(&list[2])?;
+  let byte = byte_arg(&list[3])?;
+  let len = b.len();
+  match b.get_mut(k) {
+    Some(slot) => *slot = byte,
+    None => {
+      return Err(
+        format!(
+        "Index {} out of range for bytevector of length {}",
+        k, len
+      )
+        .into(),
+      )
+    }
+  }
+  Ok(Object::Void)
+}
+
+// (bytevector-copy bv start end) copies out a slice.
+pub fn eval_bytevector_copy(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 3)?;
+  let b = bytevector_arg(&list[1])?.borrow();
+  let (start, end) = range_args(list, 2, b.len())?;
+  charged(b[start..end].to_vec(), env)
+}
+
+pub fn eval_bytevector_append(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  let mut bytes = Vec::new();
+  for obj in list[1..].iter() {
+    bytes.extend_from_slice(&bytevector_arg(obj)?.borrow());
+  }
+  charged(bytes, env)
+}
+
+pub fn eval_utf8_to_string(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 3)?;
+  let b = bytevector_arg(&list[1])?.borrow();
+  let (start, end) = range_args(list, 2, b.len())?;
+  match String::from_utf8(b[start..end].to_vec()) {
+    Ok(s) => {
+      env.borrow().charge(s.len())?;
+      Ok(Object::String(s.into()))
+    }
+    Err(_) => {
+      Err(format!("{} is not valid UTF-8", list[1]).into())
+    }
+  }
+}
+
+pub fn eval_string_to_utf8(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 1)?;
+  charged(string_arg(&list[1])?.as_bytes().to_vec(), env)
+}
+
+fn endianness_arg(obj: &Object) -> Result<bool, EvalError> {
+  match obj {
+    Object::Symbol(s) if s == "big" => Ok(true),
+    Object::Symbol(s) if s == "little" => Ok(false),
+    _ => Err(format!("Invalid endianness {}", obj).into()),
+  }
+}
+
+// The integer in the size bytes at index k.
+// (bytevector-uint-ref bv k endianness size) and
+// bytevector-sint-ref, endianness being 'big or 'little.
+pub fn eval_bytevector_int_ref(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 4, 4)?;
+  let b = bytevector_arg(&list[1])?.borrow();
+  let k = index_arg(&list[2])?;
+  let big = endianness_arg(&list[3])?;
+  let size = index_arg(&list[4])?;
+  let (start, end) = match k.checked_add(size) {
+    Some(end) if end <= b.len() => (k, end),
+    _ => {
+      return Err(
+        format!(
+          "Invalid range {} {} for bytevector of length {}",
+          k,
+          k.saturating_add(size),
+          b.len()
+        )
+        .into(),
+      )
+    }
+  };
+
+  let bytes = &b[start..end];
+  let n = match (list[0].to_string().as_str(), big) {
+    ("bytevector-sint-ref", true) => {
+      BigInt::from_signed_bytes_be(bytes)
+    }
+    ("bytevector-sint-ref", false) => {
+      BigInt::from_signed_bytes_le(bytes)
+    }
+    (_, true) => BigInt::from_bytes_be(Sign::Plus, bytes),
+    (_, false) => BigInt::from_bytes_le(Sign::Plus, bytes),
+  };
+  Ok(normalize(n))
+}
+
+// (bytevector-uint-set! bv k n endianness size) and
+// bytevector-sint-set!, n has to fit in size bytes.
+pub fn eval_bytevector_int_set(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 5, 5)?;
+  let mut b = bytevector_arg(&list[1])?.borrow_mut();
+  let k = index_arg(&list[2])?;
+  let n = to_bigint(&list[3]).ok_or_else(|| {
+    format!("{} is not an exact integer", list[3])
+  })?;
+  let big = endianness_arg(&list[4])?;
+  let size = index_arg(&list[5])?;
+  let end = match k.checked_add(size) {
+    Some(end) if end <= b.len() => end,
+    _ => {
+      return Err(
+        format!(
+          "Invalid range {} {} for bytevector of length {}",
+          k,
+          k.saturating_add(size),
+          b.len()
+        )
+        .into(),
+      )
+    }
+  };
+
+  let signed =
+    list[0].to_string() == "bytevector-sint-set!";
+  if n.sign() == Sign::Minus && !signed {
+    return Err(
+      format!("{} is not an unsigned integer", n).into(),
+    );
+  }
+  let (mut bytes, pad) = match n.sign() {
+    Sign::Minus => (n.to_signed_bytes_le(), 0xff),
+    _ if signed => (n.to_signed_bytes_le(), 0),
+    _ => (n.to_bytes_le().1, 0),
+  };
+  if bytes.len() > size {
+    return Err(
+      format!("{} does not fit in {} bytes", n, size)
+        .into(),
+    );
+  }
+  bytes.resize(size, pad);
+  if big {
+    bytes.reverse();
+  }
+  b[k..end].copy_from_slice(&bytes);
+  Ok(Object::Void)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::env::*;
+  use crate::eval::eval;
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  #[test]
+  fn test_bytevector_procedures() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define b (make-bytevector 4 0))
+            (bytevector-u8-set! b 0 255)
+            (list
+                b
+                (bytevector-u8-ref #u8(1 2 3) 2)
+                (bytevector-length (bytevector 1 2))
+                (bytevector-copy #u8(1 2 3 4) 1 3)
+                (bytevector-append #u8(1) #u8() #u8(2 3))
+                (utf8->string #u8(104 105 226 130 172))
+                (string->utf8 \"A\u{e9}\")
+                (bytevector? #u8())))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result.to_string(),
+      "(#u8(255 0 0 0) 3 2 #u8(2 3) #u8(1 2 3) hi\u{20ac} \
+       #u8(65 195 169) true)"
+    );
+    assert!(eval("(bytevector 256)", &mut env).is_err());
+    assert!(eval("(bytevector-u8-ref #u8() 0)", &mut env)
+      .is_err());
+    assert!(
+      eval("(utf8->string #u8(255))", &mut env).is_err()
+    );
+    assert!(eval("(bytevector-copy #u8(1) 0 2)", &mut env)
+      .is_err());
+    assert!(eval(
+      "(make-bytevector 1000000000000000000 0)",
+      &mut env
+    )
+    .is_err());
+  }
+
+  #[test]
+  fn test_integer_encoding() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define b (make-bytevector 8 0))
+            (bytevector-uint-set! b 0 258 'big 2)
+            (bytevector-sint-set! b 2 (- 0 2) 'little 2)
+            (define c (make-bytevector 9 0))
+            (bytevector-uint-set! c 1 18446744073709551615 'little 8)
+            (list
+                b
+                (bytevector-uint-ref b 0 'big 2)
+                (bytevector-uint-ref b 0 'little 2)
+                (bytevector-sint-ref b 2 'little 2)
+                (bytevector-uint-ref b 2 'little 2)
+                (bytevector-uint-ref c 1 'big 8)
+                (bytevector-sint-ref c 1 'big 8)))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result.to_string(),
+      "(#u8(1 2 254 255 0 0 0 0) 258 513 -2 65534 \
+       18446744073709551615 -1)"
+    );
+    for program in [
+      "(bytevector-uint-set! (make-bytevector 1) 0 256 'big 1)",
+      "(bytevector-uint-set! (make-bytevector 1) 0 (- 0 1) 'big 1)",
+      "(bytevector-sint-set! (make-bytevector 1) 0 128 'big 1)",
+      "(bytevector-uint-ref #u8(1) 0 'big 2)",
+      "(bytevector-uint-ref #u8(1) 0 'middle 1)",
+    ] {
+      assert!(eval(program, &mut env).is_err(), "{}", program);
+    }
+  }
+}
@@ -0,0 +1,223 @@
+use crate::eval::EvalError;
+use crate::object::*;
+
+fn char_arg(obj: &Object) -> Result<char, EvalError> {
+  match obj {
+    Object::Char(c) => Ok(*c),
+    _ => Err(format!("{} is not a char", obj).into()),
+  }
+}
+
+// The procedures taking a single char, and integer->char.
+pub fn eval_char_procedure(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  let name = list[0].to_string();
+  if list.len() != 2 {
+    return Err(
+      format!("Invalid number of arguments for {}", name)
+        .into(),
+    );
+  }
+
+  match name.as_str() {
+    "char?" => {
+      return Ok(Object::Bool(matches!(
+        list[1],
+        Object::Char(_)
+      )))
+    }
+    "integer->char" => {
+      return match list[1] {
+        Object::Integer(n) => u32::try_from(n)
+          .ok()
+          .and_then(char::from_u32)
+          .map(Object::Char)
+          .ok_or_else(|| {
+            format!("{} is not a valid char", n).into()
+          }),
+        _ => Err(
+          format!("{} is not an integer", list[1]).into(),
+        ),
+      }
+    }
+    _ => {}
+  }
+
+  let c = char_arg(&list[1])?;
+  let result = match name.as_str() {
+    "char->integer" => Object::Integer(c as i64),
+    "char-upcase" => Object::Char(upcase(c)),
+    "char-downcase" | "char-foldcase" => {
+      Object::Char(downcase(c))
+    }
+    "char-alphabetic?" => Object::Bool(c.is_alphabetic()),
+    "char-numeric?" => Object::Bool(c.is_numeric()),
+    "char-whitespace?" => Object::Bool(c.is_whitespace()),
+    "char-upper-case?" => Object::Bool(c.is_uppercase()),
+    "char-lower-case?" => Object::Bool(c.is_lowercase()),
+    "digit-value" => match digit_value(c) {
+      Some(d) => Object::Integer(d as i64),
+      None => Object::Bool(false),
+    },
+    _ => {
+      return Err(
+        format!("Unknown keyword: {}", name).into(),
+      )
+    }
+  };
+  Ok(result)
+}
+
+// The first char of every run of ten decimal digits, the
+// chars of Unicode category Nd, as of Unicode 16.
+const DIGIT_ZEROS: [u32; 76] = [
+  0x0030, 0x0660, 0x06F0, 0x07C0, 0x0966, 0x09E6, 0x0A66,
+  0x0AE6, 0x0B66, 0x0BE6, 0x0C66, 0x0CE6, 0x0D66, 0x0DE6,
+  0x0E50, 0x0ED0, 0x0F20, 0x1040, 0x1090, 0x17E0, 0x1810,
+  0x1946, 0x19D0, 0x1A80, 0x1A90, 0x1B50, 0x1BB0, 0x1C40,
+  0x1C50, 0xA620, 0xA8D0, 0xA900, 0xA9D0, 0xA9F0, 0xAA50,
+  0xABF0, 0xFF10, 0x104A0, 0x10D30, 0x10D40, 0x11066,
+  0x110F0, 0x11136, 0x111D0, 0x112F0, 0x11450, 0x114D0,
+  0x11650, 0x116C0, 0x116D0, 0x116DA, 0x11730, 0x118E0,
+  0x11950, 0x11BF0, 0x11C50, 0x11D50, 0x11DA0, 0x11F50,
+  0x16130, 0x16A60, 0x16AC0, 0x16B50, 0x16D70, 0x1CCF0,
+  0x1D7CE, 0x1D7D8, 0x1D7E2, 0x1D7EC, 0x1D7F6, 0x1E140,
+  0x1E2F0, 0x1E4F0, 0x1E5F1, 0x1E950, 0x1FBF0,
+];
+
+fn digit_value(c: char) -> Option<u32> {
+  let c = c as u32;
+  let i = DIGIT_ZEROS.partition_point(|zero| *zero <= c);
+  let d = c - DIGIT_ZEROS[..i].last()?;
+  (d < 10).then_some(d)
+}
+
+// Case mappings that would turn one char into several, like
+// the German sharp s, leave the char as it is.
+fn upcase(c: char) -> char {
+  let mut upper = c.to_uppercase();
+  match (upper.next(), upper.next()) {
+    (Some(u), None) => u,
+    _ => c,
+  }
+}
+
+fn downcase(c: char) -> char {
+  let mut lower = c.to_lowercase();
+  match (lower.next(), lower.next()) {
+    (Some(l), None) => l,
+    _ => c,
+  }
+}
+
+// char=? char<? and the rest, true when every neighbouring
+// pair of arguments is in order.
+pub fn eval_char_compare(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  let name = list[0].to_string();
+  if list.len() < 3 {
+    return Err(
+      format!("Invalid number of arguments for {}", name)
+        .into(),
+    );
+  }
+
+  let fold = name.starts_with("char-ci");
+  let mut chars = Vec::new();
+  for obj in list[1..].iter() {
+    let c = char_arg(obj)?;
+    chars.push(if fold { downcase(c) } else { c });
+  }
+  let op = name
+    .trim_start_matches("char-ci")
+    .trim_start_matches("char");
+  let ordered = chars.windows(2).all(|pair| match op {
+    "=?" => pair[0] == pair[1],
+    "<?" => pair[0] < pair[1],
+    ">?" => pair[0] > pair[1],
+    "<=?" => pair[0] <= pair[1],
+    _ => pair[0] >= pair[1],
+  });
+  Ok(Object::Bool(ordered))
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::env::*;
+  use crate::eval::eval;
+  use crate::object::*;
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  #[test]
+  fn test_char_procedures() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (list
+            (char->integer #\\A)
+            (integer->char 955)
+            (char-upcase #\\z)
+            (char-alphabetic? #\\3)
+            (digit-value #\\7)
+            (char? #\\newline))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result,
+      Object::ListData(Rc::new(vec![
+        Object::Integer(65),
+        Object::Char('λ'),
+        Object::Char('Z'),
+        Object::Bool(false),
+        Object::Integer(7),
+        Object::Bool(true),
+      ]))
+    );
+  }
+
+  #[test]
+  fn test_char_compare() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (list
+            (char<? #\\a #\\b #\\c)
+            (char<? #\\a #\\c #\\b)
+            (char=? #\\a #\\A)
+            (char-ci=? #\\a #\\A))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result,
+      Object::ListData(Rc::new(vec![
+        Object::Bool(true),
+        Object::Bool(false),
+        Object::Bool(false),
+        Object::Bool(true),
+      ]))
+    );
+  }
+
+  #[test]
+  fn test_digit_value() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (list
+            (digit-value #\\x0663)
+            (digit-value #\\x1D7D9)
+            (digit-value #\\x0E59)
+            (digit-value #\\x00BD)
+            (digit-value #\\x2163)
+            (digit-value #\\a))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result.to_string(),
+      "(3 1 9 false false false)"
+    );
+  }
+}
@@ -0,0 +1,351 @@
+use crate::eval::expand_do;
+use crate::object::*;
+use crate::symbols::{self, Symbol};
+use std::collections::HashSet;
+use std::rc::Rc;
+
+// The variables of an environment the code will run in: the
+// slots it is made with, and the names a define inside it
+// may add later.
+struct Scope {
+  slots: Vec<Symbol>,
+  defined: HashSet<Symbol>,
+}
+
+impl Scope {
+  fn new(
+    slots: Vec<Symbol>,
+    vars: &[Symbol],
+    body: &[Object],
+  ) -> Scope {
+    let mut defined: HashSet<Symbol> =
+      vars.iter().copied().collect();
+    for obj in body.iter() {
+      defined_names(obj, &mut defined);
+    }
+    Scope { slots, defined }
+  }
+}
+
+// Names a define in obj may bind. Defines in nested scopes
+// are counted too, which at worst leaves a variable to be
+// looked up by name.
+fn defined_names(
+  obj: &Object,
+  names: &mut HashSet<Symbol>,
+) {
+  let list = match obj {
+    Object::List(list) => list,
+    _ => return,
+  };
+  match (list.first(), list.get(1)) {
+    (Some(Object::Keyword(k)), Some(Object::Symbol(s)))
+      if k == "define" =>
+    {
+      names.insert(*s);
+    }
+    (Some(Object::Keyword(k)), Some(Object::List(l)))
+      if k == "define" =>
+    {
+      if let Some(Object::Symbol(s)) = l.first() {
+        names.insert(*s);
+      }
+    }
+    (Some(Object::Keyword(k)), _)
+      if k == "define-record-type" =>
+    {
+      symbols_in(obj, names);
+    }
+    _ => {}
+  }
+  for obj in list.iter() {
+    defined_names(obj, names);
+  }
+}
+
+fn symbols_in(obj: &Object, names: &mut HashSet<Symbol>) {
+  match obj {
+    Object::Symbol(s) => {
+      names.insert(*s);
+    }
+    Object::List(list) => {
+      list.iter().for_each(|obj| symbols_in(obj, names))
+    }
+    _ => {}
+  }
+}
+
+fn symbol_list(list: &[Object]) -> Option<Vec<Symbol>> {
+  list
+    .iter()
+    .map(|obj| match obj {
+      Object::Symbol(s) => Some(*s),
+      _ => None,
+    })
+    .collect()
+}
+
+// A variable bound in the slots of an enclosing scope becomes
+// a Local, unless a scope in between may define it too.
+// Globals and anything defined are looked up by name.
+fn lookup(s: Symbol, scopes: &[Scope]) -> Object {
+  if matches!(
+    s,
+    symbols::TRUE | symbols::FALSE | symbols::NIL
+  ) {
+    return Object::Symbol(s);
+  }
+  for (depth, scope) in scopes.iter().rev().enumerate() {
+    if let Some(index) =
+      scope.slots.iter().rposition(|n| *n == s)
+    {
+      return Object::Local(s, depth, index);
+    }
+    if scope.defined.contains(&s) {
+      break;
+    }
+  }
+  Object::Symbol(s)
+}
+
+fn resolve(
+  obj: &Object,
+  scopes: &mut Vec<Scope>,
+) -> Object {
+  match obj {
+    Object::Symbol(s) => lookup(*s, scopes),
+    Object::List(list) => {
+      match resolve_list(list, scopes) {
+        Some(list) => Object::List(Rc::new(list)),
+        None => obj.clone(),
+      }
+    }
+    _ => obj.clone(),
+  }
+}
+
+fn resolve_all(
+  list: &[Object],
+  scopes: &mut Vec<Scope>,
+) -> Vec<Object> {
+  list.iter().map(|obj| resolve(obj, scopes)).collect()
+}
+
+// Resolve body in a new environment, made with the given
+// slots and with vars set in it by name.
+fn resolve_scope(
+  slots: Vec<Symbol>,
+  vars: &[Symbol],
+  body: &[Object],
+  scopes: &mut Vec<Scope>,
+) -> Vec<Object> {
+  scopes.push(Scope::new(slots, vars, body));
+  let body = resolve_all(body, scopes);
+  scopes.pop();
+  body
+}
+
+// Each special form is resolved with the environments the
+// evaluator makes for it. A form the evaluator would reject
+// is left as it is, to fail the same way when it runs.
+fn resolve_list(
+  list: &[Object],
+  scopes: &mut Vec<Scope>,
+) -> Option<Vec<Object>> {
+  let keyword = match list.first() {
+    Some(Object::Keyword(k)) => k.as_str(),
+    _ => "",
+  };
+  let mut items = list.to_vec();
+  match keyword {
+    "quote" | "quasiquote" | "define-record-type" => {
+      return None
+    }
+    "do" => {
+      let expanded = expand_do(list).ok()?;
+      return match resolve(&expanded, scopes) {
+        Object::List(list) => Some(list.to_vec()),
+        _ => None,
+      };
+    }
+    "define" if list.len() == 3 => match &list[1] {
+      Object::Symbol(_) => {
+        items[2] = resolve(&list[2], scopes);
+      }
+      Object::List(l) => {
+        let params = symbol_list(l.get(1..)?)?;
+        items[2] =
+          resolve_scope(params, &[], &list[2..], scopes)
+            .pop()?;
+      }
+      _ => return None,
+    },
+    "lambda" if list.len() >= 3 => {
+      let params = match &list[1] {
+        Object::List(params) => symbol_list(params)?,
+        _ => return None,
+      };
+      items[2] =
+        resolve_scope(params, &[], &list[2..3], scopes)
+          .pop()?;
+    }
+    "let" if list.len() >= 3 => {
+      let bindings = match &list[1] {
+        Object::List(bindings) => bindings,
+        _ => return None,
+      };
+      let mut names = Vec::new();
+      let mut resolved = Vec::new();
+      for binding in bindings.iter() {
+        match binding {
+          Object::List(b) if b.len() == 2 => {
+            match &b[0] {
+              Object::Symbol(name) => names.push(*name),
+              _ => return None,
+            }
+            resolved.push(Object::List(Rc::new(vec![
+              b[0].clone(),
+              resolve(&b[1], scopes),
+            ])));
+          }
+          _ => return None,
+        }
+      }
+      items[1] = Object::List(Rc::new(resolved));
+      items.splice(
+        2..,
+        resolve_scope(names, &[], &list[2..], scopes),
+      );
+    }
+    "begin" => {
+      items.splice(
+        1..,
+        resolve_scope(vec![], &[], &list[1..], scopes),
+      );
+    }
+    // The clauses run in an environment holding the raised
+    // object, the body in one of its own.
+    "guard" if list.len() >= 3 => {
+      let spec = match &list[1] {
+        Object::List(spec) => spec,
+        _ => return None,
+      };
+      let var = match spec.first() {
+        Some(Object::Symbol(var)) => *var,
+        _ => return None,
+      };
+      let mut clauses = vec![spec[0].clone()];
+      clauses.extend(resolve_scope(
+        vec![],
+        &[var],
+        &spec[1..],
+        scopes,
+      ));
+      items[1] = Object::List(Rc::new(clauses));
+      items.splice(
+        2..,
+        resolve_scope(vec![], &[], &list[2..], scopes),
+      );
+    }
+    "shift" if list.len() >= 3 => {
+      let k = match &list[1] {
+        Object::Symbol(k) => *k,
+        _ => return None,
+      };
+      items.splice(
+        2..,
+        resolve_scope(vec![], &[k], &list[2..], scopes),
+      );
+    }
+    "define" | "lambda" | "let" | "guard" | "shift" => {
+      return None
+    }
+    _ => items = resolve_all(list, scopes),
+  }
+  Some(items)
+}
+
+// Resolve the variables of expanded code to the slots of the
+// environments they will be found in, so that looking them
+// up doesn't hash names or search every environment on the
+// way.
+pub fn compile(obj: &Object) -> Object {
+  resolve(obj, &mut Vec::new())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::env::*;
+  use crate::eval::eval;
+  use crate::parser::parse;
+  use std::cell::RefCell;
+
+  #[test]
+  fn test_lexical_addresses() {
+    let program = "
+        (begin
+            (define n 10)
+            (lambda (x y)
+                (let ((z (+ x n)))
+                    (begin (define y 2) (list x y z n)))))
+        ";
+    let compiled = compile(&parse(program).unwrap());
+    let body = match &compiled {
+      Object::List(list) => list[2].clone(),
+      _ => panic!("{}", compiled),
+    };
+    let addresses = |obj: &Object| {
+      let mut found = Vec::new();
+      fn walk(obj: &Object, found: &mut Vec<String>) {
+        match obj {
+          Object::Local(s, depth, index) => {
+            found.push(format!("{}@{}.{}", s, depth, index))
+          }
+          Object::Symbol(s) => found.push(s.to_string()),
+          Object::List(list) => {
+            list.iter().for_each(|obj| walk(obj, found))
+          }
+          _ => {}
+        }
+      }
+      walk(obj, &mut found);
+      found.join(" ")
+    };
+    // n is defined and y may be redefined in the begin, so
+    // they are looked up by name there.
+    assert_eq!(
+      addresses(&body),
+      "x y z x@0.0 n y x@2.0 y z@1.0 n"
+    );
+  }
+
+  #[test]
+  fn test_compiled_scopes() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define (make-counter start)
+                (let ((n start) (n (+ start 1)))
+                    (lambda (step)
+                        (begin
+                            (define n (+ n step))
+                            n))))
+            (define (shadow x)
+                (list
+                    x
+                    (let ((x (* x 2))) x)
+                    ((lambda (x x) (+ x 0)) 1 2)
+                    (guard (x (#t x)) (raise (+ x 1)))
+                    (do ((i 0 (+ i 1)) (acc x (+ acc i)))
+                        ((= i 3) acc))))
+            (list
+                ((make-counter 5) 10)
+                (shadow 1)
+                (reset (+ 1 (shift k (k (k 1)))))))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result.to_string(), "(16 (1 2 2 2 4) 3)");
+  }
+}
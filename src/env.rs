@@ -1,12 +1,55 @@
+use crate::macros::Macro;
 use crate::object::Object;
-use std::cell::RefCell;
+use crate::symbols::Symbol;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::rc::Rc;
 
+#[derive(Debug, PartialEq, Default)]
+pub struct Quota {
+  limit: usize,
+  used: Cell<usize>,
+}
+
+impl Quota {
+  pub fn new(limit: usize) -> Self {
+    Quota {
+      limit,
+      used: Cell::new(0),
+    }
+  }
+
+  // Record an allocation of approximately `bytes` bytes and
+  // fail once the running total goes over the limit.
+  pub fn charge(&self, bytes: usize) -> Result<(), String> {
+    let used = self.used.get().saturating_add(bytes);
+    self.used.set(used);
+    if used > self.limit {
+      return Err(format!(
+        "Resource limit exceeded: allocated {} of {} bytes",
+        used, self.limit
+      ));
+    }
+    Ok(())
+  }
+}
+
+// The variables an environment is made with, the parameters
+// of a procedure or the bindings of a let, are kept in slots
+// that compiled code reads by index. Anything defined later
+// goes in vars.
 #[derive(Debug, PartialEq, Default)]
 pub struct Env {
   parent: Option<Rc<RefCell<Env>>>,
-  vars: HashMap<String, Object>,
+  names: Rc<Vec<Symbol>>,
+  slots: Vec<Object>,
+  vars: HashMap<Symbol, Object>,
+  macros: HashMap<Symbol, Rc<Macro>>,
+  // The aliases that the macros defined here give the free
+  // identifiers of their templates, and the names they stand
+  // for.
+  aliases: HashMap<Symbol, Symbol>,
+  quota: Option<Rc<Quota>>,
 }
 
 impl Env {
@@ -14,26 +57,49 @@ impl Env {
     Default::default()
   }
 
-  pub fn update(&mut self, data: Rc<RefCell<Self>>) {
-    self.vars.extend(
-      data
-        .borrow()
-        .vars
-        .iter()
-        .map(|(k, v)| (k.clone(), v.clone())),
-    );
+  pub fn with_quota(limit: usize) -> Self {
+    Env {
+      quota: Some(Rc::new(Quota::new(limit))),
+      ..Default::default()
+    }
   }
 
   pub fn extend(parent: Rc<RefCell<Self>>) -> Env {
+    Env::with_slots(parent, Rc::default(), Vec::new())
+  }
+
+  pub fn with_slots(
+    parent: Rc<RefCell<Self>>,
+    names: Rc<Vec<Symbol>>,
+    slots: Vec<Object>,
+  ) -> Env {
+    let quota = parent.borrow().quota.clone();
     Env {
+      names,
+      slots,
       vars: HashMap::new(),
+      macros: HashMap::new(),
+      aliases: HashMap::new(),
       parent: Some(parent),
+      quota,
     }
   }
 
-  pub fn get(&self, name: &str) -> Option<Object> {
-    match self.vars.get(name) {
-      Some(value) => Some(value.clone()),
+  // A name given twice to a procedure or let is bound to the
+  // last value, so slots are searched from the end.
+  fn slot(&self, name: Symbol) -> Option<usize> {
+    self.names.iter().rposition(|n| *n == name)
+  }
+
+  pub fn get(&self, name: Symbol) -> Option<Object> {
+    if let Some(i) = self.slot(name) {
+      return Some(self.slots[i].clone());
+    }
+    if let Some(value) = self.vars.get(&name) {
+      return Some(value.clone());
+    }
+    match self.aliases.get(&name) {
+      Some(of) => self.get(*of),
       None => self
         .parent
         .as_ref()
@@ -41,7 +107,70 @@ impl Env {
     }
   }
 
-  pub fn set(&mut self, name: &str, val: Object) {
-    self.vars.insert(name.to_string(), val);
+  // The slot index of the environment depth levels out.
+  pub fn get_at(
+    &self,
+    depth: usize,
+    index: usize,
+  ) -> Option<Object> {
+    match (depth, &self.parent) {
+      (0, _) => self.slots.get(index).cloned(),
+      (_, Some(parent)) => {
+        parent.borrow().get_at(depth - 1, index)
+      }
+      (_, None) => None,
+    }
+  }
+
+  pub fn parent(&self) -> Option<&Rc<RefCell<Env>>> {
+    self.parent.as_ref()
+  }
+
+  pub fn values(&self) -> impl Iterator<Item = &Object> {
+    self.slots.iter().chain(self.vars.values())
+  }
+
+  pub fn set(&mut self, name: Symbol, val: Object) {
+    match self.slot(name) {
+      Some(i) => self.slots[i] = val,
+      None => {
+        self.vars.insert(name, val);
+      }
+    }
+  }
+
+  pub fn macros(&self) -> impl Iterator<Item = &Rc<Macro>> {
+    self.macros.values()
+  }
+
+  pub fn get_macro(
+    &self,
+    name: Symbol,
+  ) -> Option<Rc<Macro>> {
+    if let Some(m) = self.macros.get(&name) {
+      return Some(m.clone());
+    }
+    match self.aliases.get(&name) {
+      Some(of) => self.get_macro(*of),
+      None => self
+        .parent
+        .as_ref()
+        .and_then(|o| o.borrow().get_macro(name)),
+    }
+  }
+
+  pub fn set_alias(&mut self, alias: Symbol, of: Symbol) {
+    self.aliases.insert(alias, of);
+  }
+
+  pub fn set_macro(&mut self, name: Symbol, m: Macro) {
+    self.macros.insert(name, Rc::new(m));
+  }
+
+  pub fn charge(&self, bytes: usize) -> Result<(), String> {
+    match &self.quota {
+      Some(quota) => quota.charge(bytes),
+      None => Ok(()),
+    }
   }
 }
@@ -0,0 +1,291 @@
+use crate::eval::EvalError;
+use crate::object::*;
+use std::rc::Rc;
+
+// Lists, strings, procedures and the other shared objects
+// are eqv? when they are the same object. Procedures that
+// return part of a list, like cdr, make a new one, so
+// (eq? (cdr l) (cdr l)) is #f. Persistent maps and vectors
+// are copied wherever they go, so they have no identity to
+// compare. Empty lists, strings, maps and vectors are all
+// eqv? to each other.
+pub fn is_eqv(left: &Object, right: &Object) -> bool {
+  match (left, right) {
+    (Object::Void, Object::Void) => true,
+    (Object::Integer(l), Object::Integer(r)) => l == r,
+    (Object::BigInt(l), Object::BigInt(r)) => l == r,
+    (Object::Rational(l), Object::Rational(r)) => l == r,
+    // Unlike =, 0.0 and -0.0 differ and NaN is itself.
+    (Object::Float(l), Object::Float(r)) => {
+      l.to_bits() == r.to_bits()
+    }
+    (Object::Bool(l), Object::Bool(r)) => l == r,
+    (Object::Char(l), Object::Char(r)) => l == r,
+    (Object::Symbol(l), Object::Symbol(r)) => l == r,
+    (
+      Object::Local(l, l_depth, l_index),
+      Object::Local(r, r_depth, r_index),
+    ) => (l, l_depth, l_index) == (r, r_depth, r_index),
+    (Object::Keyword(l), Object::Keyword(r))
+    | (Object::BinaryOp(l), Object::BinaryOp(r)) => l == r,
+    (Object::String(l), Object::String(r)) => {
+      Rc::ptr_eq(l, r) || l.is_empty() && r.is_empty()
+    }
+    (Object::ListData(l), Object::ListData(r)) => {
+      Rc::ptr_eq(l, r) || l.is_empty() && r.is_empty()
+    }
+    (Object::List(l), Object::List(r)) => Rc::ptr_eq(l, r),
+    (
+      Object::Lambda(_, l_body, l_env),
+      Object::Lambda(_, r_body, r_env),
+    ) => {
+      Rc::ptr_eq(l_body, r_body) && Rc::ptr_eq(l_env, r_env)
+    }
+    (Object::Continuation(l), Object::Continuation(r)) => {
+      Rc::ptr_eq(l, r)
+    }
+    (Object::Generator(l), Object::Generator(r))
+    | (Object::Yield(l), Object::Yield(r)) => {
+      Rc::ptr_eq(l, r)
+    }
+    (Object::Promise(l), Object::Promise(r)) => {
+      Rc::ptr_eq(l, r)
+    }
+    (Object::Vector(l), Object::Vector(r)) => {
+      Rc::ptr_eq(l, r)
+    }
+    (Object::Bytevector(l), Object::Bytevector(r)) => {
+      Rc::ptr_eq(l, r)
+    }
+    (Object::Record(l), Object::Record(r)) => {
+      Rc::ptr_eq(l, r)
+    }
+    (Object::RecordType(l), Object::RecordType(r)) => {
+      Rc::ptr_eq(l, r)
+    }
+    (
+      Object::RecordProcedure(l),
+      Object::RecordProcedure(r),
+    ) => Rc::ptr_eq(l, r),
+    (Object::Builtin(l, _), Object::Builtin(r, _)) => {
+      l == r
+    }
+    (Object::HashTable(l), Object::HashTable(r)) => {
+      Rc::ptr_eq(l, r)
+    }
+    (
+      Object::PersistentMap(l),
+      Object::PersistentMap(r),
+    ) => l.is_empty() && r.is_empty(),
+    (
+      Object::PersistentVector(l),
+      Object::PersistentVector(r),
+    ) => l.is_empty() && r.is_empty(),
+    _ => false,
+  }
+}
+
+// Structural equality, eqv? for anything without elements.
+pub fn is_equal(left: &Object, right: &Object) -> bool {
+  Equal::default().equal(left, right)
+}
+
+#[derive(Default)]
+struct Equal {
+  // The pairs of vectors being compared further out. One
+  // that turns up again is taken to be equal, so vectors
+  // that hold themselves are compared in finite time.
+  comparing: Vec<(*const (), *const ())>,
+}
+
+impl Equal {
+  fn equal(
+    &mut self,
+    left: &Object,
+    right: &Object,
+  ) -> bool {
+    match (left, right) {
+      (Object::String(l), Object::String(r)) => l == r,
+      (Object::ListData(l), Object::ListData(r)) => {
+        self.all_equal(l, r)
+      }
+      (Object::List(l), Object::List(r)) => {
+        self.all_equal(l, r)
+      }
+      (Object::Vector(l), Object::Vector(r)) => {
+        if Rc::ptr_eq(l, r) {
+          return true;
+        }
+        let pair =
+          (Rc::as_ptr(l).cast(), Rc::as_ptr(r).cast());
+        if self.comparing.contains(&pair) {
+          return true;
+        }
+        self.comparing.push(pair);
+        let result =
+          self.all_equal(&l.borrow(), &r.borrow());
+        self.comparing.pop();
+        result
+      }
+      (Object::Bytevector(l), Object::Bytevector(r)) => {
+        Rc::ptr_eq(l, r) || l == r
+      }
+      (
+        Object::PersistentMap(l),
+        Object::PersistentMap(r),
+      ) => {
+        l.size() == r.size()
+          && l.iter().all(|(k, v)| {
+            r.get(k).is_some_and(|w| self.equal(v, w))
+          })
+      }
+      (
+        Object::PersistentVector(l),
+        Object::PersistentVector(r),
+      ) => {
+        l.len() == r.len()
+          && l
+            .iter()
+            .zip(r.iter())
+            .all(|(l, r)| self.equal(l, r))
+      }
+      (
+        Object::Error(l_message, l_irritants),
+        Object::Error(r_message, r_irritants),
+      ) => {
+        l_message == r_message
+          && self.all_equal(l_irritants, r_irritants)
+      }
+      _ => is_eqv(left, right),
+    }
+  }
+
+  fn all_equal(
+    &mut self,
+    left: &[Object],
+    right: &[Object],
+  ) -> bool {
+    left.len() == right.len()
+      && left
+        .iter()
+        .zip(right)
+        .all(|(l, r)| self.equal(l, r))
+  }
+}
+
+// eq? is the same as eqv?, numbers and chars are compared by
+// value either way.
+pub fn eval_equality(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  let name = list[0].to_string();
+  if list.len() != 3 {
+    return Err(
+      format!("Invalid number of arguments for {}", name)
+        .into(),
+    );
+  }
+
+  let result = match name.as_str() {
+    "equal?" => is_equal(&list[1], &list[2]),
+    _ => is_eqv(&list[1], &list[2]),
+  };
+  Ok(Object::Bool(result))
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::env::*;
+  use crate::eval::eval;
+  use crate::object::*;
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  #[test]
+  fn test_eq_and_eqv() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define (f x) (list x))
+            (define g (lambda (x) (list x)))
+            (list
+                (eq? 'a 'a)
+                (eq? 'a 'b)
+                (eqv? 1.0 1)
+                (eqv? 100000000000000000000 100000000000000000000)
+                (eqv? 1/2 (/ 2 4))
+                (eq? (list) (list))
+                (eq? (list 1) (list 1))
+                (eq? f f)
+                (eq? f g)
+                (eqv? #\\a #\\a)))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result,
+      Object::ListData(Rc::new(vec![
+        Object::Bool(true),
+        Object::Bool(false),
+        Object::Bool(false),
+        Object::Bool(true),
+        Object::Bool(true),
+        Object::Bool(true),
+        Object::Bool(false),
+        Object::Bool(true),
+        Object::Bool(false),
+        Object::Bool(true),
+      ]))
+    );
+
+    let program = "
+        (begin
+            (define l (list 1 2))
+            (define s \"ab\")
+            (define t s)
+            (list
+                (eq? l l)
+                (eqv? s s)
+                (eq? s t)
+                (eq? (car (list l)) l)
+                (eq? s \"ab\")
+                (eq? (cdr l) (cdr l))))
+        ";
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result.to_string(),
+      "(true true true true false false)"
+    );
+  }
+
+  #[test]
+  fn test_equal_and_numeric_equality() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (list
+            (equal? (list 1 (list 2 \"x\")) (list 1 (list 2 \"x\")))
+            (equal? (list 1 (list 2)) (list 1 (list 3)))
+            (equal? \"abc\" \"abc\")
+            (equal? 1 1.0)
+            (= 1.0 1.0)
+            (= 1 1.0)
+            (= 1/2 0.5)
+            (= 2 2.5))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result,
+      Object::ListData(Rc::new(vec![
+        Object::Bool(true),
+        Object::Bool(false),
+        Object::Bool(true),
+        Object::Bool(false),
+        Object::Bool(true),
+        Object::Bool(true),
+        Object::Bool(true),
+        Object::Bool(false),
+      ]))
+    );
+  }
+}
@@ -1,20 +1,519 @@
+use crate::bytevectors::*;
+use crate::chars::*;
+use crate::compile::compile;
 use crate::env::*;
+use crate::equality::*;
+use crate::gc;
+use crate::hashtables::*;
+use crate::lexer;
+use crate::lists::*;
+use crate::macros::*;
+use crate::numbers::*;
 use crate::object::*;
 use crate::parser::*;
-use std::cell::RefCell;
+use crate::persistent::*;
+use crate::records::*;
+use crate::strings::*;
+use crate::symbols::{self, Symbol};
+use crate::vectors::*;
+use std::cell::{Cell, RefCell};
 use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::mem::size_of;
 use std::rc::Rc;
 
+// Evaluation fails either with an object that was raised
+// and not caught, or while unwinding to the evaluator a
+// continuation was captured in. Errors detected by the
+// evaluator itself are raised as error objects holding the
+// message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+  Raise(Object),
+  Throw(Rc<Continuation>, Object),
+}
+
+impl fmt::Display for EvalError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      EvalError::Raise(obj @ Object::Error(_, _)) => {
+        write!(f, "{}", obj)
+      }
+      EvalError::Raise(obj) => {
+        write!(f, "Uncaught exception: {}", obj)
+      }
+      EvalError::Throw(_, _) => {
+        write!(
+          f,
+          "Continuation invoked outside of its extent"
+        )
+      }
+    }
+  }
+}
+
+impl Error for EvalError {}
+
+impl From<String> for EvalError {
+  fn from(err: String) -> Self {
+    EvalError::Raise(Object::Error(err, vec![]))
+  }
+}
+
+// The rest of a computation, as captured by call/cc: the
+// frames that were waiting for a value at that point. A
+// delimited continuation, captured by shift, only holds the
+// frames up to the enclosing reset and returns to its caller
+// once they are done.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Continuation {
+  machine: usize,
+  frames: Vec<Frame>,
+  delimited: bool,
+}
+
+// A coroutine made by make-generator. Calling it runs the
+// procedure until it yields, keeping the frames in between
+// to resume from on the next call.
+#[derive(Debug, PartialEq)]
+pub struct Generator {
+  state: RefCell<GeneratorState>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum GeneratorState {
+  Start(Object),
+  Suspended(Vec<Frame>),
+  Running,
+  // The value the procedure returned, which every call after
+  // it returns too.
+  Done(Object),
+}
+
+// A value made by delay, computed the first time it is
+// forced.
+#[derive(Debug, PartialEq)]
+pub struct Promise {
+  state: RefCell<PromiseState>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PromiseState {
+  Delayed(Object, Rc<RefCell<Env>>),
+  // The expression produces another promise to force in
+  // place of this one.
+  DelayForce(Object, Rc<RefCell<Env>>),
+  // Being forced. The state it had is put back if the
+  // forcing is abandoned.
+  Running(Box<PromiseState>),
+  Done(Object),
+}
+
+// Work left to do once the expression being evaluated
+// produces a value. The evaluator keeps these on an
+// explicit stack instead of the Rust stack, so that they can
+// be captured and reinstated.
+#[derive(Debug, Clone, PartialEq)]
+enum Frame {
+  // Evaluating the arguments of a call, the procedure is
+  // vals[0].
+  Args {
+    list: Rc<Vec<Object>>,
+    vals: Vec<Object>,
+    env: Rc<RefCell<Env>>,
+  },
+  // Evaluating every element of a list whose head is not a
+  // procedure.
+  Elements {
+    list: Rc<Vec<Object>>,
+    vals: Vec<Object>,
+    env: Rc<RefCell<Env>>,
+  },
+  If {
+    list: Rc<Vec<Object>>,
+    env: Rc<RefCell<Env>>,
+  },
+  // The remaining expressions of a body, starting at next.
+  Body {
+    list: Rc<Vec<Object>>,
+    next: usize,
+    env: Rc<RefCell<Env>>,
+  },
+  // The same for the begin a program is made of, whose
+  // expressions are expanded when they are reached.
+  Toplevel {
+    list: Rc<Vec<Object>>,
+    next: usize,
+    env: Rc<RefCell<Env>>,
+  },
+  Define {
+    name: Symbol,
+    env: Rc<RefCell<Env>>,
+  },
+  Let {
+    list: Rc<Vec<Object>>,
+    vals: Vec<Object>,
+    env: Rc<RefCell<Env>>,
+  },
+  Cond {
+    list: Rc<Vec<Object>>,
+    next: usize,
+    env: Rc<RefCell<Env>>,
+  },
+  Guard {
+    spec: Rc<Vec<Object>>,
+    env: Rc<RefCell<Env>>,
+  },
+  GuardClause {
+    spec: Rc<Vec<Object>>,
+    next: usize,
+    obj: Object,
+    env: Rc<RefCell<Env>>,
+  },
+  Handler(Object),
+  // A handler is running for obj. Handlers at or above
+  // index handler are not visible to raises from inside it.
+  Raise {
+    obj: Object,
+    continuable: bool,
+    handler: usize,
+  },
+  // The before thunk of dynamic-wind is running.
+  Winding {
+    before: Object,
+    thunk: Object,
+    after: Object,
+  },
+  // The thunk of dynamic-wind is running.
+  Wind {
+    id: usize,
+    before: Object,
+    after: Object,
+  },
+  // Return this value once the current expression is done.
+  Discard(Object),
+  // Delimits the continuations captured by shift.
+  Prompt,
+  // A generator is running, yield returns from here.
+  Generator(Rc<Generator>),
+  Force(Rc<Promise>),
+  // Waiting for the test of a while loop.
+  WhileTest {
+    list: Rc<Vec<Object>>,
+    env: Rc<RefCell<Env>>,
+  },
+  // Waiting for the body of a while loop.
+  WhileBody {
+    list: Rc<Vec<Object>>,
+    env: Rc<RefCell<Env>>,
+  },
+  // Applying func to the elements of lists at index. acc
+  // holds the results so far, or the value of a fold.
+  Walk {
+    kind: Walk,
+    func: Object,
+    lists: Vec<Vec<Object>>,
+    index: usize,
+    acc: Vec<Object>,
+  },
+  // Calling pred on the items from index until it returns
+  // true.
+  Find {
+    kind: Find,
+    pred: Object,
+    x: Object,
+    items: Vec<Object>,
+    index: usize,
+  },
+  // Waiting for less? to compare the next pair.
+  Sort {
+    less: Object,
+    sort: MergeSort,
+  },
+}
+
+// The procedures that go through lists calling a procedure
+// on their elements.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Walk {
+  ForEach,
+  Map,
+  Filter,
+  FoldLeft,
+  FoldRight,
+  Reduce,
+  VectorMap,
+}
+
+// The searches that take a procedure to test the items with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Find {
+  // (member x list compare) calls (compare x item).
+  Member,
+  // (assoc x alist compare) calls (compare x key).
+  Assoc,
+  // (string-index s pred) calls (pred char).
+  StringIndex,
+}
+
+// The references the collector follows through frames,
+// generators, promises and continuations.
+impl Frame {
+  fn visit(&self, f: &mut dyn FnMut(gc::Node)) {
+    let env =
+      |env: &Rc<RefCell<Env>>| gc::Node::Env(env.clone());
+    match self {
+      Frame::Args { list, vals, env: e }
+      | Frame::Elements { list, vals, env: e }
+      | Frame::Let { list, vals, env: e } => {
+        gc::visit_list(list, f);
+        vals.iter().for_each(|obj| gc::visit_obj(obj, f));
+        f(env(e));
+      }
+      Frame::If { list, env: e }
+      | Frame::Body { list, env: e, .. }
+      | Frame::Toplevel { list, env: e, .. }
+      | Frame::Cond { list, env: e, .. }
+      | Frame::WhileTest { list, env: e }
+      | Frame::WhileBody { list, env: e } => {
+        gc::visit_list(list, f);
+        f(env(e));
+      }
+      Frame::Guard { spec, env: e } => {
+        gc::visit_list(spec, f);
+        f(env(e));
+      }
+      Frame::GuardClause {
+        spec, obj, env: e, ..
+      } => {
+        gc::visit_list(spec, f);
+        gc::visit_obj(obj, f);
+        f(env(e));
+      }
+      Frame::Define { env: e, .. } => f(env(e)),
+      Frame::Handler(obj)
+      | Frame::Raise { obj, .. }
+      | Frame::Discard(obj) => gc::visit_obj(obj, f),
+      Frame::Winding {
+        before,
+        thunk,
+        after,
+      } => {
+        for obj in [before, thunk, after] {
+          gc::visit_obj(obj, f);
+        }
+      }
+      Frame::Wind { before, after, .. } => {
+        gc::visit_obj(before, f);
+        gc::visit_obj(after, f);
+      }
+      Frame::Prompt => {}
+      Frame::Generator(g) => {
+        f(gc::Node::Generator(g.clone()))
+      }
+      Frame::Force(p) => f(gc::Node::Promise(p.clone())),
+      Frame::Walk {
+        func, lists, acc, ..
+      } => {
+        gc::visit_obj(func, f);
+        for obj in lists.iter().flatten().chain(acc) {
+          gc::visit_obj(obj, f);
+        }
+      }
+      Frame::Find { pred, x, items, .. } => {
+        for obj in [pred, x].into_iter().chain(items) {
+          gc::visit_obj(obj, f);
+        }
+      }
+      Frame::Sort { less, sort } => {
+        gc::visit_obj(less, f);
+        sort
+          .objects()
+          .for_each(|obj| gc::visit_obj(obj, f));
+      }
+    }
+  }
+}
+
+impl Continuation {
+  pub fn visit(&self, f: &mut dyn FnMut(gc::Node)) {
+    self.frames.iter().for_each(|frame| frame.visit(f));
+  }
+}
+
+impl Generator {
+  // Returns false when the generator is running.
+  pub fn visit(&self, f: &mut dyn FnMut(gc::Node)) -> bool {
+    let state = match self.state.try_borrow() {
+      Ok(state) => state,
+      Err(_) => return false,
+    };
+    match &*state {
+      GeneratorState::Start(func) => gc::visit_obj(func, f),
+      GeneratorState::Suspended(frames) => {
+        frames.iter().for_each(|frame| frame.visit(f))
+      }
+      GeneratorState::Running => return false,
+      GeneratorState::Done(obj) => gc::visit_obj(obj, f),
+    }
+    true
+  }
+
+  pub fn clear(&self) {
+    drop(
+      self
+        .state
+        .replace(GeneratorState::Done(Object::Void)),
+    );
+  }
+}
+
+impl Promise {
+  // Returns false when the promise is being updated.
+  pub fn visit(&self, f: &mut dyn FnMut(gc::Node)) -> bool {
+    let state = match self.state.try_borrow() {
+      Ok(state) => state,
+      Err(_) => return false,
+    };
+    let state = match &*state {
+      PromiseState::Running(prev) => prev,
+      state => state,
+    };
+    match state {
+      PromiseState::Delayed(obj, env)
+      | PromiseState::DelayForce(obj, env) => {
+        gc::visit_obj(obj, f);
+        f(gc::Node::Env(env.clone()));
+      }
+      PromiseState::Done(obj) => gc::visit_obj(obj, f),
+      PromiseState::Running(_) => unreachable!(),
+    }
+    true
+  }
+
+  pub fn clear(&self) {
+    drop(
+      self.state.replace(PromiseState::Done(Object::Void)),
+    );
+  }
+}
+
+enum Step {
+  Eval(Object, Rc<RefCell<Env>>),
+  Apply(Object, Vec<Object>),
+  Force(Rc<Promise>),
+  Return(Object),
+}
+
+thread_local! {
+  static NEXT_ID: Cell<usize> = const { Cell::new(0) };
+  // Machines that are running, innermost last.
+  static MACHINES: RefCell<Vec<usize>> =
+    const { RefCell::new(Vec::new()) };
+  // The builtin procedures by name. They are looked up after
+  // the global environment, so that programs can define the
+  // same names.
+  static BUILTINS: HashMap<Symbol, Object> = lexer::BUILTINS
+    .iter()
+    .map(|name| {
+      (Symbol::new(name), Object::Keyword(name.to_string()))
+    })
+    .collect();
+}
+
+const OPERATORS: [&str; 11] =
+  ["+", "-", "*", "/", "%", "<", ">", "=", "!=", "&", "|"];
+
+fn builtin(s: Symbol) -> Option<Object> {
+  BUILTINS.with(|builtins| {
+    builtins
+      .get(&s)
+      .or_else(|| builtins.get(&s.original()))
+      .cloned()
+  })
+}
+
+// A builtin in argument position becomes a procedure that
+// can be passed around and applied like a lambda.
+fn builtin_value(
+  op: Object,
+  env: &Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  let callable = match &op {
+    Object::Keyword(k) => builtin(Symbol::new(k)).is_some(),
+    Object::BinaryOp(s) => OPERATORS.contains(&s.as_str()),
+    _ => false,
+  };
+  if !callable {
+    return Err(format!("Invalid object: {}", op).into());
+  }
+  Ok(Object::Builtin(Rc::new(op), env.clone()))
+}
+
+fn next_id() -> usize {
+  NEXT_ID.with(|id| {
+    id.set(id.get() + 1);
+    id.get()
+  })
+}
+
+pub fn charge_list(
+  len: usize,
+  env: &Rc<RefCell<Env>>,
+) -> Result<(), String> {
+  env
+    .borrow()
+    .charge(len.saturating_mul(size_of::<Object>()))
+}
+
+// An empty vec with room for len items, or an error when the
+// memory can't be had, so that asking for a huge list fails
+// the program instead of aborting the process.
+pub fn alloc_vec<T>(len: usize) -> Result<Vec<T>, String> {
+  let mut items = Vec::new();
+  items.try_reserve_exact(len).map_err(|_| {
+    format!("Cannot allocate {} items", len)
+  })?;
+  Ok(items)
+}
+
+pub fn extend_env(
+  env: &Rc<RefCell<Env>>,
+) -> Result<Rc<RefCell<Env>>, String> {
+  extend_env_with(env, Rc::default(), Vec::new())
+}
+
+fn extend_env_with(
+  env: &Rc<RefCell<Env>>,
+  names: Rc<Vec<Symbol>>,
+  slots: Vec<Object>,
+) -> Result<Rc<RefCell<Env>>, String> {
+  env.borrow().charge(size_of::<Env>())?;
+  let new_env = Rc::new(RefCell::new(Env::with_slots(
+    env.clone(),
+    names,
+    slots,
+  )));
+  gc::track(&new_env);
+  Ok(new_env)
+}
+
+// Run func on a machine of its own, which a yield can't
+// suspend. Only the before and after thunks of dynamic-wind
+// are called this way, as frames are unwound or reinstated.
+pub fn apply_procedure(
+  func: &Object,
+  args: Vec<Object>,
+) -> Result<Object, EvalError> {
+  Machine::start(Step::Apply(func.clone(), args))
+}
+
 fn print_list(
   list: &[Object],
-  env: &mut Rc<RefCell<Env>>,
-) -> Result<Object, String> {
-  let mut new_list = Vec::new();
-
+) -> Result<Object, EvalError> {
   for obj in list[1..].iter() {
-    new_list.push(eval_obj(obj, env)?);
-  }
-  for obj in new_list.iter() {
     print!("{} ", obj);
   }
   println!();
@@ -24,58 +523,91 @@ fn print_list(
 fn eval_cons(
   list: &[Object],
   env: &mut Rc<RefCell<Env>>,
-) -> Result<Object, String> {
+) -> Result<Object, EvalError> {
   if list.len() != 3 {
     return Err(
-      "Invalid number of arguments for cons".to_string(),
+      "Invalid number of arguments for cons"
+        .to_string()
+        .into(),
     );
   }
 
-  let head = eval_obj(&list[1], env)?;
-  let tail = eval_obj(&list[2], env)?;
+  let head = list[1].clone();
+  let tail = list[2].clone();
 
   match tail {
-    Object::ListData(mut l) => {
+    Object::ListData(l) => {
+      charge_list(l.len() + 1, env)?;
+      let mut l = Rc::unwrap_or_clone(l);
       l.insert(0, head);
-      Ok(Object::ListData(l))
+      Ok(Object::ListData(Rc::new(l)))
     }
-    _ => Err(format!("{} is not a list", tail)),
+    _ => Err(format!("{} is not a list", tail).into()),
   }
 }
 
-fn eval_car(
-  list: &[Object],
-  env: &mut Rc<RefCell<Env>>,
-) -> Result<Object, String> {
-  let l = eval_obj(&list[1], env)?;
+fn eval_car(list: &[Object]) -> Result<Object, EvalError> {
+  if list.len() != 2 {
+    return Err(
+      "Invalid number of arguments for car"
+        .to_string()
+        .into(),
+    );
+  }
+
+  let l = list[1].clone();
   match l {
-    Object::ListData(list) => Ok(list[0].clone()),
-    _ => Err(format!("{} is not a list", l)),
+    Object::ListData(list) => match list.first() {
+      Some(obj) => Ok(obj.clone()),
+      None => {
+        Err("car of an empty list".to_string().into())
+      }
+    },
+    _ => Err(format!("{} is not a list", l).into()),
   }
 }
 
 fn eval_cdr(
   list: &[Object],
   env: &mut Rc<RefCell<Env>>,
-) -> Result<Object, String> {
-  let l = eval_obj(&list[1], env)?;
+) -> Result<Object, EvalError> {
+  if list.len() != 2 {
+    return Err(
+      "Invalid number of arguments for cdr"
+        .to_string()
+        .into(),
+    );
+  }
+
+  let l = list[1].clone();
   let mut new_list = vec![];
   match l {
+    Object::ListData(list) if list.is_empty() => {
+      Err("cdr of an empty list".to_string().into())
+    }
     Object::ListData(list) => {
+      charge_list(list.len() - 1, env)?;
       for obj in list[1..].iter() {
         new_list.push(obj.clone());
       }
-      Ok(Object::ListData(new_list))
+      Ok(Object::ListData(Rc::new(new_list)))
     }
-    _ => Err(format!("{} is not a list", l)),
+    _ => Err(format!("{} is not a list", l).into()),
   }
 }
 
 fn eval_length(
   list: &[Object],
-  env: &mut Rc<RefCell<Env>>,
-) -> Result<Object, String> {
-  let obj = eval_obj(&list[1], env)?;
+) -> Result<Object, EvalError> {
+  if list.len() != 2 {
+    return Err(
+      "Invalid number of arguments for length"
+        .to_string()
+        .into(),
+    );
+  }
+
+  let obj = list[1].clone();
   match obj {
     Object::List(list) => {
       Ok(Object::Integer(list.len() as i64))
@@ -83,1252 +615,3793 @@ fn eval_length(
     Object::ListData(list) => {
       Ok(Object::Integer(list.len() as i64))
     }
-    _ => Err(format!("{} is not a list", obj)),
+    _ => Err(format!("{} is not a list", obj).into()),
   }
 }
 
 fn eval_is_null(
   list: &[Object],
-  env: &mut Rc<RefCell<Env>>,
-) -> Result<Object, String> {
-  let obj = eval_obj(&list[1], env)?;
+) -> Result<Object, EvalError> {
+  if list.len() != 2 {
+    return Err(
+      "Invalid number of arguments for null?"
+        .to_string()
+        .into(),
+    );
+  }
+
+  let obj = list[1].clone();
   match obj {
     Object::List(list) => Ok(Object::Bool(list.is_empty())),
     Object::ListData(list) => {
       Ok(Object::Bool(list.is_empty()))
     }
-    _ => Err(format!("{} is not a list", obj)),
+    _ => Err(format!("{} is not a list", obj).into()),
   }
 }
 
 fn eval_binary_op(
   list: &[Object],
   env: &mut Rc<RefCell<Env>>,
-) -> Result<Object, String> {
+) -> Result<Object, EvalError> {
   if list.len() != 3 {
     return Err(
       "Invalid number of arguments for infix operator"
-        .to_string(),
+        .to_string()
+        .into(),
     );
   }
-  let operator = list[0].clone();
-  let left = &eval_obj(&list[1].clone(), env)?;
-  let right = &eval_obj(&list[2].clone(), env)?;
-  match operator {
-    Object::BinaryOp(s) => match s.as_str() {
-      "+" => match (left, right) {
-        (Object::Integer(l), Object::Integer(r)) => {
-          Ok(Object::Integer(l + r))
-        }
-        (Object::Float(l), Object::Float(r)) => {
-          Ok(Object::Float(l + r))
-        }
-        (Object::Integer(l), Object::Float(r)) => {
-          Ok(Object::Float(*l as f64 + r))
-        }
-        (Object::Float(l), Object::Integer(r)) => {
-          Ok(Object::Float(l + *r as f64))
-        }
-        (Object::String(l), Object::String(r)) => {
-          Ok(Object::String(l.to_owned() + r))
-        }
-        _ => Err(format!(
-          "Invalid types for + operator {} {}",
-          left, right
-        )),
-      },
-      "-" => match (left, right) {
-        (Object::Integer(l), Object::Integer(r)) => {
-          Ok(Object::Integer(l - r))
-        }
-        (Object::Float(l), Object::Float(r)) => {
-          Ok(Object::Float(l - r))
-        }
-        (Object::Integer(l), Object::Float(r)) => {
-          Ok(Object::Float(*l as f64 - r))
-        }
-        (Object::Float(l), Object::Integer(r)) => {
-          Ok(Object::Float(l - *r as f64))
-        }
-        _ => Err(format!(
-          "Invalid types for - operator {} {}",
-          left, right
-        )),
-      },
-      "*" => match (left, right) {
-        (Object::Integer(l), Object::Integer(r)) => {
-          Ok(Object::Integer(l * r))
-        }
-        (Object::Float(l), Object::Float(r)) => {
-          Ok(Object::Float(l * r))
-        }
-        (Object::Integer(l), Object::Float(r)) => {
-          Ok(Object::Float(*l as f64 * r))
-        }
-        (Object::Float(l), Object::Integer(r)) => {
-          Ok(Object::Float(l * (*r) as f64))
-        }
-        _ => Err(format!(
-          "Invalid types for * operator {} {}",
-          left, right
-        )),
-      },
-      "/" => match (left, right) {
-        (Object::Integer(l), Object::Integer(r)) => {
-          Ok(Object::Integer(l / r))
-        }
-        (Object::Float(l), Object::Float(r)) => {
-          Ok(Object::Float(l / r))
-        }
-        (Object::Integer(l), Object::Float(r)) => {
-          Ok(Object::Float(*l as f64 / r))
-        }
-        (Object::Float(l), Object::Integer(r)) => {
-          Ok(Object::Float(l / (*r) as f64))
-        }
-        _ => Err(format!(
-          "Invalid types for / operator {} {}",
-          left, right
-        )),
-      },
-      "%" => match (left, right) {
-        (Object::Integer(l), Object::Integer(r)) => {
-          Ok(Object::Integer(l % r))
-        }
-        (Object::Float(l), Object::Float(r)) => {
-          Ok(Object::Float(l % r))
-        }
-        (Object::Integer(l), Object::Float(r)) => {
-          Ok(Object::Float(*l as f64 % r))
-        }
-        (Object::Float(l), Object::Integer(r)) => {
-          Ok(Object::Float(l % (*r) as f64))
-        }
-        _ => Err(format!(
-          "Invalid types for % operator {} {}",
-          left, right
-        )),
-      },
-      "<" => match (left, right) {
-        (Object::Integer(l), Object::Integer(r)) => {
-          Ok(Object::Bool(l < r))
-        }
-        (Object::Float(l), Object::Float(r)) => {
-          Ok(Object::Bool(l < r))
-        }
-        (Object::Integer(l), Object::Float(r)) => {
-          Ok(Object::Bool((*l as f64) < *r))
-        }
-        (Object::Float(l), Object::Integer(r)) => {
-          Ok(Object::Bool(l < &(*r as f64)))
-        }
-        (Object::String(l), Object::String(r)) => {
-          Ok(Object::Bool(l.cmp(r) == Ordering::Less))
-        }
-        _ => Err(format!(
-          "Invalid types for < operator {} {}",
-          left, right
-        )),
-      },
-      ">" => match (left, right) {
-        (Object::Integer(l), Object::Integer(r)) => {
-          Ok(Object::Bool(l > r))
-        }
-        (Object::Float(l), Object::Float(r)) => {
-          Ok(Object::Bool(l > r))
-        }
-        (Object::Integer(l), Object::Float(r)) => {
-          Ok(Object::Bool(*l as f64 > *r))
-        }
-        (Object::Float(l), Object::Integer(r)) => {
-          Ok(Object::Bool(l > &(*r as f64)))
-        }
-        (Object::String(l), Object::String(r)) => {
-          Ok(Object::Bool(l.cmp(r) == Ordering::Greater))
-        }
-        _ => Err(format!(
-          "Invalid types for > operator {} {}",
-          left, right
-        )),
-      },
-      "=" => match (left, right) {
-        (Object::Integer(l), Object::Integer(r)) => {
-          Ok(Object::Bool(l == r))
-        }
-        (Object::String(l), Object::String(r)) => {
-          Ok(Object::Bool(l == r))
-        }
-        _ => Err(format!(
-          "Invalid types for = operator {} {}",
-          left, right
-        )),
-      },
-      "!=" => match (left, right) {
-        (Object::Integer(l), Object::Integer(r)) => {
-          Ok(Object::Bool(l != r))
-        }
-        (Object::Float(l), Object::Float(r)) => {
-          Ok(Object::Bool(l != r))
-        }
-        (Object::Integer(l), Object::Float(r)) => {
-          Ok(Object::Bool(*l as f64 != *r))
-        }
-        (Object::Float(l), Object::Integer(r)) => {
-          Ok(Object::Bool(*l != (*r) as f64))
-        }
-        (Object::String(l), Object::String(r)) => {
-          Ok(Object::Bool(l.cmp(r) != Ordering::Equal))
-        }
-        _ => Err(format!(
-          "Invalid types for != operator {} {}",
-          left, right
-        )),
-      },
-      "&" => match (left, right) {
-        (Object::Bool(l), Object::Bool(r)) => {
-          Ok(Object::Bool(*l && *r))
-        }
-        _ => Err(format!(
-          "Invalid types for & operator {} {}",
-          left, right
-        )),
-      },
-      "|" => match (left, right) {
-        (Object::Bool(l), Object::Bool(r)) => {
-          Ok(Object::Bool(*l || *r))
-        }
-        _ => Err(format!(
-          "Invalid types for | operator {} {}",
-          left, right
-        )),
-      },
-      _ => Err(format!("Invalid infix operator: {}", s)),
-    },
-    _ => Err("Operator must be a symbol".to_string()),
+  let op = match &list[0] {
+    Object::BinaryOp(s) => s.as_str(),
+    _ => {
+      return Err(
+        "Operator must be a symbol".to_string().into(),
+      )
+    }
+  };
+  let left = &list[1];
+  let right = &list[2];
+  if let Some(result) = eval_number_op(op, left, right, env)
+  {
+    return result;
+  }
+
+  match (op, left, right) {
+    ("+", Object::String(l), Object::String(r)) => {
+      env.borrow().charge(l.len() + r.len())?;
+      Ok(Object::String(format!("{}{}", l, r).into()))
+    }
+    ("<", Object::String(l), Object::String(r)) => {
+      Ok(Object::Bool(l.cmp(r) == Ordering::Less))
+    }
+    (">", Object::String(l), Object::String(r)) => {
+      Ok(Object::Bool(l.cmp(r) == Ordering::Greater))
+    }
+    ("=", Object::String(l), Object::String(r)) => {
+      Ok(Object::Bool(l == r))
+    }
+    ("!=", Object::String(l), Object::String(r)) => {
+      Ok(Object::Bool(l.cmp(r) != Ordering::Equal))
+    }
+    ("&", Object::Bool(l), Object::Bool(r)) => {
+      Ok(Object::Bool(*l && *r))
+    }
+    ("|", Object::Bool(l), Object::Bool(r)) => {
+      Ok(Object::Bool(*l || *r))
+    }
+    (
+      "+" | "-" | "*" | "/" | "%" | "<" | ">" | "=" | "!="
+      | "&" | "|",
+      _,
+      _,
+    ) => Err(
+      format!(
+        "Invalid types for {} operator {} {}",
+        op, left, right
+      )
+      .into(),
+    ),
+    _ => {
+      Err(format!("Invalid infix operator: {}", op).into())
+    }
   }
 }
 
-fn eval_begin(
+fn eval_list_data(
   list: &[Object],
   env: &mut Rc<RefCell<Env>>,
-) -> Result<Object, String> {
-  let mut result = Object::Void;
-  let mut new_env =
-    Rc::new(RefCell::new(Env::extend(env.clone())));
-
-  for obj in list[1..].iter() {
-    result = eval_obj(obj, &mut new_env)?;
-  }
-  Ok(result)
+) -> Result<Object, EvalError> {
+  let new_list = list[1..].to_vec();
+  charge_list(new_list.len(), env)?;
+  Ok(Object::ListData(Rc::new(new_list)))
 }
 
-fn eval_let(
+fn eval_function_definition(
   list: &[Object],
   env: &mut Rc<RefCell<Env>>,
-) -> Result<Object, String> {
-  let mut result = Object::Void;
-  let bindings_env = Rc::new(RefCell::new(Env::new()));
-
+) -> Result<Object, EvalError> {
   if list.len() < 3 {
     return Err(
-      "Invalid number of arguments for let".to_string(),
+      "Invalid number of arguments for lambda"
+        .to_string()
+        .into(),
     );
   }
 
-  let bindings = match list[1].clone() {
-    Object::List(bindings) => bindings,
-    _ => {
-      return Err("Invalid bindings for let".to_string())
-    }
-  };
-
-  for binding in bindings.iter() {
-    let binding = match binding {
-      Object::List(binding) => binding,
-      _ => {
-        return Err("Invalid binding for let".to_string())
+  let params = match &list[1] {
+    Object::List(list) => {
+      let mut params = Vec::new();
+      for param in (*list).iter() {
+        match param {
+          Object::Symbol(s) => params.push(*s),
+          _ => {
+            return Err(
+              format!(
+                "Invalid lambda parameter {:?}",
+                param
+              )
+              .into(),
+            )
+          }
+        }
       }
-    };
-
-    if binding.len() != 2 {
-      return Err("Invalid binding for let".to_string());
+      params
     }
+    _ => return Err("Invalid lambda".to_string().into()),
+  };
 
-    let name = match binding[0].clone() {
-      Object::Symbol(name) => name,
-      _ => {
-        return Err("Invalid binding for let".to_string())
-      }
-    };
-
-    let value = eval_obj(&binding[1], env)?;
-    bindings_env.borrow_mut().set(name.as_str(), value);
-  }
-
-  println!("let arguments {:?}", bindings_env);
-  let mut new_env =
-    Rc::new(RefCell::new(Env::extend(env.clone())));
-  new_env.borrow_mut().update(bindings_env);
-
-  for obj in list[2..].iter() {
-    result = eval_obj(obj, &mut new_env)?;
-  }
-  Ok(result)
+  let body = match &list[2] {
+    Object::List(list) => list.clone(),
+    _ => return Err("Invalid lambda".to_string().into()),
+  };
+  Ok(Object::Lambda(
+    Rc::new(params),
+    Rc::new(body.to_vec()),
+    env.clone(),
+  ))
 }
 
-fn eval_define(
+fn eval_quote(
   list: &[Object],
-  env: &mut Rc<RefCell<Env>>,
-) -> Result<Object, String> {
-  if list.len() != 3 {
+) -> Result<Object, EvalError> {
+  if list.len() != 2 {
     return Err(
-      "Invalid number of arguments for define".to_string(),
+      "Invalid number of arguments for quote"
+        .to_string()
+        .into(),
     );
   }
-
-  let sym = match &list[1] {
-    Object::Symbol(s) => s.clone(),
-    Object::List(l) => {
-      let name = match &l[0] {
-        Object::Symbol(s) => s.clone(),
-        _ => return Err(format!("Invalid define {:?}", l)),
-      };
-      let params = Object::List(Rc::new(l[1..].to_vec()));
-      let body = list[2].clone();
-      let lambda = eval_function_definition(
-        &[Object::Void, params, body],
-        env,
-      )?;
-      env.borrow_mut().set(&name, lambda);
-      return Ok(Object::Void);
-    }
-    _ => return Err("Invalid define".to_string()),
-  };
-  let val = eval_obj(&list[2], env)?;
-  env.borrow_mut().set(&sym, val);
-  Ok(Object::Void)
+  Ok(to_data(&list[1]))
 }
 
-fn eval_list_data(
-  list: &[Object],
+fn eval_quasi(
+  obj: &Object,
+  depth: usize,
   env: &mut Rc<RefCell<Env>>,
-) -> Result<Object, String> {
-  let mut new_list = Vec::new();
+) -> Result<Object, EvalError> {
+  let list = match obj {
+    Object::List(list) => list,
+    _ => return Ok(to_data(obj)),
+  };
 
-  for obj in list[1..].iter() {
-    new_list.push(eval_obj(obj, env)?);
-  }
-  Ok(Object::ListData(new_list))
+  if list.len() == 2 {
+    match &list[0] {
+      Object::Keyword(k)
+        if k == "unquote" && depth == 1 =>
+      {
+        return eval_obj(&list[1], env);
+      }
+      Object::Keyword(k) if k == "unquote" => {
+        return Ok(Object::ListData(Rc::new(vec![
+          list[0].clone(),
+          eval_quasi(&list[1], depth - 1, env)?,
+        ])));
+      }
+      Object::Keyword(k) if k == "quasiquote" => {
+        return Ok(Object::ListData(Rc::new(vec![
+          list[0].clone(),
+          eval_quasi(&list[1], depth + 1, env)?,
+        ])));
+      }
+      _ => {}
+    }
+  }
+
+  let mut new_list = Vec::new();
+  for obj in list.iter() {
+    match obj {
+      Object::List(l)
+        if depth == 1
+          && l.len() == 2
+          && l[0]
+            == Object::Keyword(
+              "unquote-splicing".to_string(),
+            ) =>
+      {
+        match eval_obj(&l[1], env)? {
+          Object::ListData(spliced) => {
+            new_list.extend(spliced.iter().cloned())
+          }
+          other => {
+            return Err(
+              format!("{} is not a list", other).into(),
+            )
+          }
+        }
+      }
+      _ => new_list.push(eval_quasi(obj, depth, env)?),
+    }
+  }
+  charge_list(new_list.len(), env)?;
+  Ok(Object::ListData(Rc::new(new_list)))
+}
+
+fn eval_quasiquote(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  if list.len() != 2 {
+    return Err(
+      "Invalid number of arguments for quasiquote"
+        .to_string()
+        .into(),
+    );
+  }
+  eval_quasi(&list[1], 1, env)
+}
+
+fn eval_gensym(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  let prefix = match list.get(1) {
+    None => "g".to_string(),
+    Some(Object::String(s)) => s.to_string(),
+    Some(Object::Symbol(s)) => s.to_string(),
+    Some(obj) => {
+      return Err(
+        format!("Invalid gensym prefix {}", obj).into(),
+      )
+    }
+  };
+  Ok(Object::Symbol(gensym(&prefix)))
 }
 
-fn eval_cond(
+fn eval_macroexpand(
   list: &[Object],
   env: &mut Rc<RefCell<Env>>,
-) -> Result<Object, String> {
+  once: bool,
+) -> Result<Object, EvalError> {
+  if list.len() != 2 {
+    return Err(
+      "Invalid number of arguments for macroexpand"
+        .to_string()
+        .into(),
+    );
+  }
+  let form = to_code(&list[1]);
+  let expanded = if once {
+    macroexpand_1(&form, env)?.0
+  } else {
+    macroexpand(&form, env)?
+  };
+  Ok(to_data(&expanded))
+}
+
+fn eval_error(
+  list: &[Object],
+) -> Result<Object, EvalError> {
   if list.len() < 2 {
     return Err(
-      "Invalid number of arguments for cond".to_string(),
+      "Invalid number of arguments for error"
+        .to_string()
+        .into(),
     );
   }
+  let message = match &list[1] {
+    Object::String(s) => s.to_string(),
+    obj => obj.to_string(),
+  };
+  let irritants = list[2..].to_vec();
+  Err(EvalError::Raise(Object::Error(message, irritants)))
+}
 
-  println!("cond {:?}", list);
+fn eval_raise(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  if list.len() != 2 {
+    return Err(
+      "Invalid number of arguments for raise"
+        .to_string()
+        .into(),
+    );
+  }
+  Err(EvalError::Raise(list[1].clone()))
+}
 
-  for l in list[1..].iter() {
-    match l {
-      Object::List(list) => {
-        if list.len() != 2 {
-          return Err(format!(
-            "Invalid cond clause {:?}",
-            list
-          ));
-        }
+fn eval_is_error_object(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  if list.len() != 2 {
+    return Err(
+      "Invalid number of arguments for error-object?"
+        .to_string()
+        .into(),
+    );
+  }
+  Ok(Object::Bool(matches!(list[1], Object::Error(_, _))))
+}
 
-        if list[0] == Object::Keyword("else".to_string()) {
-          return eval_else(list, env);
-        }
+fn eval_error_object_message(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  if list.len() != 2 {
+    return Err(
+      "Invalid number of arguments for error-object-message"
+        .to_string()
+        .into(),
+    );
+  }
+  match &list[1] {
+    Object::Error(message, _) => {
+      Ok(Object::String(message.clone().into()))
+    }
+    obj => {
+      Err(format!("{} is not an error object", obj).into())
+    }
+  }
+}
 
-        let cond = eval_obj(&list[0], env)?;
-        let cond_val = match cond {
-          Object::Bool(b) => b,
-          _ => {
-            return Err(format!(
-              "Condition must be a boolean {:?}",
-              cond
-            ))
-          }
-        };
-        if cond_val {
-          return eval_obj(&list[1], env);
-        }
-      }
-      _ => return Err("Invalid cond clause".to_string()),
+fn eval_error_object_irritants(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  if list.len() != 2 {
+    return Err(
+      "Invalid number of arguments for error-object-irritants"
+        .to_string()
+        .into(),
+    );
+  }
+  match &list[1] {
+    Object::Error(_, irritants) => {
+      Ok(Object::ListData(Rc::new(irritants.clone())))
+    }
+    obj => {
+      Err(format!("{} is not an error object", obj).into())
     }
   }
+}
 
-  Err("No cond clause matched".to_string())
+fn eval_make_generator(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  if list.len() != 2 {
+    return Err(
+      "Invalid number of arguments for make-generator"
+        .to_string()
+        .into(),
+    );
+  }
+  if !matches!(list[1], Object::Lambda(_, _, _)) {
+    return Err(
+      format!("Generator must be a lambda {}", list[1])
+        .into(),
+    );
+  }
+  Ok(Object::Generator(Rc::new(Generator {
+    state: RefCell::new(GeneratorState::Start(
+      list[1].clone(),
+    )),
+  })))
 }
 
-fn eval_else(
+fn eval_is_generator_done(
   list: &[Object],
-  env: &mut Rc<RefCell<Env>>,
-) -> Result<Object, String> {
+) -> Result<Object, EvalError> {
   if list.len() != 2 {
-    return Err(format!(
-      "Invalid number of arguments for else {:?}",
-      list
-    ));
+    return Err(
+      "Invalid number of arguments for generator-done?"
+        .to_string()
+        .into(),
+    );
+  }
+  match &list[1] {
+    Object::Generator(g) => Ok(Object::Bool(matches!(
+      *g.state.borrow(),
+      GeneratorState::Done(_)
+    ))),
+    obj => {
+      Err(format!("{} is not a generator", obj).into())
+    }
   }
+}
 
-  eval_obj(&list[1], env)
+fn recursive_force() -> EvalError {
+  "Promise forced recursively".to_string().into()
 }
 
-fn eval_function_definition(
+fn force_object(obj: &Object) -> Result<Object, EvalError> {
+  match obj {
+    Object::Promise(p) => {
+      Machine::start(Step::Force(p.clone()))
+    }
+    _ => Ok(obj.clone()),
+  }
+}
+
+fn eval_delay(
+  list: &[Object],
+  env: &Rc<RefCell<Env>>,
+  force: bool,
+) -> Result<Object, EvalError> {
+  if list.len() != 2 {
+    return Err(
+      format!(
+        "Invalid number of arguments for {}",
+        list[0]
+      )
+      .into(),
+    );
+  }
+  let state = if force {
+    PromiseState::DelayForce(list[1].clone(), env.clone())
+  } else {
+    PromiseState::Delayed(list[1].clone(), env.clone())
+  };
+  Ok(Object::Promise(Rc::new(Promise {
+    state: RefCell::new(state),
+  })))
+}
+
+fn eval_make_promise(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  if list.len() != 2 {
+    return Err(
+      "Invalid number of arguments for make-promise"
+        .to_string()
+        .into(),
+    );
+  }
+  match &list[1] {
+    Object::Promise(_) => Ok(list[1].clone()),
+    obj => Ok(Object::Promise(Rc::new(Promise {
+      state: RefCell::new(PromiseState::Done(obj.clone())),
+    }))),
+  }
+}
+
+fn eval_is_promise(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  if list.len() != 2 {
+    return Err(
+      "Invalid number of arguments for promise?"
+        .to_string()
+        .into(),
+    );
+  }
+  Ok(Object::Bool(matches!(list[1], Object::Promise(_))))
+}
+
+// A stream is either empty list data, or a pair of its first
+// element and a promise of the rest.
+fn stream_pair(
+  obj: &Object,
+) -> Result<&[Object], EvalError> {
+  match obj {
+    Object::ListData(pair)
+      if pair.len() == 2
+        && matches!(pair[1], Object::Promise(_)) =>
+    {
+      Ok(pair)
+    }
+    _ => {
+      Err(format!("{} is not a stream pair", obj).into())
+    }
+  }
+}
+
+fn eval_stream_car(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  if list.len() != 2 {
+    return Err(
+      "Invalid number of arguments for stream-car"
+        .to_string()
+        .into(),
+    );
+  }
+  Ok(stream_pair(&list[1])?[0].clone())
+}
+
+fn eval_is_stream_null(
+  list: &[Object],
+  pair: bool,
+) -> Result<Object, EvalError> {
+  if list.len() != 2 {
+    return Err(
+      format!(
+        "Invalid number of arguments for {}",
+        list[0]
+      )
+      .into(),
+    );
+  }
+  if pair {
+    return Ok(Object::Bool(stream_pair(&list[1]).is_ok()));
+  }
+  Ok(Object::Bool(
+    list[1] == Object::ListData(Rc::new(vec![])),
+  ))
+}
+
+fn eval_stream_take(
   list: &[Object],
   env: &mut Rc<RefCell<Env>>,
-) -> Result<Object, String> {
-  let params = match &list[1] {
-    Object::List(list) => {
-      let mut params = Vec::new();
-      for param in (*list).iter() {
-        match param {
-          Object::Symbol(s) => params.push(s.clone()),
-          _ => {
-            return Err(format!(
-              "Invalid lambda parameter {:?}",
-              param
-            ))
-          }
-        }
-      }
-      params
+) -> Result<Object, EvalError> {
+  if list.len() != 3 {
+    return Err(
+      "Invalid number of arguments for stream-take"
+        .to_string()
+        .into(),
+    );
+  }
+  let n = match list[2] {
+    Object::Integer(n) => n,
+    _ => {
+      return Err(
+        format!("Invalid stream-take count {}", list[2])
+          .into(),
+      )
     }
-    _ => return Err("Invalid lambda".to_string()),
   };
 
-  let body = match &list[2] {
-    Object::List(list) => list.clone(),
-    _ => return Err("Invalid lambda".to_string()),
+  let mut items = Vec::new();
+  let mut stream = list[1].clone();
+  while (items.len() as i64) < n {
+    if stream == Object::ListData(Rc::new(vec![])) {
+      break;
+    }
+    let pair = stream_pair(&stream)?;
+    items.push(pair[0].clone());
+    stream = force_object(&pair[1])?;
+  }
+  charge_list(items.len(), env)?;
+  Ok(Object::ListData(Rc::new(items)))
+}
+
+// The integers from start, step apart. The rest of the
+// stream is a promise of the same call one step further.
+fn eval_stream_range(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  let (start, step) = match list {
+    [_, Object::Integer(start)] => (*start, 1),
+    [_, Object::Integer(start), Object::Integer(step)] => {
+      (*start, *step)
+    }
+    _ => {
+      return Err(
+        "Invalid arguments for stream-range"
+          .to_string()
+          .into(),
+      )
+    }
   };
-  Ok(Object::Lambda(
-    params,
-    Rc::new(body.to_vec()),
-    env.clone(),
-  ))
+  let next = start.checked_add(step).ok_or_else(|| {
+    "stream-range went past the largest integer".to_string()
+  })?;
+  let rest = Object::List(Rc::new(vec![
+    list[0].clone(),
+    Object::Integer(next),
+    Object::Integer(step),
+  ]));
+  charge_list(2, env)?;
+  Ok(Object::ListData(Rc::new(vec![
+    Object::Integer(start),
+    eval_delay(&[Object::Void, rest], env, false)?,
+  ])))
 }
 
-fn eval_symbol(
-  s: &str,
+fn eval_range(
+  list: &[Object],
   env: &mut Rc<RefCell<Env>>,
-) -> Result<Object, String> {
+) -> Result<Object, EvalError> {
+  let (start, end, step) = match list {
+    [_, Object::Integer(start), Object::Integer(end)] => {
+      (*start, *end, 1)
+    }
+    [_, Object::Integer(start), Object::Integer(end), Object::Integer(step)] => {
+      (*start, *end, *step)
+    }
+    _ => {
+      return Err(
+        "Invalid arguments for range".to_string().into(),
+      )
+    }
+  };
+  if step == 0 {
+    return Err(
+      "range step must not be 0".to_string().into(),
+    );
+  }
+
+  // Charge for the list before building it, and count in
+  // i128 so that ranges near the ends of i64 do not overflow.
+  let (start, end, step) =
+    (start as i128, end as i128, step as i128);
+  let count = if step > 0 {
+    (end - start + step - 1) / step
+  } else {
+    (start - end - step - 1) / -step
+  }
+  .max(0);
+  let len = usize::try_from(count).unwrap_or(usize::MAX);
+  charge_list(len, env)?;
+  let mut items = alloc_vec(len)?;
+  items.extend(
+    (0..count)
+      .map(|i| Object::Integer((start + i * step) as i64)),
+  );
+  Ok(Object::ListData(Rc::new(items)))
+}
+
+// (do ((var init step) ...) (test expr ...) body ...) runs
+// as the loop
+// (begin
+//   (define (loop var ...)
+//     (if test
+//       (begin expr ...)
+//       (begin body ... (loop step ...))))
+//   (loop init ...))
+pub fn expand_do(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  if list.len() < 3 {
+    return Err(
+      "Invalid number of arguments for do"
+        .to_string()
+        .into(),
+    );
+  }
+  let specs = match &list[1] {
+    Object::List(specs) => specs,
+    _ => {
+      return Err(
+        format!("Invalid do bindings {}", list[1]).into(),
+      )
+    }
+  };
+  let exit = match &list[2] {
+    Object::List(exit) if !exit.is_empty() => exit,
+    _ => {
+      return Err(
+        format!("Invalid do test {}", list[2]).into(),
+      )
+    }
+  };
+
+  let name = Object::Symbol(gensym("do"));
+  let mut vars = vec![name.clone()];
+  let mut inits = vec![name.clone()];
+  let mut steps = vec![name.clone()];
+  for spec in specs.iter() {
+    match spec {
+      Object::List(spec)
+        if (spec.len() == 2 || spec.len() == 3)
+          && matches!(spec[0], Object::Symbol(_)) =>
+      {
+        vars.push(spec[0].clone());
+        inits.push(spec[1].clone());
+        steps.push(spec.get(2).unwrap_or(&spec[0]).clone());
+      }
+      _ => {
+        return Err(
+          format!("Invalid do binding {}", spec).into(),
+        )
+      }
+    }
+  }
+
+  let begin = Object::Keyword("begin".to_string());
+  let mut done = vec![begin.clone()];
+  done.extend(exit[1..].iter().cloned());
+  let mut body = vec![begin.clone()];
+  body.extend(list[3..].iter().cloned());
+  body.push(Object::List(Rc::new(steps)));
+  let test = Object::List(Rc::new(vec![
+    Object::Keyword("if".to_string()),
+    exit[0].clone(),
+    Object::List(Rc::new(done)),
+    Object::List(Rc::new(body)),
+  ]));
+  Ok(Object::List(Rc::new(vec![
+    begin,
+    Object::List(Rc::new(vec![
+      Object::Keyword("define".to_string()),
+      Object::List(Rc::new(vars)),
+      test,
+    ])),
+    Object::List(Rc::new(inits)),
+  ])))
+}
+
+fn eval_symbol(
+  s: Symbol,
+  env: &Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
   let val = match s {
-    "#t" => return Ok(Object::Bool(true)),
-    "#f" => return Ok(Object::Bool(false)),
-    "#nil" => return Ok(Object::Void),
+    symbols::TRUE => return Ok(Object::Bool(true)),
+    symbols::FALSE => return Ok(Object::Bool(false)),
+    symbols::NIL => return Ok(Object::Void),
     _ => env.borrow_mut().get(s),
   };
 
-  if val.is_none() {
-    return Err(format!("Unbound symbol: {}", s));
+  match (val, builtin(s)) {
+    (Some(val), _) => Ok(val),
+    (None, Some(op)) => builtin_value(op, env),
+    (None, None) => match unaliased(s, env) {
+      Some(val) => Ok(val),
+      None => Err(format!("Unbound symbol: {}", s).into()),
+    },
+  }
+}
+
+// The free identifiers of a macro template refer to the
+// environment the macro is defined in. One that isn't bound
+// there either, such as a parameter of the procedure whose
+// body defines the macro, is looked up where it is used.
+fn unaliased(
+  s: Symbol,
+  env: &Rc<RefCell<Env>>,
+) -> Option<Object> {
+  let original = s.original();
+  if original == s {
+    return None;
   }
+  env.borrow().get(original)
+}
 
-  Ok(val.unwrap().clone())
+fn eval_local(
+  s: Symbol,
+  depth: usize,
+  index: usize,
+  env: &Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  match env.borrow().get_at(depth, index) {
+    Some(val) => Ok(val),
+    None => Err(format!("Unbound symbol: {}", s).into()),
+  }
 }
 
 fn eval_keyword(
   list: &[Object],
   env: &mut Rc<RefCell<Env>>,
-) -> Result<Object, String> {
+) -> Result<Object, EvalError> {
   let head = &list[0];
   match head {
     Object::Keyword(s) => match s.as_str() {
-      "define" => eval_define(list, env),
-      "begin" => eval_begin(list, env),
-      "let" => eval_let(list, env),
       "list" => eval_list_data(list, env),
-      "print" => print_list(list, env),
-      "lambda" => eval_function_definition(list, env),
+      "print" => print_list(list),
       "cons" => eval_cons(list, env),
-      "car" => eval_car(list, env),
+      "car" => eval_car(list),
       "cdr" => eval_cdr(list, env),
-      "length" => eval_length(list, env),
-      "null?" => eval_is_null(list, env),
-      "cond" => eval_cond(list, env),
-      _ => Err(format!("Unknown keyword: {}", s)),
-    },
-    _ => Err(format!("Invalid keyword: {}", head)),
-  }
-}
-
-fn eval_obj(
-  obj: &Object,
-  env: &mut Rc<RefCell<Env>>,
-) -> Result<Object, String> {
-  let mut current_obj = Box::new(obj.clone());
-  let mut current_env = env.clone();
-  loop {
-    match *current_obj {
-      Object::List(list) => {
-        let head = &list[0];
-        match head {
-          Object::BinaryOp(_op) => {
-            return eval_binary_op(&list, &mut current_env);
-          }
-          Object::Keyword(_keyword) => {
-            if _keyword == "if" {
-              if list.len() != 4 {
-                return Err(
-                                    "Invalid number of arguments for if statement".to_string()
-                                );
-              }
-
-              let cond_obj =
-                eval_obj(&list[1], &mut current_env)?;
-              let cond = match cond_obj {
-                Object::Bool(b) => b,
-                _ => {
-                  return Err(
-                    "Condition must be a boolean"
-                      .to_string(),
-                  )
-                }
-              };
-
-              if cond {
-                current_obj = Box::new(list[2].clone());
-              } else {
-                current_obj = Box::new(list[3].clone());
-              }
-              continue;
-            } else {
-              return eval_keyword(&list, &mut current_env);
-            }
-          }
-          Object::Lambda(params, body, func_env) => {
-            let new_env = Rc::new(RefCell::new(
-              Env::extend(func_env.clone()),
-            ));
-            for (i, param) in params.iter().enumerate() {
-              let val =
-                eval_obj(&list[i + 1], &mut current_env)?;
-              new_env.borrow_mut().set(param, val);
-            }
-            current_obj =
-              Box::new(Object::List(body.clone()));
-            current_env = new_env;
-            continue;
-          }
-          Object::Symbol(s) => {
-            let lamdba = current_env.borrow_mut().get(s);
-            if lamdba.is_none() {
-              return Err(format!(
-                "Unbound function: {}",
-                s
-              ));
-            }
-
-            let func = lamdba.unwrap();
-            match func {
-              Object::Lambda(params, body, func_env) => {
-                let new_env = Rc::new(RefCell::new(
-                  Env::extend(func_env.clone()),
-                ));
-                for (i, param) in params.iter().enumerate()
-                {
-                  let val = eval_obj(
-                    &list[i + 1],
-                    &mut current_env,
-                  )?;
-                  new_env.borrow_mut().set(param, val);
-                }
-                current_obj = Box::new(Object::List(body));
-                current_env = new_env.clone();
-                continue;
-              }
-              _ => {
-                return Err(format!(
-                  "Not a lambda: {} {:?}",
-                  s, func
-                ))
-              }
-            }
-          }
-          _ => {
-            let mut new_list = Vec::new();
-            for obj in (*list).iter() {
-              let result = eval_obj(obj, &mut current_env)?;
-              match result {
-                Object::Void => {}
-                _ => new_list.push(result),
-              }
-            }
-            let head = &new_list[0];
-            match head {
-              Object::Lambda(_, _, _) => {
-                return eval_obj(
-                  &Object::List(Rc::new(new_list)),
-                  &mut current_env,
-                );
-              }
-              _ => {
-                return Ok(Object::List(Rc::new(new_list)));
-              }
-            }
-          }
-        }
+      "length" => eval_length(list),
+      "null?" => eval_is_null(list),
+      "gensym" => eval_gensym(list),
+      "error" => eval_error(list),
+      "raise" => eval_raise(list),
+      "error-object?" => eval_is_error_object(list),
+      "error-object-message" => {
+        eval_error_object_message(list)
       }
-      Object::Symbol(s) => {
-        return eval_symbol(&s, &mut current_env);
+      "error-object-irritants" => {
+        eval_error_object_irritants(list)
       }
-      Object::Void => return Ok(Object::Void),
-      Object::Lambda(_params, _body, _func_env) => {
-        return Ok(Object::Void)
+      "make-generator" => eval_make_generator(list),
+      "generator-done?" => eval_is_generator_done(list),
+      "make-promise" => eval_make_promise(list),
+      "promise?" => eval_is_promise(list),
+      "stream-car" => eval_stream_car(list),
+      "stream-null?" => eval_is_stream_null(list, false),
+      "stream-pair?" => eval_is_stream_null(list, true),
+      "stream-take" => eval_stream_take(list, env),
+      "stream-range" => eval_stream_range(list, env),
+      "range" => eval_range(list, env),
+      "char?" | "char->integer" | "integer->char"
+      | "char-upcase" | "char-downcase"
+      | "char-foldcase" | "char-alphabetic?"
+      | "char-numeric?" | "char-whitespace?"
+      | "char-upper-case?" | "char-lower-case?"
+      | "digit-value" => eval_char_procedure(list),
+      "char=?" | "char<?" | "char>?" | "char<=?"
+      | "char>=?" | "char-ci=?" | "char-ci<?"
+      | "char-ci>?" | "char-ci<=?" | "char-ci>=?" => {
+        eval_char_compare(list)
       }
-      Object::Bool(_) => return Ok(obj.clone()),
-      Object::Integer(n) => return Ok(Object::Integer(n)),
-      Object::Float(n) => return Ok(Object::Float(n)),
-      Object::String(s) => {
-        return Ok(Object::String(s.to_string()))
+      "append" => eval_append(list, env),
+      "reverse" => eval_reverse(list, env),
+      "last" => eval_last(list),
+      "list-ref" => eval_list_ref(list),
+      "list-tail" | "drop" => eval_split(list, env, false),
+      "take" => eval_split(list, env, true),
+      "member" => eval_member(list, env, false),
+      "assoc" if is_persistent(list.get(1)) => {
+        eval_assoc(list, env)
       }
-      Object::ListData(l) => {
-        return Ok(Object::ListData(l.to_vec()))
+      "assoc" => eval_member(list, env, true),
+      "iota" => eval_iota(list, env),
+      "make-vector" => eval_make_vector(list, env),
+      "vector" => eval_vector(list, env),
+      "vector?" => eval_is_vector(list),
+      "vector-ref" => eval_vector_ref(list),
+      "vector-set!" => eval_vector_set(list),
+      "vector-length" => eval_vector_length(list),
+      "vector->list" => eval_vector_to_list(list, env),
+      "list->vector" => eval_list_to_vector(list, env),
+      "make-hash-table" => eval_make_hash_table(list),
+      "hash-table?" => eval_is_hash_table(list),
+      "hash-table-set!" => eval_hash_table_set(list, env),
+      "hash-table-ref" => eval_hash_table_ref(list),
+      "hash-table-delete!" => eval_hash_table_delete(list),
+      "hash-table-contains?" => {
+        eval_hash_table_contains(list)
       }
-      _ => {
-        return Err(format!("Invalid object: {:?}", obj))
+      "hash-table-count" => eval_hash_table_count(list),
+      "persistent-map" => eval_persistent_map(list, env),
+      "persistent-vector" => {
+        eval_persistent_vector(list, env)
       }
-    }
-  }
-}
-
-pub fn eval(
-  program: &str,
-  env: &mut Rc<RefCell<Env>>,
-) -> Result<Object, String> {
-  let parsed_list = parse(program);
-  if parsed_list.is_err() {
-    return Err(format!("{}", parsed_list.err().unwrap()));
-  }
-  eval_obj(&parsed_list.unwrap(), env)
+      "persistent-map?" => eval_is_persistent(list, true),
+      "persistent-vector?" => {
+        eval_is_persistent(list, false)
+      }
+      "dissoc" => eval_dissoc(list),
+      "conj" => eval_conj(list, env),
+      "get" => eval_get(list, false),
+      "get-in" => eval_get(list, true),
+      "assoc-in" => eval_assoc_in(list, env),
+      "count" => eval_count(list),
+      "persistent->list" => {
+        eval_persistent_to_list(list, env)
+      }
+      "bytevector" => eval_bytevector(list, env),
+      "make-bytevector" => eval_make_bytevector(list, env),
+      "bytevector?" => eval_is_bytevector(list),
+      "bytevector-length" => eval_bytevector_length(list),
+      "bytevector-u8-ref" => eval_bytevector_u8_ref(list),
+      "bytevector-u8-set!" => eval_bytevector_u8_set(list),
+      "bytevector-copy" => eval_bytevector_copy(list, env),
+      "bytevector-append" => {
+        eval_bytevector_append(list, env)
+      }
+      "utf8->string" => eval_utf8_to_string(list, env),
+      "string->utf8" => eval_string_to_utf8(list, env),
+      "bytevector-uint-ref" | "bytevector-sint-ref" => {
+        eval_bytevector_int_ref(list)
+      }
+      "bytevector-uint-set!" | "bytevector-sint-set!" => {
+        eval_bytevector_int_set(list)
+      }
+      "hash-table-keys" => {
+        eval_hash_table_list(list, env, |k, _| k.clone())
+      }
+      "hash-table-values" => {
+        eval_hash_table_list(list, env, |_, v| v.clone())
+      }
+      "hash-table->alist" => {
+        eval_hash_table_list(list, env, |k, v| {
+          Object::ListData(Rc::new(vec![
+            k.clone(),
+            v.clone(),
+          ]))
+        })
+      }
+      "string?" => eval_is_string(list),
+      "string-length" => eval_string_length(list),
+      "string-ref" => eval_string_ref(list),
+      "substring" => eval_substring(list, env),
+      "string-append" => eval_string_append(list, env),
+      "string-split" => eval_string_split(list, env),
+      "string-join" => eval_string_join(list, env),
+      "string-index" => eval_string_index(list),
+      "string-contains" => eval_string_contains(list),
+      "string-upcase" | "string-downcase" => {
+        eval_string_case(list, env)
+      }
+      "string-trim" | "string-trim-left"
+      | "string-trim-right" => eval_string_trim(list, env),
+      "string-replace" => eval_string_replace(list, env),
+      "string->list" => eval_string_to_list(list, env),
+      "list->string" => eval_list_to_string(list, env),
+      "string->number" => eval_string_to_number(list),
+      "number->string" => eval_number_to_string(list, env),
+      "string->symbol" => eval_string_to_symbol(list, env),
+      "symbol->string" => eval_symbol_to_string(list, env),
+      "number?" | "complex?" | "real?" | "rational?"
+      | "integer?" | "exact-integer?" | "exact?"
+      | "inexact?" | "nan?" | "finite?" | "infinite?"
+      | "zero?" | "positive?" | "negative?" | "odd?"
+      | "even?" | "exact" | "inexact"
+      | "exact->inexact" | "inexact->exact" | "floor"
+      | "ceiling" | "round" | "truncate" | "abs"
+      | "numerator" | "denominator" | "sqrt" | "exp"
+      | "log" | "sin" | "cos" | "tan" | "asin" | "acos"
+      | "atan" => eval_number_procedure(list),
+      "quotient" | "remainder" | "modulo" => {
+        eval_integer_division(list)
+      }
+      "min" | "max" => eval_min_max(list),
+      "gcd" | "lcm" => eval_gcd_lcm(list),
+      "expt" => eval_expt(list, env),
+      "eq?" | "eqv?" | "equal?" => eval_equality(list),
+      "macroexpand" => eval_macroexpand(list, env, false),
+      "macroexpand-1" => eval_macroexpand(list, env, true),
+      _ => Err(format!("Unknown keyword: {}", s).into()),
+    },
+    _ => Err(format!("Invalid keyword: {}", head).into()),
+  }
 }
 
-#[cfg(test)]
-mod tests {
-  use super::*;
+fn let_binding(
+  binding: &Object,
+) -> Result<(Symbol, &Object), EvalError> {
+  match binding {
+    Object::List(binding) if binding.len() == 2 => {
+      match &binding[0] {
+        Object::Symbol(name) => Ok((*name, &binding[1])),
+        _ => {
+          Err("Invalid binding for let".to_string().into())
+        }
+      }
+    }
+    _ => Err("Invalid binding for let".to_string().into()),
+  }
+}
+
+fn winders(frames: &[Frame]) -> Vec<(usize, &Object)> {
+  frames
+    .iter()
+    .filter_map(|frame| match frame {
+      Frame::Wind { id, before, .. } => Some((*id, before)),
+      _ => None,
+    })
+    .collect()
+}
+
+struct Machine {
+  id: usize,
+  stack: Vec<Frame>,
+}
+
+impl Machine {
+  fn start(step: Step) -> Result<Object, EvalError> {
+    Machine::start_with(Vec::new(), step)
+  }
+
+  fn start_with(
+    stack: Vec<Frame>,
+    step: Step,
+  ) -> Result<Object, EvalError> {
+    let mut machine = Machine {
+      id: next_id(),
+      stack,
+    };
+    MACHINES.with(|m| m.borrow_mut().push(machine.id));
+    let result = machine.run(step);
+    MACHINES.with(|m| m.borrow_mut().pop());
+    result
+  }
+
+  fn run(
+    &mut self,
+    mut step: Step,
+  ) -> Result<Object, EvalError> {
+    loop {
+      let next = match step {
+        Step::Eval(obj, env) => self.eval(obj, env),
+        Step::Apply(func, args) => self.apply(func, args),
+        Step::Force(p) => self.force(p),
+        Step::Return(val) => match self.stack.pop() {
+          Some(frame) => self.resume(frame, val),
+          None => return Ok(val),
+        },
+      };
+      step = match next {
+        Ok(step) => step,
+        Err(e) => self.fail(e)?,
+      };
+    }
+  }
+
+  // Hand an error to the handlers, or unwind out of the
+  // machine with it.
+  fn fail(
+    &mut self,
+    err: EvalError,
+  ) -> Result<Step, EvalError> {
+    match err {
+      EvalError::Raise(obj) => {
+        self.raise(obj, false, self.stack.len())
+      }
+      EvalError::Throw(k, val) if k.machine == self.id => {
+        self.reinstate(&k, val)
+      }
+      err => {
+        self.unwind(0)?;
+        Err(err)
+      }
+    }
+  }
+
+  fn eval(
+    &mut self,
+    obj: Object,
+    mut env: Rc<RefCell<Env>>,
+  ) -> Result<Step, EvalError> {
+    let list = match &obj {
+      Object::List(list) => list.clone(),
+      Object::Symbol(s) => {
+        return Ok(Step::Return(eval_symbol(*s, &env)?))
+      }
+      Object::Local(s, depth, index) => {
+        return Ok(Step::Return(eval_local(
+          *s, *depth, *index, &env,
+        )?))
+      }
+      Object::Keyword(_) | Object::BinaryOp(_) => {
+        return Ok(Step::Return(builtin_value(obj, &env)?))
+      }
+      _ => return Ok(Step::Return(obj)),
+    };
+
+    let head = match list.first() {
+      Some(head) => head.clone(),
+      None => {
+        return Err(
+          "Cannot evaluate an empty list"
+            .to_string()
+            .into(),
+        )
+      }
+    };
+    match &head {
+      Object::Keyword(k) => match k.as_str() {
+        "define" => self.eval_define(list, env),
+        "begin" => {
+          let new_env = extend_env(&env)?;
+          Ok(self.eval_body(list, 1, new_env))
+        }
+        "let" => self.eval_let(list, vec![], env),
+        "lambda" => Ok(Step::Return(
+          eval_function_definition(&list, &mut env)?,
+        )),
+        "if" => {
+          if list.len() != 4 {
+            return Err(
+              "Invalid number of arguments for if statement"
+                .to_string()
+                .into(),
+            );
+          }
+          let cond = list[1].clone();
+          self.stack.push(Frame::If {
+            list,
+            env: env.clone(),
+          });
+          Ok(Step::Eval(cond, env))
+        }
+        "cond" => {
+          if list.len() < 2 {
+            return Err(
+              "Invalid number of arguments for cond"
+                .to_string()
+                .into(),
+            );
+          }
+          self.eval_cond(list, 1, env)
+        }
+        "quote" => Ok(Step::Return(eval_quote(&list)?)),
+        "quasiquote" => Ok(Step::Return(eval_quasiquote(
+          &list, &mut env,
+        )?)),
+        "guard" => self.eval_guard(list, env),
+        "delay" => {
+          Ok(Step::Return(eval_delay(&list, &env, false)?))
+        }
+        "delay-force" => {
+          Ok(Step::Return(eval_delay(&list, &env, true)?))
+        }
+        // (stream-cons a b) is (list a (delay b))
+        "stream-cons" => {
+          if list.len() != 3 {
+            return Err(
+              "Invalid number of arguments for stream-cons"
+                .to_string()
+                .into(),
+            );
+          }
+          let pair = Object::List(Rc::new(vec![
+            Object::Keyword("list".to_string()),
+            list[1].clone(),
+            Object::List(Rc::new(vec![
+              Object::Keyword("delay".to_string()),
+              list[2].clone(),
+            ])),
+          ]));
+          Ok(Step::Eval(pair, env))
+        }
+        "do" => Ok(Step::Eval(expand_do(&list)?, env)),
+        "while" => {
+          if list.len() < 2 {
+            return Err(
+              "Invalid number of arguments for while"
+                .to_string()
+                .into(),
+            );
+          }
+          let test = list[1].clone();
+          self.stack.push(Frame::WhileTest {
+            list,
+            env: env.clone(),
+          });
+          Ok(Step::Eval(test, env))
+        }
+        "reset" => {
+          self.stack.push(Frame::Prompt);
+          Ok(self.eval_body(list, 1, env))
+        }
+        "shift" => self.eval_shift(list, env),
+        "define-record-type" => Ok(Step::Return(
+          eval_define_record_type(&list, &env)?,
+        )),
+        _ => self.eval_args(list, vec![head], env),
+      },
+      Object::Symbol(s) => {
+        let func =
+          env.borrow().get(*s).or_else(|| builtin(*s));
+        let func = func.or_else(|| unaliased(*s, &env));
+        match func {
+          Some(func) => {
+            self.eval_args(list, vec![func], env)
+          }
+          None => {
+            Err(format!("Unbound function: {}", s).into())
+          }
+        }
+      }
+      Object::Local(s, depth, index) => {
+        let func = eval_local(*s, *depth, *index, &env)?;
+        self.eval_args(list, vec![func], env)
+      }
+      Object::BinaryOp(_) | Object::Lambda(_, _, _) => {
+        self.eval_args(list, vec![head], env)
+      }
+      _ => self.eval_elements(list, vec![], env),
+    }
+  }
+
+  fn resume(
+    &mut self,
+    frame: Frame,
+    val: Object,
+  ) -> Result<Step, EvalError> {
+    match frame {
+      Frame::Args {
+        list,
+        mut vals,
+        env,
+      } => {
+        vals.push(val);
+        self.eval_args(list, vals, env)
+      }
+      Frame::Elements {
+        list,
+        mut vals,
+        env,
+      } => {
+        vals.push(val);
+        self.eval_elements(list, vals, env)
+      }
+      Frame::If { list, env } => match val {
+        Object::Bool(true) => {
+          Ok(Step::Eval(list[2].clone(), env))
+        }
+        Object::Bool(false) => {
+          Ok(Step::Eval(list[3].clone(), env))
+        }
+        _ => Err(
+          "Condition must be a boolean".to_string().into(),
+        ),
+      },
+      Frame::Body { list, next, env } => {
+        Ok(self.eval_body(list, next, env))
+      }
+      Frame::Toplevel { list, next, env } => {
+        let obj = match list.get(next) {
+          Some(obj) => compile(&expand(obj, &env)?),
+          None => return Ok(Step::Return(val)),
+        };
+        if next + 1 < list.len() {
+          self.stack.push(Frame::Toplevel {
+            list,
+            next: next + 1,
+            env: env.clone(),
+          });
+        }
+        Ok(Step::Eval(obj, env))
+      }
+      Frame::Define { name, env } => {
+        env.borrow_mut().set(name, val);
+        Ok(Step::Return(Object::Void))
+      }
+      Frame::Let {
+        list,
+        mut vals,
+        env,
+      } => {
+        vals.push(val);
+        self.eval_let(list, vals, env)
+      }
+      Frame::Cond { list, next, env } => match val {
+        Object::Bool(true) => match &list[next] {
+          Object::List(clause) => {
+            Ok(Step::Eval(clause[1].clone(), env))
+          }
+          _ => unreachable!(),
+        },
+        Object::Bool(false) => {
+          self.eval_cond(list, next + 1, env)
+        }
+        _ => Err(
+          format!("Condition must be a boolean {}", val)
+            .into(),
+        ),
+      },
+      Frame::Guard { .. } | Frame::Handler(_) => {
+        Ok(Step::Return(val))
+      }
+      Frame::GuardClause {
+        spec,
+        next,
+        obj,
+        env,
+      } => match val {
+        Object::Bool(true) => match &spec[next] {
+          Object::List(clause) if clause.len() == 1 => {
+            Ok(Step::Return(val))
+          }
+          Object::List(clause) => {
+            Ok(self.eval_body(clause.clone(), 1, env))
+          }
+          _ => unreachable!(),
+        },
+        Object::Bool(false) => {
+          self.eval_guard_clause(spec, next + 1, obj, env)
+        }
+        _ => Err(
+          format!("Condition must be a boolean {}", val)
+            .into(),
+        ),
+      },
+      Frame::Raise {
+        obj,
+        continuable,
+        handler,
+      } => {
+        if continuable {
+          return Ok(Step::Return(val));
+        }
+        self.raise(
+          Object::Error(
+            "Exception handler returned".to_string(),
+            vec![obj],
+          ),
+          false,
+          handler,
+        )
+      }
+      Frame::Winding {
+        before,
+        thunk,
+        after,
+      } => {
+        self.stack.push(Frame::Wind {
+          id: next_id(),
+          before,
+          after,
+        });
+        Ok(Step::Apply(thunk, vec![]))
+      }
+      Frame::Wind { after, .. } => {
+        self.stack.push(Frame::Discard(val));
+        Ok(Step::Apply(after, vec![]))
+      }
+      Frame::Discard(val) => Ok(Step::Return(val)),
+      Frame::Prompt => Ok(Step::Return(val)),
+      Frame::WhileTest { list, env } => match val {
+        Object::Bool(true) => {
+          self.stack.push(Frame::WhileBody {
+            list: list.clone(),
+            env: env.clone(),
+          });
+          Ok(self.eval_body(list, 2, env))
+        }
+        Object::Bool(false) => {
+          Ok(Step::Return(Object::Void))
+        }
+        _ => Err(
+          format!("Condition must be a boolean {}", val)
+            .into(),
+        ),
+      },
+      Frame::WhileBody { list, env } => {
+        let test = list[1].clone();
+        self.stack.push(Frame::WhileTest {
+          list,
+          env: env.clone(),
+        });
+        Ok(Step::Eval(test, env))
+      }
+      Frame::Walk {
+        kind,
+        func,
+        lists,
+        index,
+        mut acc,
+      } => {
+        match kind {
+          Walk::ForEach => {}
+          Walk::Map | Walk::VectorMap => acc.push(val),
+          Walk::Filter => {
+            if val != Object::Bool(false) {
+              acc.push(lists[0][index - 1].clone());
+            }
+          }
+          _ => acc = vec![val],
+        }
+        self.walk(kind, func, lists, index, acc)
+      }
+      Frame::Find {
+        kind,
+        pred,
+        x,
+        items,
+        index,
+      } => {
+        // string-index wants #t, as a char test would give.
+        let found = match kind {
+          Find::StringIndex => val == Object::Bool(true),
+          _ => is_true(&val),
+        };
+        if !found {
+          return self.find(
+            kind,
+            pred,
+            x,
+            items,
+            index + 1,
+          );
+        }
+        Ok(Step::Return(match kind {
+          Find::Member => Object::ListData(Rc::new(
+            items[index..].to_vec(),
+          )),
+          Find::Assoc => items[index].clone(),
+          Find::StringIndex => {
+            Object::Integer(index as i64)
+          }
+        }))
+      }
+      Frame::Sort { less, mut sort } => {
+        sort.take(is_true(&val));
+        self.sort(less, sort)
+      }
+      Frame::Force(p) => {
+        let state = match p.state.borrow().clone() {
+          PromiseState::Running(prev) => *prev,
+          state => state,
+        };
+        match state {
+          // Computed by a continuation that left and came
+          // back, the first value stays.
+          PromiseState::Done(val) => Ok(Step::Return(val)),
+          PromiseState::Delayed(_, _) => {
+            *p.state.borrow_mut() =
+              PromiseState::Done(val.clone());
+            Ok(Step::Return(val))
+          }
+          PromiseState::DelayForce(_, _) => {
+            // Checked before p is put back, as next may be p.
+            let next = match &val {
+              Object::Promise(next) => {
+                next.state.borrow().clone()
+              }
+              _ => {
+                p.state.replace(state);
+                return Err(
+                  format!("{} is not a promise", val)
+                    .into(),
+                );
+              }
+            };
+            if let PromiseState::Running(_) = next {
+              p.state.replace(state);
+              return Err(recursive_force());
+            }
+            p.state.replace(next);
+            self.force(p)
+          }
+          PromiseState::Running(_) => unreachable!(),
+        }
+      }
+      Frame::Generator(g) => {
+        *g.state.borrow_mut() =
+          GeneratorState::Done(val.clone());
+        Ok(Step::Return(val))
+      }
+    }
+  }
+
+  fn eval_args(
+    &mut self,
+    list: Rc<Vec<Object>>,
+    vals: Vec<Object>,
+    env: Rc<RefCell<Env>>,
+  ) -> Result<Step, EvalError> {
+    match list.get(vals.len()).cloned() {
+      Some(arg) => {
+        self.stack.push(Frame::Args {
+          list,
+          vals,
+          env: env.clone(),
+        });
+        Ok(Step::Eval(arg, env))
+      }
+      None => self.call(vals, env),
+    }
+  }
+
+  fn eval_elements(
+    &mut self,
+    list: Rc<Vec<Object>>,
+    vals: Vec<Object>,
+    env: Rc<RefCell<Env>>,
+  ) -> Result<Step, EvalError> {
+    if let Some(obj) = list.get(vals.len()).cloned() {
+      self.stack.push(Frame::Elements {
+        list,
+        vals,
+        env: env.clone(),
+      });
+      return Ok(Step::Eval(obj, env));
+    }
+
+    let mut vals = vals
+      .into_iter()
+      .filter(|val| *val != Object::Void)
+      .collect::<Vec<_>>();
+    match vals.first() {
+      Some(Object::Lambda(_, _, _))
+      | Some(Object::Builtin(_, _))
+      | Some(Object::Continuation(_))
+      | Some(Object::Generator(_))
+      | Some(Object::Yield(_)) => {
+        let args = vals.split_off(1);
+        Ok(Step::Apply(vals.remove(0), args))
+      }
+      _ => Ok(Step::Return(Object::List(Rc::new(vals)))),
+    }
+  }
+
+  fn eval_body(
+    &mut self,
+    list: Rc<Vec<Object>>,
+    start: usize,
+    env: Rc<RefCell<Env>>,
+  ) -> Step {
+    let obj = match list.get(start) {
+      Some(obj) => obj.clone(),
+      None => return Step::Return(Object::Void),
+    };
+    if start + 1 < list.len() {
+      self.stack.push(Frame::Body {
+        list,
+        next: start + 1,
+        env: env.clone(),
+      });
+    }
+    Step::Eval(obj, env)
+  }
+
+  fn eval_define(
+    &mut self,
+    list: Rc<Vec<Object>>,
+    mut env: Rc<RefCell<Env>>,
+  ) -> Result<Step, EvalError> {
+    if list.len() != 3 {
+      return Err(
+        "Invalid number of arguments for define"
+          .to_string()
+          .into(),
+      );
+    }
+
+    match &list[1] {
+      Object::Symbol(s) => {
+        self.stack.push(Frame::Define {
+          name: *s,
+          env: env.clone(),
+        });
+        Ok(Step::Eval(list[2].clone(), env))
+      }
+      Object::List(l) => {
+        let name = match l.first() {
+          Some(Object::Symbol(s)) => *s,
+          _ => {
+            return Err(
+              format!("Invalid define {:?}", l).into(),
+            )
+          }
+        };
+        let params = Object::List(Rc::new(l[1..].to_vec()));
+        let body = list[2].clone();
+        let lambda = eval_function_definition(
+          &[Object::Void, params, body],
+          &mut env,
+        )?;
+        env.borrow_mut().set(name, lambda);
+        Ok(Step::Return(Object::Void))
+      }
+      _ => Err("Invalid define".to_string().into()),
+    }
+  }
+
+  // Evaluate the binding values of a let one at a time,
+  // then its body in a new scope holding them.
+  fn eval_let(
+    &mut self,
+    list: Rc<Vec<Object>>,
+    vals: Vec<Object>,
+    env: Rc<RefCell<Env>>,
+  ) -> Result<Step, EvalError> {
+    if list.len() < 3 {
+      return Err(
+        "Invalid number of arguments for let"
+          .to_string()
+          .into(),
+      );
+    }
+
+    let bindings = match &list[1] {
+      Object::List(bindings) => bindings.clone(),
+      _ => {
+        return Err(
+          "Invalid bindings for let".to_string().into(),
+        )
+      }
+    };
+
+    if let Some(binding) = bindings.get(vals.len()) {
+      let (_, value) = let_binding(binding)?;
+      let value = value.clone();
+      self.stack.push(Frame::Let {
+        list,
+        vals,
+        env: env.clone(),
+      });
+      return Ok(Step::Eval(value, env));
+    }
+
+    let names = bindings
+      .iter()
+      .map(|binding| Ok(let_binding(binding)?.0))
+      .collect::<Result<Vec<_>, EvalError>>()?;
+    let new_env =
+      extend_env_with(&env, Rc::new(names), vals)?;
+    Ok(self.eval_body(list, 2, new_env))
+  }
+
+  fn eval_cond(
+    &mut self,
+    list: Rc<Vec<Object>>,
+    next: usize,
+    env: Rc<RefCell<Env>>,
+  ) -> Result<Step, EvalError> {
+    let clause = match list.get(next) {
+      Some(Object::List(clause)) => clause.clone(),
+      Some(_) => {
+        return Err(
+          "Invalid cond clause".to_string().into(),
+        )
+      }
+      None => {
+        return Err(
+          "No cond clause matched".to_string().into(),
+        )
+      }
+    };
+    if clause.len() != 2 {
+      return Err(
+        format!("Invalid cond clause {:?}", clause).into(),
+      );
+    }
+
+    if clause[0] == Object::Keyword("else".to_string()) {
+      return Ok(Step::Eval(clause[1].clone(), env));
+    }
+    self.stack.push(Frame::Cond {
+      list,
+      next,
+      env: env.clone(),
+    });
+    Ok(Step::Eval(clause[0].clone(), env))
+  }
+
+  fn eval_guard(
+    &mut self,
+    list: Rc<Vec<Object>>,
+    env: Rc<RefCell<Env>>,
+  ) -> Result<Step, EvalError> {
+    if list.len() < 3 {
+      return Err(
+        "Invalid number of arguments for guard"
+          .to_string()
+          .into(),
+      );
+    }
+    let spec = match &list[1] {
+      Object::List(spec)
+        if matches!(
+          spec.first(),
+          Some(Object::Symbol(_))
+        ) =>
+      {
+        spec.clone()
+      }
+      _ => {
+        return Err(
+          format!("Invalid guard {}", list[1]).into(),
+        )
+      }
+    };
+
+    let body_env = extend_env(&env)?;
+    self.stack.push(Frame::Guard { spec, env });
+    Ok(self.eval_body(list, 2, body_env))
+  }
+
+  fn eval_guard_clause(
+    &mut self,
+    spec: Rc<Vec<Object>>,
+    next: usize,
+    obj: Object,
+    env: Rc<RefCell<Env>>,
+  ) -> Result<Step, EvalError> {
+    let clause = match spec.get(next) {
+      Some(Object::List(clause)) if !clause.is_empty() => {
+        clause.clone()
+      }
+      Some(clause) => {
+        return Err(
+          format!("Invalid guard clause {}", clause).into(),
+        )
+      }
+      // No clause matched, pass the object on to the outer
+      // handlers.
+      None => {
+        return self.raise(obj, false, self.stack.len())
+      }
+    };
+
+    if clause[0] == Object::Keyword("else".to_string()) {
+      return Ok(self.eval_body(clause, 1, env));
+    }
+    let test = clause[0].clone();
+    self.stack.push(Frame::GuardClause {
+      spec,
+      next,
+      obj,
+      env: env.clone(),
+    });
+    Ok(Step::Eval(test, env))
+  }
+
+  // Capture the frames up to the nearest reset as a
+  // procedure, and evaluate the body of shift with only the
+  // reset left to return to.
+  fn eval_shift(
+    &mut self,
+    list: Rc<Vec<Object>>,
+    env: Rc<RefCell<Env>>,
+  ) -> Result<Step, EvalError> {
+    if list.len() < 3 {
+      return Err(
+        "Invalid number of arguments for shift"
+          .to_string()
+          .into(),
+      );
+    }
+    let name = match &list[1] {
+      Object::Symbol(name) => *name,
+      obj => {
+        return Err(
+          format!("Invalid shift variable {}", obj).into(),
+        )
+      }
+    };
+    let prompt = match self
+      .stack
+      .iter()
+      .rposition(|frame| matches!(frame, Frame::Prompt))
+    {
+      Some(i) => i,
+      None => {
+        return Err(
+          "shift outside of reset".to_string().into(),
+        )
+      }
+    };
+
+    let k = Continuation {
+      machine: self.id,
+      frames: self.stack[prompt + 1..].to_vec(),
+      delimited: true,
+    };
+    self.unwind(prompt + 1)?;
+    let new_env = extend_env(&env)?;
+    new_env
+      .borrow_mut()
+      .set(name, Object::Continuation(Rc::new(k)));
+    Ok(self.eval_body(list, 2, new_env))
+  }
+
+  // Procedures that need the machine itself, the others are
+  // handed their evaluated arguments.
+  fn call(
+    &mut self,
+    mut vals: Vec<Object>,
+    mut env: Rc<RefCell<Env>>,
+  ) -> Result<Step, EvalError> {
+    let keyword = match &vals[0] {
+      Object::Keyword(k) => k.as_str(),
+      Object::BinaryOp(_) => {
+        return Ok(Step::Return(eval_binary_op(
+          &vals, &mut env,
+        )?))
+      }
+      _ => {
+        let args = vals.split_off(1);
+        return Ok(Step::Apply(vals.remove(0), args));
+      }
+    };
+
+    match keyword {
+      "raise-continuable" => {
+        if vals.len() != 2 {
+          return Err(
+            "Invalid number of arguments for raise"
+              .to_string()
+              .into(),
+          );
+        }
+        self.raise(vals.remove(1), true, self.stack.len())
+      }
+      "with-exception-handler" => {
+        if vals.len() != 3 {
+          return Err(
+            "Invalid number of arguments for with-exception-handler"
+              .to_string()
+              .into(),
+          );
+        }
+        if !matches!(vals[1], Object::Lambda(_, _, _)) {
+          return Err(
+            format!(
+              "Exception handler must be a lambda {}",
+              vals[1]
+            )
+            .into(),
+          );
+        }
+        let thunk = vals.remove(2);
+        self.stack.push(Frame::Handler(vals.remove(1)));
+        Ok(Step::Apply(thunk, vec![]))
+      }
+      "call/cc" | "call-with-current-continuation" => {
+        if vals.len() != 2 {
+          return Err(
+            "Invalid number of arguments for call/cc"
+              .to_string()
+              .into(),
+          );
+        }
+        let k = Continuation {
+          machine: self.id,
+          frames: self.stack.clone(),
+          delimited: false,
+        };
+        Ok(Step::Apply(
+          vals.remove(1),
+          vec![Object::Continuation(Rc::new(k))],
+        ))
+      }
+      "for-each" | "map" | "filter" | "fold-left"
+      | "fold-right" | "reduce" | "vector-map" => {
+        let (kind, first) = match keyword {
+          "for-each" => (Walk::ForEach, 2),
+          "map" => (Walk::Map, 2),
+          "filter" => (Walk::Filter, 2),
+          "fold-left" => (Walk::FoldLeft, 3),
+          "fold-right" => (Walk::FoldRight, 3),
+          "vector-map" => (Walk::VectorMap, 2),
+          _ => (Walk::Reduce, 3),
+        };
+        let most = match kind {
+          Walk::Filter | Walk::Reduce => first + 1,
+          _ => usize::MAX,
+        };
+        if vals.len() <= first || vals.len() > most {
+          return Err(
+            format!(
+              "Invalid number of arguments for {}",
+              keyword
+            )
+            .into(),
+          );
+        }
+        let mut lists = Vec::new();
+        for obj in vals.drain(first..) {
+          match obj {
+            Object::ListData(l)
+              if kind != Walk::VectorMap =>
+            {
+              lists.push(Rc::unwrap_or_clone(l))
+            }
+            Object::Vector(v)
+              if kind == Walk::VectorMap =>
+            {
+              lists.push(v.borrow().clone())
+            }
+            obj if kind == Walk::VectorMap => {
+              return Err(
+                format!("{} is not a vector", obj).into(),
+              )
+            }
+            obj => {
+              return Err(
+                format!("{} is not a list", obj).into(),
+              )
+            }
+          }
+        }
+        if matches!(
+          kind,
+          Walk::Map | Walk::Filter | Walk::VectorMap
+        ) {
+          let len = lists.iter().map(|l| l.len()).min();
+          charge_list(len.unwrap_or(0), &env)?;
+        }
+        let mut acc = vals.split_off(2);
+        // (reduce f ridentity list) folds the rest of the
+        // list onto its first element.
+        if kind == Walk::Reduce && !lists[0].is_empty() {
+          acc = vec![lists[0].remove(0)];
+        }
+        self.walk(kind, vals.remove(1), lists, 0, acc)
+      }
+      // Called with each key and value, in the order of
+      // hash-table-keys.
+      "hash-table-walk" => {
+        if vals.len() != 3 {
+          return Err(
+            "Invalid number of arguments for hash-table-walk"
+              .to_string()
+              .into(),
+          );
+        }
+        let (keys, values) = match &vals[1] {
+          Object::HashTable(t) => {
+            t.borrow().entries().iter().cloned().unzip()
+          }
+          obj => {
+            return Err(
+              format!("{} is not a hash table", obj).into(),
+            )
+          }
+        };
+        let lists = vec![keys, values];
+        self.walk(
+          Walk::ForEach,
+          vals.remove(2),
+          lists,
+          0,
+          vec![],
+        )
+      }
+      "force" => {
+        if vals.len() != 2 {
+          return Err(
+            "Invalid number of arguments for force"
+              .to_string()
+              .into(),
+          );
+        }
+        match vals.remove(1) {
+          Object::Promise(p) => self.force(p),
+          val => Ok(Step::Return(val)),
+        }
+      }
+      "stream-cdr" => {
+        if vals.len() != 2 {
+          return Err(
+            "Invalid number of arguments for stream-cdr"
+              .to_string()
+              .into(),
+          );
+        }
+        match &stream_pair(&vals[1])?[1] {
+          Object::Promise(p) => self.force(p.clone()),
+          _ => unreachable!(),
+        }
+      }
+      "dynamic-wind" => {
+        if vals.len() != 4 {
+          return Err(
+            "Invalid number of arguments for dynamic-wind"
+              .to_string()
+              .into(),
+          );
+        }
+        let after = vals.remove(3);
+        let thunk = vals.remove(2);
+        let before = vals.remove(1);
+        self.stack.push(Frame::Winding {
+          before: before.clone(),
+          thunk,
+          after,
+        });
+        Ok(Step::Apply(before, vec![]))
+      }
+      // The procedures that call back into the program make
+      // their calls on this machine.
+      "member" | "assoc"
+        if vals.len() == 4
+          && !is_persistent(vals.get(1)) =>
+      {
+        let kind = match keyword {
+          "member" => Find::Member,
+          _ => Find::Assoc,
+        };
+        let items = list_arg(&vals[2])?.to_vec();
+        // The rest of the list member returns is charged
+        // for up front.
+        if kind == Find::Member {
+          charge_list(items.len(), &env)?;
+        }
+        let pred = vals.remove(3);
+        self.find(kind, pred, vals.remove(1), items, 0)
+      }
+      "string-index"
+        if vals.len() == 3
+          && !matches!(vals[2], Object::Char(_)) =>
+      {
+        let items =
+          string_arg(&vals[1])?.chars().map(Object::Char);
+        let items = items.collect();
+        let pred = vals.remove(2);
+        self.find(
+          Find::StringIndex,
+          pred,
+          Object::Void,
+          items,
+          0,
+        )
+      }
+      "sort" => {
+        check_args(&vals, 2, 2)?;
+        let items = list_arg(&vals[1])?.to_vec();
+        charge_list(items.len(), &env)?;
+        self.sort(vals.remove(2), MergeSort::new(items))
+      }
+      _ => Ok(Step::Return(eval_keyword(&vals, &mut env)?)),
+    }
+  }
+
+  fn apply(
+    &mut self,
+    func: Object,
+    args: Vec<Object>,
+  ) -> Result<Step, EvalError> {
+    match func {
+      Object::Lambda(params, body, func_env) => {
+        if params.len() != args.len() {
+          return Err(
+            format!(
+              "Invalid number of arguments for lambda {}",
+              Object::Lambda(params, body, func_env)
+            )
+            .into(),
+          );
+        }
+        let new_env =
+          extend_env_with(&func_env, params, args)?;
+        Ok(Step::Eval(Object::List(body), new_env))
+      }
+      Object::RecordProcedure(p) => {
+        Ok(Step::Return(apply_record_procedure(&p, args)?))
+      }
+      Object::Builtin(op, env) => {
+        let mut vals = vec![(*op).clone()];
+        vals.extend(args);
+        self.call(vals, env)
+      }
+      Object::Continuation(k) => {
+        let val = match args.len() {
+          0 => Object::Void,
+          1 => args[0].clone(),
+          _ => return Err(
+            "Invalid number of arguments for continuation"
+              .to_string()
+              .into(),
+          ),
+        };
+        if k.delimited {
+          return self.compose(&k, val);
+        }
+        // A continuation of an evaluator further out is
+        // reached by unwinding the Rust stack to it.
+        let active = MACHINES
+          .with(|m| m.borrow().contains(&k.machine));
+        if k.machine != self.id && active {
+          return Err(EvalError::Throw(k, val));
+        }
+        self.reinstate(&k, val)
+      }
+      Object::Generator(g) => {
+        let val =
+          args.into_iter().next().unwrap_or(Object::Void);
+        let state =
+          g.state.replace(GeneratorState::Running);
+        match state {
+          GeneratorState::Start(func) => {
+            self.stack.push(Frame::Generator(g.clone()));
+            Ok(Step::Apply(func, vec![Object::Yield(g)]))
+          }
+          GeneratorState::Suspended(frames) => {
+            for (_, before) in winders(&frames) {
+              apply_procedure(before, vec![])?;
+            }
+            self.stack.push(Frame::Generator(g));
+            self.stack.extend(frames);
+            Ok(Step::Return(val))
+          }
+          GeneratorState::Running => Err(
+            "Generator is already running"
+              .to_string()
+              .into(),
+          ),
+          GeneratorState::Done(val) => {
+            g.state
+              .replace(GeneratorState::Done(val.clone()));
+            Ok(Step::Return(val))
+          }
+        }
+      }
+      Object::Yield(g) => {
+        let val =
+          args.into_iter().next().unwrap_or(Object::Void);
+        let prompt = self.stack.iter().rposition(|frame| {
+          matches!(frame, Frame::Generator(h) if Rc::ptr_eq(h, &g))
+        });
+        let prompt = match prompt {
+          Some(i) => i,
+          None => {
+            return Err(
+              "yield outside of its generator"
+                .to_string()
+                .into(),
+            )
+          }
+        };
+        let frames = self.stack[prompt + 1..].to_vec();
+        self.unwind(prompt + 1)?;
+        self.stack.pop();
+        *g.state.borrow_mut() =
+          GeneratorState::Suspended(frames);
+        Ok(Step::Return(val))
+      }
+      _ => Err(format!("Not a lambda: {}", func).into()),
+    }
+  }
+
+  // Hand obj to the innermost handler below index from. A
+  // handler procedure is called in the dynamic context of
+  // the raise, a guard gets the object by unwinding to it.
+  fn raise(
+    &mut self,
+    obj: Object,
+    continuable: bool,
+    from: usize,
+  ) -> Result<Step, EvalError> {
+    let mut i = from;
+    while i > 0 {
+      i -= 1;
+      match &self.stack[i] {
+        Frame::Handler(handler) => {
+          let handler = handler.clone();
+          self.stack.push(Frame::Raise {
+            obj: obj.clone(),
+            continuable,
+            handler: i,
+          });
+          return Ok(Step::Apply(handler, vec![obj]));
+        }
+        Frame::Guard { spec, env } => {
+          let (spec, env) = (spec.clone(), env.clone());
+          if let Err(e) = self.unwind(i) {
+            return self.fail(e);
+          }
+          let guard_env = extend_env(&env)?;
+          if let Object::Symbol(var) = &spec[0] {
+            guard_env.borrow_mut().set(*var, obj.clone());
+          }
+          return self
+            .eval_guard_clause(spec, 1, obj, guard_env);
+        }
+        Frame::Raise { handler, .. } => i = *handler,
+        _ => {}
+      }
+    }
+    if let Err(e) = self.unwind(0) {
+      return self.fail(e);
+    }
+    Err(EvalError::Raise(obj))
+  }
+
+  // Pop frames down to depth, leaving every dynamic-wind on
+  // the way.
+  fn unwind(
+    &mut self,
+    depth: usize,
+  ) -> Result<(), EvalError> {
+    while self.stack.len() > depth {
+      match self.stack.pop() {
+        Some(Frame::Wind { after, .. }) => {
+          apply_procedure(&after, vec![])?;
+        }
+        Some(Frame::Generator(g)) => {
+          *g.state.borrow_mut() =
+            GeneratorState::Done(Object::Void);
+        }
+        Some(Frame::Force(p)) => {
+          let state = p.state.borrow().clone();
+          if let PromiseState::Running(prev) = state {
+            p.state.replace(*prev);
+          }
+        }
+        _ => {}
+      }
+    }
+    Ok(())
+  }
+
+  // Apply func to the elements at index, stopping at the
+  // end of the shortest list. fold-right counts index from
+  // the end.
+  fn walk(
+    &mut self,
+    kind: Walk,
+    func: Object,
+    lists: Vec<Vec<Object>>,
+    index: usize,
+    mut acc: Vec<Object>,
+  ) -> Result<Step, EvalError> {
+    let len =
+      lists.iter().map(|l| l.len()).min().unwrap_or(0);
+    if index >= len {
+      let val = match kind {
+        Walk::ForEach => Object::Void,
+        Walk::Map | Walk::Filter => {
+          Object::ListData(Rc::new(acc))
+        }
+        Walk::VectorMap => new_vector(acc),
+        _ => acc.pop().unwrap_or(Object::Void),
+      };
+      return Ok(Step::Return(val));
+    }
+
+    let at = match kind {
+      Walk::FoldRight => len - 1 - index,
+      _ => index,
+    };
+    let mut args: Vec<Object> =
+      lists.iter().map(|l| l[at].clone()).collect();
+    match kind {
+      Walk::FoldLeft => args.insert(0, acc[0].clone()),
+      Walk::FoldRight | Walk::Reduce => {
+        args.push(acc[0].clone())
+      }
+      _ => {}
+    }
+    self.stack.push(Frame::Walk {
+      kind,
+      func: func.clone(),
+      lists,
+      index: index + 1,
+      acc,
+    });
+    Ok(Step::Apply(func, args))
+  }
+
+  // Call pred on the item at index, or return #f past the
+  // last one.
+  fn find(
+    &mut self,
+    kind: Find,
+    pred: Object,
+    x: Object,
+    items: Vec<Object>,
+    index: usize,
+  ) -> Result<Step, EvalError> {
+    let item = match items.get(index) {
+      Some(item) => item,
+      None => return Ok(Step::Return(Object::Bool(false))),
+    };
+    let args = match (kind, item) {
+      (Find::Member, item) => vec![x.clone(), item.clone()],
+      (Find::Assoc, Object::ListData(pair))
+        if !pair.is_empty() =>
+      {
+        vec![x.clone(), pair[0].clone()]
+      }
+      (Find::Assoc, _) => {
+        return Err(
+          format!(
+            "{} is not an association list",
+            Object::ListData(Rc::new(items))
+          )
+          .into(),
+        )
+      }
+      (Find::StringIndex, item) => vec![item.clone()],
+    };
+    self.stack.push(Frame::Find {
+      kind,
+      pred: pred.clone(),
+      x,
+      items,
+      index,
+    });
+    Ok(Step::Apply(pred, args))
+  }
+
+  fn sort(
+    &mut self,
+    less: Object,
+    mut sort: MergeSort,
+  ) -> Result<Step, EvalError> {
+    match sort.next() {
+      Some((b, a)) => {
+        self.stack.push(Frame::Sort {
+          less: less.clone(),
+          sort,
+        });
+        Ok(Step::Apply(less, vec![b, a]))
+      }
+      None => Ok(Step::Return(Object::ListData(Rc::new(
+        sort.into_sorted(),
+      )))),
+    }
+  }
+
+  // Force p, replacing a chain of delay-force promises by
+  // the next one in place rather than growing the stack.
+  fn force(
+    &mut self,
+    p: Rc<Promise>,
+  ) -> Result<Step, EvalError> {
+    let state = p.state.borrow().clone();
+    match state {
+      PromiseState::Done(val) => Ok(Step::Return(val)),
+      PromiseState::Running(_) => Err(recursive_force()),
+      PromiseState::Delayed(ref expr, ref env)
+      | PromiseState::DelayForce(ref expr, ref env) => {
+        let (expr, env) = (expr.clone(), env.clone());
+        p.state
+          .replace(PromiseState::Running(Box::new(state)));
+        self.stack.push(Frame::Force(p));
+        Ok(Step::Eval(expr, env))
+      }
+    }
+  }
+
+  // Run the frames of a delimited continuation on top of
+  // the current ones, under a reset of their own.
+  fn compose(
+    &mut self,
+    k: &Continuation,
+    val: Object,
+  ) -> Result<Step, EvalError> {
+    for (_, before) in winders(&k.frames) {
+      apply_procedure(before, vec![])?;
+    }
+    self.stack.push(Frame::Prompt);
+    self.stack.extend(k.frames.iter().cloned());
+    Ok(Step::Return(val))
+  }
+
+  // Replace the stack with the frames of k, leaving the
+  // dynamic-winds that k is outside of and entering the ones
+  // it is inside of.
+  fn reinstate(
+    &mut self,
+    k: &Continuation,
+    val: Object,
+  ) -> Result<Step, EvalError> {
+    let target = winders(&k.frames);
+    let mut common = 0;
+    let mut depth = self.stack.len();
+    for (i, frame) in self.stack.iter().enumerate() {
+      if let Frame::Wind { id, .. } = frame {
+        if target.get(common).map(|w| w.0) != Some(*id) {
+          depth = i;
+          break;
+        }
+        common += 1;
+      }
+    }
+    if let Err(e) = self.unwind(depth) {
+      return self.fail(e);
+    }
+    for (_, before) in target[common..].iter() {
+      if let Err(e) = apply_procedure(before, vec![]) {
+        return self.fail(e);
+      }
+    }
+    self.stack = k.frames.clone();
+    Ok(Step::Return(val))
+  }
+}
+
+pub fn eval_obj(
+  obj: &Object,
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  Machine::start(Step::Eval(obj.clone(), env.clone()))
+}
+
+pub fn eval(
+  program: &str,
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  let parsed_list = parse(program);
+  if parsed_list.is_err() {
+    return Err(
+      format!("{}", parsed_list.err().unwrap()).into(),
+    );
+  }
+  match parsed_list.unwrap() {
+    // The forms of a program that is a begin are expanded
+    // one at a time, as they are reached, so that a macro
+    // can call the procedures defined before it.
+    Object::List(list)
+      if list.first()
+        == Some(&Object::Keyword("begin".to_string())) =>
+    {
+      let begin_env = extend_env(env)?;
+      Machine::start_with(
+        vec![Frame::Toplevel {
+          list,
+          next: 1,
+          env: begin_env,
+        }],
+        Step::Return(Object::Void),
+      )
+    }
+    program => {
+      let expanded = expand(&program, env)?;
+      eval_obj(&compile(&expanded), env)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_simple_add() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let result = eval("(+ 1 2)", &mut env).unwrap();
+    assert_eq!(result, Object::Integer(3));
+  }
+
+  #[test]
+  fn test_simple_sub() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let result = eval("(- 1.0 2)", &mut env).unwrap();
+    assert_eq!(result, Object::Float(-1.0));
+  }
+
+  #[test]
+  fn test_str_add() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let result =
+      eval("(+ \"Raleigh\" \"Durham\")", &mut env).unwrap();
+    assert_eq!(
+      result,
+      Object::String("RaleighDurham".into())
+    );
+  }
+
+  #[test]
+  fn test_str_eq_false() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let result =
+      eval("(= \"Raleigh\" \"Durham\")", &mut env).unwrap();
+    assert_eq!(result, Object::Bool(false));
+  }
+
+  #[test]
+  fn test_str_eq_true() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let result =
+      eval("(= \"Raleigh\" \"Raleigh\")", &mut env)
+        .unwrap();
+    assert_eq!(result, Object::Bool(true));
+  }
+
+  #[test]
+  fn test_greater_than_str() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let result =
+      eval("(> \"Raleigh\" \"Durham\")", &mut env).unwrap();
+    assert_eq!(result, Object::Bool(true));
+  }
+
+  #[test]
+  fn test_less_than_str() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let result =
+      eval("(< \"abcd\" \"abef\")", &mut env).unwrap();
+    assert_eq!(result, Object::Bool(true));
+  }
+
+  #[test]
+  fn test_str_with_spaces() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let result =
+      eval("(+ \"Raleigh \" \"Durham\")", &mut env)
+        .unwrap();
+    assert_eq!(
+      result,
+      Object::String("Raleigh Durham".into())
+    );
+  }
+
+  #[test]
+  fn test_str_with_spaces_2() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (
+            (define fruits \"apples mangoes bananas \")
+            (define vegetables \"carrots broccoli\")
+            (+ fruits vegetables)
+        )
+        ";
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result,
+      Object::List(Rc::new(vec![Object::String(
+        "apples mangoes bananas carrots broccoli".into()
+      )]))
+    );
+  }
+
+  #[test]
+  fn test_greater_than_int() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let result = eval("(> 10 20)", &mut env).unwrap();
+    assert_eq!(result, Object::Bool(false));
+  }
+
+  #[test]
+  fn test_less_than_int() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let result = eval("(< 21.0 20.0)", &mut env).unwrap();
+    assert_eq!(result, Object::Bool(false));
+  }
+
+  #[test]
+  fn test_modulo() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let result = eval("(% 21.0 20.0)", &mut env).unwrap();
+    assert_eq!(result, Object::Float(1.0));
+  }
+
+  #[test]
+  #[allow(clippy::approx_constant)]
+  fn test_area_of_a_circle_float() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+            (begin
+                (define r 5.0)
+                (define pi 3.14)
+                (* pi (* r r))
+            )";
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Float(3.14 * 5.0 * 5.0));
+  }
+
+  #[test]
+  fn test_area_of_a_circle() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+            (begin
+                (define r 10)
+                (define pi 314)
+                (* pi (* r r))
+            )";
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(314 * 10 * 10));
+  }
+
+  #[test]
+  fn test_sqr_function() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+            (begin
+                (define sqr (lambda (r) (* r r))) 
+                (sqr 10)
+            )";
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(10 * 10));
+  }
+
+  #[test]
+  fn test_fibonaci() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+            (begin
+                (define fib (lambda (n) 
+                    (if (< n 2) 1 
+                        (+ (fib (- n 1)) 
+                            (fib (- n 2))))))
+                (fib 10)
+            )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(89));
+  }
+
+  #[test]
+  fn test_factorial() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+            (begin
+                (define fact (lambda (n) (if (< n 1) 1 (* n (fact (- n 1))))))
+                (fact 5)
+            )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(120));
+  }
+
+  #[test]
+  fn test_circle_area_no_lambda() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+            (begin
+                (define pi 314)
+                (define r 10)
+                (define (sqr r) (* r r))
+                (define (area r) (* pi (sqr r)))
+                (area r)
+            )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(314 * 10 * 10));
+  }
+
+  #[test]
+  fn test_circle_area_function() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+            (begin
+                (define pi 314)
+                (define r 10)
+                (define sqr (lambda (r) (* r r)))
+                (define area (lambda (r) (* pi (sqr r))))
+                (area r)
+            )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(314 * 10 * 10));
+  }
+
+  #[test]
+  fn test_tail_recursion() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+            (begin
+                (define sum-n 
+                   (lambda (n a) 
+                      (if (= n 0) a 
+                          (sum-n (- n 1) (+ n a)))))
+                (sum-n 500 0)
+            )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(125250));
+  }
+
+  #[test]
+  fn test_tail_recursive_factorial() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+            (begin
+                (define fact 
+                    (lambda (n a) 
+                      (if (= n 1) a 
+                        (fact (- n 1) (* n a)))))
+                        
+                (fact 10 1)
+            )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(3628800));
+  }
+
+  #[test]
+  fn test_closure1() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+            (begin
+                (define add-n 
+                   (lambda (n) 
+                      (lambda (a) (+ n a))))
+                (define add-5 (add-n 5))
+                (add-5 10)
+            )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(15));
+  }
+
+  #[test]
+  fn test_tail_recursive_fibonnaci() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+            (begin
+                (define fib
+                  (lambda (n a b) 
+                     (if (= n 0) a 
+                       (if (= n 1) b 
+                          (fib (- n 1) b (+ a b))))))
+                  
+                (fib 10 0 1)
+            )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(55));
+  }
+
+  #[test]
+  fn test_inline_lambda() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            ((lambda (x y) (+ x y)) 10 20)
+        )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(30));
+  }
+
+  #[test]
+  fn test_car() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (car (list 1 2 3))
+        )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(1));
+  }
+
+  #[test]
+  fn test_cdr() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (cdr (list 1 2 3))
+        )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result,
+      Object::ListData(Rc::new(vec![
+        Object::Integer(2),
+        Object::Integer(3),
+      ]))
+    );
+  }
+
+  #[test]
+  fn test_length() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (length (list 1 2 3))
+        )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(3));
+  }
+
+  #[test]
+  fn test_sum_list_of_integers() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define sum-list 
+                (lambda (l) 
+                    (if (null? l) 0 
+                        (+ (car l) (sum-list (cdr l))))))
+            (sum-list (list 1 2 3 4 5))
+        )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(15));
+  }
+
+  #[test]
+  fn test_function_application() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define (double value) 
+                (* 2 value))
+            (define (apply-twice fn value) 
+                (fn (fn value)))
+        
+            (apply-twice double 5)
+        )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(20));
+  }
+
+  #[test]
+  fn test_begin_scope_test() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define a 10)
+            (define b 20)
+            (define c 30)
+            (begin
+                (define a 20)
+                (define b 30)
+                (define c 40)
+                (list a b c)
+            )
+        )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result,
+      Object::ListData(Rc::new(vec![
+        Object::Integer(20),
+        Object::Integer(30),
+        Object::Integer(40),
+      ]))
+    );
+  }
+
+  #[test]
+  fn test_begin_scope_test_2() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin 
+            (define x 10)
+            (begin
+                (define x 20)
+                x 
+            )
+            x
+        )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(10));
+  }
+
+  #[test]
+  fn test_cond_1() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+            (cond ((> 2 1) 5) 
+                  ((< 2 1) 10) 
+                  (else 15)
+            )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(5));
+  }
 
   #[test]
-  fn test_simple_add() {
+  fn test_cond_2() {
     let mut env = Rc::new(RefCell::new(Env::new()));
-    let result = eval("(+ 1 2)", &mut env).unwrap();
-    assert_eq!(result, Object::Integer(3));
+    let program = "
+            (cond ((> 1 2) 5) 
+                  ((< 1 2) 10) 
+                  (else 15)
+        )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(10));
   }
 
   #[test]
-  fn test_simple_sub() {
+  fn test_cond_3() {
     let mut env = Rc::new(RefCell::new(Env::new()));
-    let result = eval("(- 1.0 2)", &mut env).unwrap();
-    assert_eq!(result, Object::Float(-1.0));
+    let program = "
+            (cond ((> 1 2) 5) 
+                  ((< 1 0) 10) 
+                  (else 15)
+            )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(15));
   }
 
   #[test]
-  fn test_str_add() {
+  fn test_let_1() {
     let mut env = Rc::new(RefCell::new(Env::new()));
-    let result =
-      eval("(+ \"Raleigh\" \"Durham\")", &mut env).unwrap();
+    let program = "
+        (begin
+            (let ((a 10) (b 20))
+                (list a b)
+            )
+        )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
     assert_eq!(
       result,
-      Object::String("RaleighDurham".to_string())
+      Object::ListData(Rc::new(vec![
+        Object::Integer(10),
+        Object::Integer(20),
+      ]))
     );
   }
 
   #[test]
-  fn test_str_eq_false() {
+  fn test_let_2() {
     let mut env = Rc::new(RefCell::new(Env::new()));
-    let result =
-      eval("(= \"Raleigh\" \"Durham\")", &mut env).unwrap();
-    assert_eq!(result, Object::Bool(false));
-  }
+    let program = "
+        (begin
+            (define a 100)
+            (let ((a 10) (b 20))
+                (list a b)
+            )
+            a
+        )
+        ";
 
-  #[test]
-  fn test_str_eq_true() {
-    let mut env = Rc::new(RefCell::new(Env::new()));
-    let result =
-      eval("(= \"Raleigh\" \"Raleigh\")", &mut env)
-        .unwrap();
-    assert_eq!(result, Object::Bool(true));
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(100));
   }
 
   #[test]
-  fn test_greater_than_str() {
+  fn test_let_3() {
     let mut env = Rc::new(RefCell::new(Env::new()));
-    let result =
-      eval("(> \"Raleigh\" \"Durham\")", &mut env).unwrap();
-    assert_eq!(result, Object::Bool(true));
+    let program = "
+            (let ((x 2) (y 3))
+                (let ((x 7)
+                      (z (+ x y)))
+                    (* z x))) 
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(35));
   }
 
   #[test]
-  fn test_less_than_str() {
+  fn test_map() {
     let mut env = Rc::new(RefCell::new(Env::new()));
-    let result =
-      eval("(< \"abcd\" \"abef\")", &mut env).unwrap();
-    assert_eq!(result, Object::Bool(true));
+    let program = "
+        (begin
+            (define (map f l)
+                (if (null? l) 
+                    (list) 
+                    (cons (f (car l)) (map f (cdr l)))))
+            (map (lambda (x) (* x x)) (list 1 2 3 4 5))
+        )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result,
+      Object::ListData(Rc::new(vec![
+        Object::Integer(1),
+        Object::Integer(4),
+        Object::Integer(9),
+        Object::Integer(16),
+        Object::Integer(25),
+      ]))
+    );
   }
 
   #[test]
-  fn test_str_with_spaces() {
+  fn test_filter() {
     let mut env = Rc::new(RefCell::new(Env::new()));
-    let result =
-      eval("(+ \"Raleigh \" \"Durham\")", &mut env)
-        .unwrap();
+    let program = "
+        (begin
+            (define (filter f l)
+                (if (null? l) 
+                    (list) 
+                    (if (f (car l)) 
+                        (cons (car l) (filter f (cdr l))) 
+                        (filter f (cdr l)))))
+            (filter (lambda (x) (> x 2)) (list 1 2 3 4 5))
+        )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
     assert_eq!(
       result,
-      Object::String("Raleigh Durham".to_string())
+      Object::ListData(Rc::new(vec![
+        Object::Integer(3),
+        Object::Integer(4),
+        Object::Integer(5),
+      ]))
     );
   }
 
   #[test]
-  fn test_str_with_spaces_2() {
+  fn test_fold_left() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
-        (
-            (define fruits \"apples mangoes bananas \")
-            (define vegetables \"carrots broccoli\")
-            (+ fruits vegetables)
+        (begin
+            (define (fold-left f acc l)
+                (if (null? l) 
+                    acc 
+                    (fold-left f (f acc (car l)) (cdr l))))
+            (fold-left (lambda (acc x) (+ acc x)) 0 (list 1 2 3 4 5))
+        )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(15));
+  }
+
+  #[test]
+  fn test_reduce() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define (reduce f l)
+                (if (null? l) 
+                    (list) 
+                    (if (null? (cdr l)) 
+                        (car l) 
+                        (f (car l) (reduce f (cdr l))))))
+            (reduce (lambda (x y) (+ x y)) (list 1 2 3 4 5))
         )
         ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(15));
+  }
+
+  #[test]
+  fn test_list_walks() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define squares (make-generator (lambda (yield)
+                (map (lambda (x) (yield (* x x))) (list 1 2 3)))))
+            (list
+                (map (lambda (x y) (+ x y)) (list 1 2 3) (list 10 20))
+                (fold-right (lambda (x acc) (cons x acc)) (list) (list 1 2 3))
+                (fold-left (lambda (acc x) (cons x acc)) (list) (list 1 2 3))
+                (reduce (lambda (x acc) (- x acc)) 0 (list 1 2 3))
+                (reduce (lambda (x acc) (- x acc)) 0 (list))
+                (list (squares) (squares) (squares))))
+        ";
+
     let result = eval(program, &mut env).unwrap();
     assert_eq!(
-      result,
-      Object::List(Rc::new(vec![Object::String(
-        "apples mangoes bananas carrots broccoli"
-          .to_string()
-      )]))
+      result.to_string(),
+      "((11 22) (1 2 3) (3 2 1) 2 0 (1 4 9))"
     );
   }
 
   #[test]
-  fn test_greater_than_int() {
+  fn test_quote() {
     let mut env = Rc::new(RefCell::new(Env::new()));
-    let result = eval("(> 10 20)", &mut env).unwrap();
-    assert_eq!(result, Object::Bool(false));
+    let program = "
+        (begin
+            (define l '(a (b 1) \"c\"))
+            (list (car l) (car (cdr l)) (quote d))
+        )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result.to_string(), "(a (b 1) d)");
   }
 
   #[test]
-  fn test_less_than_int() {
+  fn test_quasiquote() {
     let mut env = Rc::new(RefCell::new(Env::new()));
-    let result = eval("(< 21.0 20.0)", &mut env).unwrap();
-    assert_eq!(result, Object::Bool(false));
+    let program = "
+        (begin
+            (define x 5)
+            (define l (list 1 2))
+            `(x ,x ,@l (nested ,(+ x 1)))
+        )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result.to_string(), "(x 5 1 2 (nested 6))");
   }
 
   #[test]
-  fn test_modulo() {
-    let mut env = Rc::new(RefCell::new(Env::new()));
-    let result = eval("(% 21.0 20.0)", &mut env).unwrap();
-    assert_eq!(result, Object::Float(1.0));
+  fn test_quota_within_limit() {
+    let mut env =
+      Rc::new(RefCell::new(Env::with_quota(4096)));
+    let result =
+      eval("(length (list 1 2 3))", &mut env).unwrap();
+    assert_eq!(result, Object::Integer(3));
   }
 
   #[test]
-  fn test_area_of_a_circle_float() {
+  fn test_quota_exceeded() {
+    let mut env =
+      Rc::new(RefCell::new(Env::with_quota(4096)));
+    let program = "
+        (begin
+            (define (grow n l)
+                (if (= n 0) l (grow (- n 1) (cons n l))))
+            (grow 1000 (list))
+        )
+        ";
+
+    let result = eval(program, &mut env);
+    assert!(result
+      .unwrap_err()
+      .to_string()
+      .starts_with("Resource limit exceeded"));
+  }
+
+  #[test]
+  fn test_guard_error_object() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
-            (begin
-                (define r 5.0)
-                (define pi 3.14)
-                (* pi (* r r))
-            )";
+        (guard (e ((error-object? e)
+                   (list (error-object-message e)
+                         (error-object-irritants e))))
+            (error \"bad input\" 1 2))
+        ";
+
     let result = eval(program, &mut env).unwrap();
     assert_eq!(
       result,
-      Object::Float((3.14 * 5.0 * 5.0) as f64)
+      Object::ListData(Rc::new(vec![
+        Object::String("bad input".into()),
+        Object::ListData(Rc::new(vec![
+          Object::Integer(1),
+          Object::Integer(2)
+        ])),
+      ]))
     );
   }
 
   #[test]
-  fn test_area_of_a_circle() {
+  fn test_guard_raise_and_reraise() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
-            (begin
-                (define r 10)
-                (define pi 314)
-                (* pi (* r r))
-            )";
+        (guard (e ((= e 42) (+ e 1)))
+            (guard (e ((error-object? e) 0))
+                (raise 42)))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(43));
+  }
+
+  #[test]
+  fn test_guard_internal_error() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (guard (e (else (error-object-message e)))
+            (car 5))
+        ";
+
     let result = eval(program, &mut env).unwrap();
     assert_eq!(
       result,
-      Object::Integer((314 * 10 * 10) as i64)
+      Object::String("5 is not a list".into())
     );
   }
 
   #[test]
-  fn test_sqr_function() {
+  fn test_uncaught_raise() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let result = eval("(raise 'oops)", &mut env);
+    assert_eq!(
+      result.unwrap_err().to_string(),
+      "Uncaught exception: oops"
+    );
+  }
+
+  #[test]
+  fn test_with_exception_handler() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
-            (begin
-                (define sqr (lambda (r) (* r r))) 
-                (sqr 10)
-            )";
+        (with-exception-handler
+            (lambda (e) (* e 2))
+            (lambda () (+ 1 (raise-continuable 20))))
+        ";
+
     let result = eval(program, &mut env).unwrap();
-    assert_eq!(result, Object::Integer((10 * 10) as i64));
+    assert_eq!(result, Object::Integer(41));
   }
 
   #[test]
-  fn test_fibonaci() {
+  fn test_with_exception_handler_returns() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
-            (begin
-                (define fib (lambda (n) 
-                    (if (< n 2) 1 
-                        (+ (fib (- n 1)) 
-                            (fib (- n 2))))))
-                (fib 10)
-            )
+        (guard (e ((error-object? e) (error-object-message e)))
+            (with-exception-handler
+                (lambda (e) (+ e 0))
+                (lambda () (raise 1))))
         ";
 
     let result = eval(program, &mut env).unwrap();
-    assert_eq!(result, Object::Integer((89) as i64));
+    assert_eq!(
+      result,
+      Object::String("Exception handler returned".into())
+    );
   }
 
   #[test]
-  fn test_factorial() {
+  fn test_call_cc_early_exit() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
-            (begin
-                (define fact (lambda (n) (if (< n 1) 1 (* n (fact (- n 1))))))
-                (fact 5)
-            )
+        (begin
+            (define (first-negative l)
+                (call/cc (lambda (return)
+                    (begin
+                        (define (walk l)
+                            (if (null? l) 0
+                                (if (< (car l) 0)
+                                    (return (car l))
+                                    (walk (cdr l)))))
+                        (walk l)))))
+            (first-negative (list 3 1 (- 0 4) 5 (- 0 2))))
         ";
 
     let result = eval(program, &mut env).unwrap();
-    assert_eq!(result, Object::Integer((120) as i64));
+    assert_eq!(result, Object::Integer(-4));
   }
 
   #[test]
-  fn test_circle_area_no_lambda() {
+  fn test_call_cc_reentry() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
-            (begin
-                (define pi 314)
-                (define r 10)
-                (define (sqr r) (* r r))
-                (define (area r) (* pi (sqr r)))
-                (area r)
-            )
+        (let ((p (call/cc (lambda (k) (list 0 k)))))
+            (if (< (car p) 5)
+                ((car (cdr p)) (list (+ (car p) 1) (car (cdr p))))
+                (car p)))
         ";
 
     let result = eval(program, &mut env).unwrap();
-    assert_eq!(
-      result,
-      Object::Integer((314 * 10 * 10) as i64)
-    );
+    assert_eq!(result, Object::Integer(5));
   }
 
   #[test]
-  fn test_circle_area_function() {
+  fn test_call_cc_escapes_nested_evaluation() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
-            (begin
-                (define pi 314)
-                (define r 10)
-                (define sqr (lambda (r) (* r r)))
-                (define area (lambda (r) (* pi (sqr r))))
-                (area r)
-            )
+        (+ 1 (call-with-current-continuation
+                (lambda (k) `(1 ,(k 2)))))
         ";
 
     let result = eval(program, &mut env).unwrap();
-    assert_eq!(
-      result,
-      Object::Integer((314 * 10 * 10) as i64)
-    );
+    assert_eq!(result, Object::Integer(3));
   }
 
   #[test]
-  fn test_tail_recursion() {
+  fn test_dynamic_wind() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
-            (begin
-                (define sum-n 
-                   (lambda (n a) 
-                      (if (= n 0) a 
-                          (sum-n (- n 1) (+ n a)))))
-                (sum-n 500 0)
-            )
+        (dynamic-wind
+            (lambda () (print \"before\"))
+            (lambda () (+ 1 1))
+            (lambda () (print \"after\")))
         ";
 
     let result = eval(program, &mut env).unwrap();
-    assert_eq!(result, Object::Integer((125250) as i64));
+    assert_eq!(result, Object::Integer(2));
   }
 
   #[test]
-  fn test_tail_recursive_factorial() {
+  fn test_dynamic_wind_after_runs_on_escape() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
-            (begin
-                (define fact 
-                    (lambda (n a) 
-                      (if (= n 1) a 
-                        (fact (- n 1) (* n a)))))
-                        
-                (fact 10 1)
-            )
+        (guard (e ((error-object? e) (error-object-message e)))
+            (call/cc (lambda (k)
+                (dynamic-wind
+                    (lambda () (list))
+                    (lambda () (k 1))
+                    (lambda () (error \"left\"))))))
         ";
 
     let result = eval(program, &mut env).unwrap();
-    assert_eq!(result, Object::Integer((3628800) as i64));
+    assert_eq!(result, Object::String("left".into()));
   }
 
   #[test]
-  fn test_closure1() {
+  fn test_dynamic_wind_before_runs_on_reentry() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
-            (begin
-                (define add-n 
-                   (lambda (n) 
-                      (lambda (a) (+ n a))))
-                (define add-5 (add-n 5))
-                (add-5 10)
-            )
+        (guard (e ((error-object? e) (error-object-message e)))
+            (let ((p (dynamic-wind
+                        (lambda () (list))
+                        (lambda () (call/cc (lambda (k) (list 0 k))))
+                        (lambda () (list)))))
+                (if (= (car p) 0)
+                    (dynamic-wind
+                        (lambda () (list))
+                        (lambda () ((car (cdr p)) (list 1 (car (cdr p)))))
+                        (lambda () (error \"left\")))
+                    (car p))))
         ";
 
     let result = eval(program, &mut env).unwrap();
-    assert_eq!(result, Object::Integer((15) as i64));
+    assert_eq!(result, Object::String("left".into()));
   }
 
   #[test]
-  fn test_tail_recursive_fibonnaci() {
+  fn test_shift_composes() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
-            (begin
-                (define fib
-                  (lambda (n a b) 
-                     (if (= n 0) a 
-                       (if (= n 1) b 
-                          (fib (- n 1) b (+ a b))))))
-                  
-                (fib 10 0 1)
-            )
+        (+ 1 (reset (+ 10 (shift k (k (k 100))))))
         ";
 
     let result = eval(program, &mut env).unwrap();
-    assert_eq!(result, Object::Integer((55) as i64));
+    assert_eq!(result, Object::Integer(121));
   }
 
   #[test]
-  fn test_inline_lambda() {
+  fn test_shift_early_return() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
         (begin
-            ((lambda (x y) (+ x y)) 10 20)
-        )
+            (define (product l)
+                (if (null? l) 1
+                    (if (= (car l) 0)
+                        (shift k 0)
+                        (* (car l) (product (cdr l))))))
+            (+ 1 (reset (product (list 3 2 0 4)))))
         ";
 
     let result = eval(program, &mut env).unwrap();
-    assert_eq!(result, Object::Integer((30) as i64));
+    assert_eq!(result, Object::Integer(1));
   }
 
   #[test]
-  fn test_car() {
+  fn test_shift_generator() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
         (begin
-            (car (list 1 2 3))
-        )
+            (define (walk l)
+                (if (null? l) 0
+                    (begin
+                        (shift k (list (car l) k))
+                        (walk (cdr l)))))
+            (define (start l) (reset (begin (walk l) (list))))
+            (define (next g) ((car (cdr g)) 0))
+            (let ((g (start (list 1 2 3))))
+                (let ((g (next g)))
+                    (list (car g) (car (next g)) (next (next g))))))
         ";
 
     let result = eval(program, &mut env).unwrap();
-    assert_eq!(result, Object::Integer((1) as i64));
+    assert_eq!(
+      result,
+      Object::ListData(Rc::new(vec![
+        Object::Integer(2),
+        Object::Integer(3),
+        Object::ListData(Rc::new(vec![])),
+      ]))
+    );
   }
 
   #[test]
-  fn test_cdr() {
+  fn test_shift_outside_reset() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let result = eval("(+ 1 (shift k 2))", &mut env);
+    assert_eq!(
+      result.unwrap_err().to_string(),
+      "shift outside of reset"
+    );
+  }
+
+  #[test]
+  fn test_generator() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
         (begin
-            (cdr (list 1 2 3))
-        )
+            (define g (make-generator (lambda (yield)
+                (begin (yield 1) (yield 2) (yield 3)))))
+            (list (g) (g) (g)))
         ";
 
     let result = eval(program, &mut env).unwrap();
     assert_eq!(
       result,
-      Object::ListData(vec![
+      Object::ListData(Rc::new(vec![
+        Object::Integer(1),
         Object::Integer(2),
         Object::Integer(3),
-      ])
+      ]))
     );
   }
 
   #[test]
-  fn test_length() {
+  fn test_generator_infinite_sequence() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
         (begin
-            (length (list 1 2 3))
-        )
+            (define naturals (make-generator (lambda (yield)
+                (begin
+                    (define (loop n) (begin (yield n) (loop (+ n 1))))
+                    (loop 0)))))
+            (define (sum g n acc)
+                (if (= n 0) acc (sum g (- n 1) (+ acc (g)))))
+            (sum naturals 1000 0))
         ";
 
     let result = eval(program, &mut env).unwrap();
-    assert_eq!(result, Object::Integer((3) as i64));
+    assert_eq!(result, Object::Integer(499500));
   }
 
   #[test]
-  fn test_sum_list_of_integers() {
+  fn test_generator_done() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
         (begin
-            (define sum-list 
-                (lambda (l) 
-                    (if (null? l) 0 
-                        (+ (car l) (sum-list (cdr l))))))
-            (sum-list (list 1 2 3 4 5))
-        )
+            (define g (make-generator (lambda (yield)
+                (begin (yield 1) (yield 2)))))
+            (define (drain g acc)
+                (let ((v (g)))
+                    (if (generator-done? g) acc (drain g (+ acc v)))))
+            (drain g 10))
         ";
 
     let result = eval(program, &mut env).unwrap();
-    assert_eq!(result, Object::Integer(15));
+    assert_eq!(result, Object::Integer(13));
   }
 
+  // The procedures that sort, member, assoc and string-index
+  // call run on the generator's machine, so they can yield.
   #[test]
-  fn test_function_application() {
+  fn test_yield_from_callbacks() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
         (begin
-            (define (double value) 
-                (* 2 value))
-            (define (apply-twice fn value) 
-                (fn (fn value)))
-        
-            (apply-twice double 5)
-        )
+            (define (drain g acc)
+                (let ((v (g)))
+                    (if (generator-done? g)
+                        (list (reverse acc) v)
+                        (drain g (cons v acc)))))
+            (list
+                (drain (make-generator (lambda (yield)
+                    (sort (list 3 1 2)
+                        (lambda (a b) (begin (yield (list a b)) (< a b))))))
+                    (list))
+                (drain (make-generator (lambda (yield)
+                    (member 2 (list 1 2 3)
+                        (lambda (x y) (begin (yield y) (= x y))))))
+                    (list))
+                (drain (make-generator (lambda (yield)
+                    (assoc 2 (list (list 1 'a) (list 2 'b))
+                        (lambda (x y) (begin (yield y) (= x y))))))
+                    (list))
+                (drain (make-generator (lambda (yield)
+                    (string-index \"ab\"
+                        (lambda (c) (begin (yield c) (char=? c #\\b))))))
+                    (list))))
         ";
 
     let result = eval(program, &mut env).unwrap();
-    assert_eq!(result, Object::Integer((20) as i64));
+    assert_eq!(
+      result.to_string(),
+      "((((1 3) (2 1) (2 3)) (1 2 3)) ((1 2) (2 3)) \
+       ((1 2) (2 b)) ((a b) 1))"
+    );
   }
 
   #[test]
-  fn test_begin_scope_test() {
+  fn test_generator_keeps_final_value() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
         (begin
-            (define a 10)
-            (define b 20)
-            (define c 30)
-            (begin
-                (define a 20)
-                (define b 30)
-                (define c 40)
-                (list a b c)
-            )
-        )
+            (define g (make-generator (lambda (yield)
+                (begin (yield 1) (list 'done)))))
+            (list (g) (generator-done? g) (g) (generator-done? g) (g)))
         ";
 
     let result = eval(program, &mut env).unwrap();
     assert_eq!(
-      result,
-      Object::ListData(vec![
-        Object::Integer(20),
-        Object::Integer(30),
-        Object::Integer(40),
-      ])
+      result.to_string(),
+      "(1 false (done) true (done))"
     );
   }
 
   #[test]
-  fn test_begin_scope_test_2() {
+  fn test_generator_receives_values() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
-        (begin 
-            (define x 10)
-            (begin
-                (define x 20)
-                x 
-            )
-            x
-        )
+        (begin
+            (define total (make-generator (lambda (yield)
+                (begin
+                    (define (loop n) (loop (+ n (yield n))))
+                    (loop 0)))))
+            (total)
+            (total 5)
+            (total 10))
         ";
 
     let result = eval(program, &mut env).unwrap();
-    assert_eq!(result, Object::Integer((10) as i64));
+    assert_eq!(result, Object::Integer(15));
   }
 
   #[test]
-  fn test_cond_1() {
+  fn test_force_memoizes() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
-            (cond ((> 2 1) 5) 
-                  ((< 2 1) 10) 
-                  (else 15)
-            )
+        (begin
+            (define counter (make-generator (lambda (yield)
+                (begin
+                    (define (loop n) (begin (yield n) (loop (+ n 1))))
+                    (loop 0)))))
+            (define p (delay (+ 100 (counter))))
+            (list (force p) (force p) (promise? p) (force 5)))
         ";
 
     let result = eval(program, &mut env).unwrap();
-    assert_eq!(result, Object::Integer(5));
+    assert_eq!(
+      result,
+      Object::ListData(Rc::new(vec![
+        Object::Integer(100),
+        Object::Integer(100),
+        Object::Bool(true),
+        Object::Integer(5),
+      ]))
+    );
   }
 
   #[test]
-  fn test_cond_2() {
+  fn test_delay_force_chain() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
-            (cond ((> 1 2) 5) 
-                  ((< 1 2) 10) 
-                  (else 15)
-        )
+        (begin
+            (define (countdown n)
+                (delay-force
+                    (if (= n 0)
+                        (make-promise 0)
+                        (countdown (- n 1)))))
+            (force (countdown 20000)))
         ";
 
     let result = eval(program, &mut env).unwrap();
-    assert_eq!(result, Object::Integer(10));
+    assert_eq!(result, Object::Integer(0));
   }
 
   #[test]
-  fn test_cond_3() {
+  fn test_promise_forced_recursively() {
     let mut env = Rc::new(RefCell::new(Env::new()));
+    for program in [
+      "(begin (define p (delay (force p))) (force p))",
+      "(begin (define p (delay-force p)) (force p))",
+    ] {
+      assert_eq!(
+        eval(program, &mut env).unwrap_err().to_string(),
+        "Promise forced recursively"
+      );
+    }
+    // A promise whose computation raised is computed again.
     let program = "
-            (cond ((> 1 2) 5) 
-                  ((< 1 0) 10) 
-                  (else 15)
-            )
+        (begin
+            (define n (vector 0))
+            (define p
+                (delay
+                    (begin
+                        (vector-set! n 0 (+ (vector-ref n 0) 1))
+                        (if (= (vector-ref n 0) 1)
+                            (error \"boom\")
+                            (vector-ref n 0)))))
+            (list
+                (guard (e (#t 'caught)) (force p))
+                (force p)
+                (force p)))
         ";
-
     let result = eval(program, &mut env).unwrap();
-    assert_eq!(result, Object::Integer(15));
+    assert_eq!(result.to_string(), "(caught 2 2)");
   }
 
   #[test]
-  fn test_let_1() {
+  fn test_infinite_stream() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
         (begin
-            (let ((a 10) (b 20))
-                (list a b)
-            )
-        )
+            (define (integers-from n)
+                (stream-cons n (integers-from (+ n 1))))
+            (define (squares s)
+                (stream-cons
+                    (* (stream-car s) (stream-car s))
+                    (squares (stream-cdr s))))
+            (stream-take (squares (integers-from 1)) 5))
         ";
 
     let result = eval(program, &mut env).unwrap();
     assert_eq!(
       result,
-      Object::ListData(vec![
-        Object::Integer(10),
-        Object::Integer(20),
-      ])
+      Object::ListData(Rc::new(vec![
+        Object::Integer(1),
+        Object::Integer(4),
+        Object::Integer(9),
+        Object::Integer(16),
+        Object::Integer(25),
+      ]))
     );
   }
 
   #[test]
-  fn test_let_2() {
+  fn test_stream_range() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
-        (begin
-            (define a 100)
-            (let ((a 10) (b 20))
-                (list a b)
-            )
-            a
-        )
+        (stream-take (stream-cdr (stream-range 10 5)) 3)
         ";
 
     let result = eval(program, &mut env).unwrap();
-    assert_eq!(result, Object::Integer(100));
+    assert_eq!(
+      result,
+      Object::ListData(Rc::new(vec![
+        Object::Integer(15),
+        Object::Integer(20),
+        Object::Integer(25),
+      ]))
+    );
   }
 
   #[test]
-  fn test_let_3() {
+  fn test_range() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
-            (let ((x 2) (y 3))
-                (let ((x 7)
-                      (z (+ x y)))
-                    (* z x))) 
+        (list (range 0 4) (range 10 0 (- 0 3)) (range 5 5))
         ";
 
     let result = eval(program, &mut env).unwrap();
-    assert_eq!(result, Object::Integer(35));
+    assert_eq!(
+      result,
+      Object::ListData(Rc::new(vec![
+        Object::ListData(Rc::new(vec![
+          Object::Integer(0),
+          Object::Integer(1),
+          Object::Integer(2),
+          Object::Integer(3),
+        ])),
+        Object::ListData(Rc::new(vec![
+          Object::Integer(10),
+          Object::Integer(7),
+          Object::Integer(4),
+          Object::Integer(1),
+        ])),
+        Object::ListData(Rc::new(vec![])),
+      ]))
+    );
+    assert!(eval(
+      "(range 0 9223372036854775807)",
+      &mut env
+    )
+    .is_err());
   }
 
   #[test]
-  fn test_map() {
+  fn test_do() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
-        (begin
-            (define (map f l)
-                (if (null? l) 
-                    (list) 
-                    (cons (f (car l)) (map f (cdr l)))))
-            (map (lambda (x) (* x x)) (list 1 2 3 4 5))
-        )
+        (do ((i 0 (+ i 1))
+             (acc (list) (cons i acc)))
+            ((= i 4) acc))
         ";
 
     let result = eval(program, &mut env).unwrap();
     assert_eq!(
       result,
-      Object::ListData(vec![
+      Object::ListData(Rc::new(vec![
+        Object::Integer(3),
+        Object::Integer(2),
         Object::Integer(1),
-        Object::Integer(4),
-        Object::Integer(9),
-        Object::Integer(16),
-        Object::Integer(25),
-      ])
+        Object::Integer(0),
+      ]))
     );
   }
 
   #[test]
-  fn test_filter() {
+  fn test_while() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
         (begin
-            (define (filter f l)
-                (if (null? l) 
-                    (list) 
-                    (if (f (car l)) 
-                        (cons (car l) (filter f (cdr l))) 
-                        (filter f (cdr l)))))
-            (filter (lambda (x) (> x 2)) (list 1 2 3 4 5))
-        )
+            (define i 0)
+            (define sum 0)
+            (while (< i 5)
+                (define sum (+ sum i))
+                (define i (+ i 1)))
+            sum)
         ";
 
     let result = eval(program, &mut env).unwrap();
-    assert_eq!(
-      result,
-      Object::ListData(vec![
-        Object::Integer(3),
-        Object::Integer(4),
-        Object::Integer(5),
-      ])
-    );
+    assert_eq!(result, Object::Integer(10));
   }
 
   #[test]
-  fn test_fold_left() {
+  fn test_for_each() {
     let mut env = Rc::new(RefCell::new(Env::new()));
     let program = "
         (begin
-            (define (fold-left f acc l)
-                (if (null? l) 
-                    acc 
-                    (fold-left f (f acc (car l)) (cdr l))))
-            (fold-left (lambda (acc x) (+ acc x)) 0 (list 1 2 3 4 5))
-        )
+            (define total (make-generator (lambda (yield)
+                (begin
+                    (define (loop n) (loop (+ n (yield n))))
+                    (loop 0)))))
+            (total)
+            (for-each
+                (lambda (x y) (total (* x y)))
+                (range 1 5)
+                (list 10 20 30))
+            (total 0))
         ";
 
     let result = eval(program, &mut env).unwrap();
-    assert_eq!(result, Object::Integer(15));
+    assert_eq!(result, Object::Integer(140));
   }
 
   #[test]
-  fn test_reduce() {
+  fn test_empty_list_and_arity_errors() {
     let mut env = Rc::new(RefCell::new(Env::new()));
-    let program = "
-        (begin
-            (define (reduce f l)
-                (if (null? l) 
-                    (list) 
-                    (if (null? (cdr l)) 
-                        (car l) 
-                        (f (car l) (reduce f (cdr l))))))
-            (reduce (lambda (x y) (+ x y)) (list 1 2 3 4 5))
-        )
-        ";
+    for program in [
+      "(car (list))",
+      "(cdr (list))",
+      "(car)",
+      "(cdr (list 1) (list 2))",
+      "(length)",
+      "(null?)",
+      "(lambda)",
+      "(lambda (x))",
+      "(stream-range 9223372036854775807)",
+      "(list 1.2.3)",
+    ] {
+      assert!(
+        eval(program, &mut env).is_err(),
+        "{}",
+        program
+      );
+    }
+    let result = eval(
+      "(range 9223372036854775800 9223372036854775807 3)",
+      &mut env,
+    )
+    .unwrap();
+    assert_eq!(
+      result,
+      Object::ListData(Rc::new(vec![
+        Object::Integer(9223372036854775800),
+        Object::Integer(9223372036854775803),
+        Object::Integer(9223372036854775806),
+      ]))
+    );
+  }
 
-    let result = eval(program, &mut env).unwrap();
-    assert_eq!(result, Object::Integer(15));
+  // Random programs made of builtins and awkward arguments
+  // must come back as values or errors, never panic.
+  #[test]
+  fn test_random_programs_do_not_panic() {
+    // Everything the lexer knows except the loops, which can
+    // run forever, and print, which would flood the output.
+    let skipped = ["while", "do", "print"];
+    let mut heads: Vec<&str> = Vec::new();
+    for head in lexer::KEYWORDS
+      .into_iter()
+      .chain(lexer::BUILTINS)
+      .chain(OPERATORS)
+    {
+      if !skipped.contains(&head) && !heads.contains(&head)
+      {
+        heads.push(head);
+      }
+    }
+    let atoms = [
+      "0",
+      "1",
+      "2.5",
+      "1/2",
+      "99999999999999999999",
+      "(- 0 1)",
+      "\"\"",
+      "\"ab c\"",
+      "#\\a",
+      "#t",
+      "#f",
+      "x",
+      "'a",
+      "'()",
+      "(list)",
+      "(list 1 2)",
+      "(lambda (x) (list x))",
+      "(delay 1)",
+      "(stream-range 0)",
+      "#(1 (2))",
+      "{a 1 (b) #(2)}",
+      "#u8(0 255)",
+      "'big",
+    ];
+
+    let mut seed: u64 = 0x9e3779b97f4a7c15;
+    let mut random = move |n: usize| {
+      seed ^= seed << 13;
+      seed ^= seed >> 7;
+      seed ^= seed << 17;
+      (seed % n as u64) as usize
+    };
+    fn form(
+      random: &mut dyn FnMut(usize) -> usize,
+      heads: &[&str],
+      atoms: &[&str],
+      depth: usize,
+    ) -> String {
+      if depth == 0 || random(3) == 0 {
+        return atoms[random(atoms.len())].to_string();
+      }
+      let mut parts =
+        vec![heads[random(heads.len())].to_string()];
+      for _ in 0..random(4) {
+        parts.push(form(random, heads, atoms, depth - 1));
+      }
+      format!("({})", parts.join(" "))
+    }
+
+    for _ in 0..20000 {
+      let program = form(&mut random, &heads, &atoms, 4);
+      let mut env =
+        Rc::new(RefCell::new(Env::with_quota(1 << 20)));
+      let _ = eval(&program, &mut env);
+    }
   }
 }
@@ -0,0 +1,424 @@
+use crate::env::*;
+use crate::eval::{Continuation, Generator, Promise};
+use crate::hashtables::HashTable;
+use crate::macros::Macro;
+use crate::object::*;
+use crate::records::Record;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+// Closures hold an Rc to the environment they were defined
+// in, and that environment usually holds the closure, so
+// plain reference counting never frees them. Every
+// environment created by the evaluator is registered here
+// and `gc` breaks the cycles that are no longer reachable
+// from the host.
+thread_local! {
+  static ENVS: RefCell<Registry> =
+    const { RefCell::new(Registry::new()) };
+}
+
+// Most environments die as soon as the call or let that made
+// them returns, so the dead entries are dropped whenever the
+// registry has doubled since they were last dropped. A long
+// loop then needs room for the environments alive at once,
+// not for every one it made.
+struct Registry {
+  envs: Vec<Weak<RefCell<Env>>>,
+  pruned_at: usize,
+}
+
+impl Registry {
+  const fn new() -> Registry {
+    Registry {
+      envs: Vec::new(),
+      pruned_at: 0,
+    }
+  }
+
+  fn prune(&mut self) {
+    self.envs.retain(|env| env.strong_count() > 0);
+    self.pruned_at = self.envs.len();
+  }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GcStats {
+  pub tracked: usize,
+  pub collected: usize,
+}
+
+pub fn track(env: &Rc<RefCell<Env>>) {
+  ENVS.with(|registry| {
+    let mut registry = registry.borrow_mut();
+    registry.envs.push(Rc::downgrade(env));
+    if registry.envs.len() >= 2 * registry.pruned_at.max(64)
+    {
+      registry.prune();
+    }
+  });
+}
+
+// Something the collector follows references through: an
+// environment, or a mutable or suspended value that can close
+// a cycle of them.
+#[derive(Clone)]
+pub enum Node {
+  Env(Rc<RefCell<Env>>),
+  Vector(Rc<RefCell<Vec<Object>>>),
+  HashTable(Rc<RefCell<HashTable>>),
+  Record(Rc<Record>),
+  Generator(Rc<Generator>),
+  Promise(Rc<Promise>),
+  Continuation(Rc<Continuation>),
+}
+
+impl Node {
+  fn address(&self) -> *const () {
+    match self {
+      Node::Env(env) => Rc::as_ptr(env) as *const (),
+      Node::Vector(items) => Rc::as_ptr(items) as *const (),
+      Node::HashTable(t) => Rc::as_ptr(t) as *const (),
+      Node::Record(r) => Rc::as_ptr(r) as *const (),
+      Node::Generator(g) => Rc::as_ptr(g) as *const (),
+      Node::Promise(p) => Rc::as_ptr(p) as *const (),
+      Node::Continuation(k) => Rc::as_ptr(k) as *const (),
+    }
+  }
+
+  fn strong_count(&self) -> usize {
+    match self {
+      Node::Env(env) => Rc::strong_count(env),
+      Node::Vector(items) => Rc::strong_count(items),
+      Node::HashTable(t) => Rc::strong_count(t),
+      Node::Record(r) => Rc::strong_count(r),
+      Node::Generator(g) => Rc::strong_count(g),
+      Node::Promise(p) => Rc::strong_count(p),
+      Node::Continuation(k) => Rc::strong_count(k),
+    }
+  }
+
+  // Calls f with the nodes this one refers to. Returns false
+  // when it is being updated right now, and so is in use.
+  fn visit(&self, f: &mut dyn FnMut(Node)) -> bool {
+    match self {
+      Node::Env(env) => match env.try_borrow() {
+        Ok(env) => visit_env(&env, f),
+        Err(_) => return false,
+      },
+      Node::Vector(items) => match items.try_borrow() {
+        Ok(items) => {
+          items.iter().for_each(|obj| visit_obj(obj, f))
+        }
+        Err(_) => return false,
+      },
+      Node::HashTable(t) => match t.try_borrow() {
+        Ok(t) => {
+          for (k, v) in t.entries() {
+            visit_obj(k, f);
+            visit_obj(v, f);
+          }
+        }
+        Err(_) => return false,
+      },
+      Node::Record(r) => match r.fields.try_borrow() {
+        Ok(fields) => {
+          fields.iter().for_each(|obj| visit_obj(obj, f))
+        }
+        Err(_) => return false,
+      },
+      Node::Generator(g) => return g.visit(f),
+      Node::Promise(p) => return p.visit(f),
+      Node::Continuation(k) => k.visit(f),
+    }
+    true
+  }
+
+  // Drops what an unreachable node holds, which breaks the
+  // cycles it is part of. Continuations are never changed
+  // once made, so a cycle through one is broken elsewhere.
+  fn clear(&self) {
+    match self {
+      Node::Env(env) => {
+        drop(std::mem::take(&mut *env.borrow_mut()))
+      }
+      Node::Vector(items) => drop(items.take()),
+      Node::HashTable(t) => drop(t.take()),
+      Node::Record(r) => drop(r.fields.take()),
+      Node::Generator(g) => g.clear(),
+      Node::Promise(p) => p.clear(),
+      Node::Continuation(_) => {}
+    }
+  }
+}
+
+pub fn visit_list(
+  list: &Rc<Vec<Object>>,
+  f: &mut dyn FnMut(Node),
+) {
+  // A list shared with something outside the environment
+  // may be kept alive from elsewhere, so only look inside
+  // lists owned outright.
+  if Rc::strong_count(list) == 1 {
+    for obj in list.iter() {
+      visit_obj(obj, f);
+    }
+  }
+}
+
+pub fn visit_obj(obj: &Object, f: &mut dyn FnMut(Node)) {
+  match obj {
+    Object::Lambda(_, body, env) => {
+      f(Node::Env(env.clone()));
+      visit_list(body, f);
+    }
+    Object::Builtin(_, env) => f(Node::Env(env.clone())),
+    Object::List(list) => visit_list(list, f),
+    Object::ListData(list) => {
+      for obj in list.iter() {
+        visit_obj(obj, f);
+      }
+    }
+    Object::Error(_, list) => {
+      for obj in list.iter() {
+        visit_obj(obj, f);
+      }
+    }
+    Object::Vector(items) => f(Node::Vector(items.clone())),
+    Object::HashTable(t) => f(Node::HashTable(t.clone())),
+    Object::Record(r) => f(Node::Record(r.clone())),
+    Object::Generator(g) | Object::Yield(g) => {
+      f(Node::Generator(g.clone()))
+    }
+    Object::Promise(p) => f(Node::Promise(p.clone())),
+    Object::Continuation(k) => {
+      f(Node::Continuation(k.clone()))
+    }
+    // The nodes of persistent maps and vectors are shared
+    // between versions without a count that can be read, so
+    // what they hold can't be told apart from what the host
+    // holds. The environments in them are always kept, and a
+    // cycle through one is never collected.
+    _ => {}
+  }
+}
+
+fn visit_env(env: &Env, f: &mut dyn FnMut(Node)) {
+  if let Some(parent) = env.parent() {
+    f(Node::Env(parent.clone()));
+  }
+  for obj in env.values() {
+    visit_obj(obj, f);
+  }
+  for m in env.macros() {
+    if let (1, Macro::Procedural(_, _, _, env)) =
+      (Rc::strong_count(m), m.as_ref())
+    {
+      f(Node::Env(env.clone()));
+    }
+  }
+}
+
+// Trial deletion: a node whose strong count is fully
+// explained by references from other nodes is only kept
+// alive by cycles, unless it can be reached from one that
+// the host still holds. The nodes are the tracked
+// environments and everything found from them.
+pub fn gc() -> GcStats {
+  let mut nodes: Vec<Node> = ENVS.with(|registry| {
+    let mut registry = registry.borrow_mut();
+    registry.prune();
+    registry
+      .envs
+      .iter()
+      .filter_map(|env| env.upgrade())
+      .map(Node::Env)
+      .collect()
+  });
+  let tracked = nodes.len();
+
+  let mut index: HashMap<*const (), usize> = nodes
+    .iter()
+    .enumerate()
+    .map(|(i, node)| (node.address(), i))
+    .collect();
+  let mut internal = vec![0; nodes.len()];
+  let mut roots = vec![false; nodes.len()];
+  let mut edges: Vec<Vec<usize>> = Vec::new();
+  let mut i = 0;
+  while i < nodes.len() {
+    let mut found = Vec::new();
+    // Nodes being updated right now are in use.
+    if !nodes[i].visit(&mut |node| found.push(node)) {
+      roots[i] = true;
+    }
+    let mut to = Vec::new();
+    for node in found {
+      let j =
+        *index.entry(node.address()).or_insert_with(|| {
+          nodes.push(node);
+          internal.push(0);
+          roots.push(false);
+          nodes.len() - 1
+        });
+      internal[j] += 1;
+      to.push(j);
+    }
+    edges.push(to);
+    i += 1;
+  }
+
+  let mut stack = Vec::new();
+  for (i, node) in nodes.iter().enumerate() {
+    // One of the strong references is the one taken above.
+    if roots[i] || node.strong_count() - 1 > internal[i] {
+      roots[i] = true;
+      stack.push(i);
+    }
+  }
+
+  let mut reachable = roots;
+  while let Some(i) = stack.pop() {
+    for &j in edges[i].iter() {
+      if !reachable[j] {
+        reachable[j] = true;
+        stack.push(j);
+      }
+    }
+  }
+
+  // Everything is cleared while the nodes are still held, so
+  // nothing is freed until the last one is done.
+  let mut collected = 0;
+  for (i, node) in nodes.iter().enumerate() {
+    if !reachable[i] {
+      node.clear();
+      collected += matches!(node, Node::Env(_)) as usize;
+    }
+  }
+
+  GcStats { tracked, collected }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::eval::eval;
+  use crate::symbols::Symbol;
+
+  #[test]
+  fn test_recursive_closure_is_collected() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    track(&env);
+    let program = "
+        (begin
+            (define (fact n) (if (< n 1) 1 (* n (fact (- n 1)))))
+            (fact 5)
+        )
+        ";
+    eval(program, &mut env).unwrap();
+
+    let root = Rc::downgrade(&env);
+    drop(env);
+    assert!(root.upgrade().is_some());
+
+    let stats = gc();
+    assert_eq!(stats.collected, 2);
+    assert!(root.upgrade().is_none());
+    assert_eq!(gc().tracked, 0);
+  }
+
+  #[test]
+  fn test_live_closure_is_kept() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    track(&env);
+    eval("(define (sqr r) (* r r))", &mut env).unwrap();
+
+    assert_eq!(gc().collected, 0);
+    let result = eval("(sqr 4)", &mut env).unwrap();
+    assert_eq!(result, Object::Integer(16));
+  }
+
+  #[test]
+  fn test_cycles_through_values_are_collected() {
+    for program in [
+      "(begin
+           (define g (make-generator (lambda (yield) (yield g))))
+           (g))",
+      "(define p (delay (list p)))",
+      "(define k (call/cc (lambda (c) (list c))))",
+      "(begin
+           (define h (make-hash-table))
+           (hash-table-set! h 1 (lambda (x) (list x h))))",
+    ] {
+      let mut env = Rc::new(RefCell::new(Env::new()));
+      track(&env);
+      eval(program, &mut env).unwrap();
+
+      let root = Rc::downgrade(&env);
+      drop(env);
+      gc();
+      assert!(root.upgrade().is_none(), "{}", program);
+    }
+  }
+
+  #[test]
+  fn test_vector_holding_itself_is_cleared() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    track(&env);
+    // f keeps the environment alive until it is collected.
+    for program in [
+      "(define (f x) (list x))",
+      "(define v (make-vector 1 0))",
+      "(vector-set! v 0 v)",
+    ] {
+      eval(program, &mut env).unwrap();
+    }
+    let v = env.borrow().get(Symbol::new("v"));
+    let items = match v {
+      Some(Object::Vector(items)) => Rc::downgrade(&items),
+      obj => panic!("{:?}", obj),
+    };
+    drop(env);
+
+    assert!(items.upgrade().is_some());
+    gc();
+    assert!(items.upgrade().is_none());
+  }
+
+  // A known leak: persistent maps and vectors are not looked
+  // inside, so a closure kept in one keeps its environment.
+  #[test]
+  fn test_cycle_through_persistent_map_is_kept() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    track(&env);
+    eval(
+      "(define m (persistent-map 'f (lambda (x) (list x m))))",
+      &mut env,
+    )
+    .unwrap();
+
+    let root = Rc::downgrade(&env);
+    drop(env);
+    gc();
+    assert!(root.upgrade().is_some());
+  }
+
+  #[test]
+  fn test_registry_stays_bounded() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define (loop n acc)
+                (if (= n 0) acc (loop (- n 1) (+ acc 1))))
+            (loop 100000 0))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(100000));
+    let registered =
+      ENVS.with(|registry| registry.borrow().envs.len());
+    assert!(registered < 1000, "{}", registered);
+  }
+}
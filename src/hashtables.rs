@@ -0,0 +1,372 @@
+use crate::env::*;
+use crate::equality::is_equal;
+use crate::eval::{charge_list, EvalError};
+use crate::object::*;
+use crate::strings::check_args;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::rc::Rc;
+
+// Keys are compared with equal?, so they are hashed by what
+// equal? looks at: the elements of strings, lists, vectors
+// and bytevectors, and the identity of everything else that
+// is not a plain value. As in R7RS and SRFI 69, what happens
+// when a vector or bytevector is changed while it is a key
+// is undefined. Its entry stays where the old elements put
+// it, and may not be found again. Entries are kept in a Vec
+// so that keys come back in a stable order.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HashTable {
+  entries: Vec<(Object, Object)>,
+  slots: HashMap<u64, Vec<usize>>,
+}
+
+// Hashing stops going into elements after this many, so that
+// a key holding itself is hashed in finite time. Elements are
+// counted in the order they are reached, which is the same
+// for keys that are equal?.
+const HASH_BUDGET: usize = 256;
+
+fn hash_into(
+  obj: &Object,
+  state: &mut DefaultHasher,
+  budget: &mut usize,
+) {
+  std::mem::discriminant(obj).hash(state);
+  match obj {
+    Object::Void => {}
+    Object::Integer(n) => n.hash(state),
+    Object::BigInt(n) => n.hash(state),
+    Object::Rational(r) => r.hash(state),
+    Object::Float(n) => n.to_bits().hash(state),
+    Object::Bool(b) => b.hash(state),
+    Object::Char(c) => c.hash(state),
+    Object::Keyword(s) | Object::BinaryOp(s) => {
+      s.hash(state)
+    }
+    Object::String(s) => s.hash(state),
+    Object::Symbol(s) => s.hash(state),
+    Object::Local(s, depth, index) => {
+      (s, depth, index).hash(state)
+    }
+    Object::ListData(list) | Object::List(list) => {
+      hash_items(list.len(), list.iter(), state, budget)
+    }
+    Object::Error(_, list) => {
+      hash_items(list.len(), list.iter(), state, budget)
+    }
+    Object::Vector(items) => {
+      let items = items.borrow();
+      hash_items(items.len(), items.iter(), state, budget)
+    }
+    Object::PersistentVector(v) => {
+      hash_items(v.len(), v.iter(), state, budget)
+    }
+    Object::Bytevector(b) => b.borrow().hash(state),
+    Object::Record(r) => Rc::as_ptr(r).hash(state),
+    Object::RecordType(rtd) => Rc::as_ptr(rtd).hash(state),
+    Object::RecordProcedure(p) => Rc::as_ptr(p).hash(state),
+    Object::Builtin(op, _) => op.to_string().hash(state),
+    // Equal maps can keep their entries in different orders,
+    // so the entries are combined in a way that ignores it.
+    Object::PersistentMap(m) => {
+      m.size().hash(state);
+      m.iter()
+        .map_while(|(k, v)| {
+          *budget = budget.checked_sub(1)?;
+          let k = hash_at(&k.0, budget);
+          Some(k ^ hash_at(v, budget).rotate_left(1))
+        })
+        .fold(0u64, u64::wrapping_add)
+        .hash(state)
+    }
+    Object::Lambda(_, body, _) => {
+      Rc::as_ptr(body).hash(state)
+    }
+    Object::Continuation(k) => Rc::as_ptr(k).hash(state),
+    Object::Generator(g) | Object::Yield(g) => {
+      Rc::as_ptr(g).hash(state)
+    }
+    Object::Promise(p) => Rc::as_ptr(p).hash(state),
+    Object::HashTable(t) => Rc::as_ptr(t).hash(state),
+  }
+}
+
+fn hash_items<'a>(
+  len: usize,
+  items: impl Iterator<Item = &'a Object>,
+  state: &mut DefaultHasher,
+  budget: &mut usize,
+) {
+  len.hash(state);
+  for obj in items {
+    match budget.checked_sub(1) {
+      Some(left) => *budget = left,
+      None => return,
+    }
+    hash_into(obj, state, budget);
+  }
+}
+
+fn hash_at(obj: &Object, budget: &mut usize) -> u64 {
+  let mut state = DefaultHasher::new();
+  hash_into(obj, &mut state, budget);
+  state.finish()
+}
+
+pub fn hash_key(key: &Object) -> u64 {
+  let mut budget = HASH_BUDGET;
+  hash_at(key, &mut budget)
+}
+
+impl HashTable {
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn entries(&self) -> &[(Object, Object)] {
+    &self.entries
+  }
+
+  fn find(&self, key: &Object, hash: u64) -> Option<usize> {
+    self.slots.get(&hash).and_then(|slot| {
+      slot
+        .iter()
+        .copied()
+        .find(|&i| is_equal(&self.entries[i].0, key))
+    })
+  }
+
+  pub fn get(&self, key: &Object) -> Option<&Object> {
+    let i = self.find(key, hash_key(key))?;
+    Some(&self.entries[i].1)
+  }
+
+  // Returns whether the key is new.
+  pub fn insert(
+    &mut self,
+    key: Object,
+    value: Object,
+  ) -> bool {
+    let hash = hash_key(&key);
+    if let Some(i) = self.find(&key, hash) {
+      self.entries[i].1 = value;
+      return false;
+    }
+    self
+      .slots
+      .entry(hash)
+      .or_default()
+      .push(self.entries.len());
+    self.entries.push((key, value));
+    true
+  }
+
+  // The last entry is moved into the place of the removed
+  // one.
+  pub fn remove(&mut self, key: &Object) -> Option<Object> {
+    let hash = hash_key(key);
+    let i = self.find(key, hash)?;
+    self.unlink(hash, i);
+    let last = self.entries.len() - 1;
+    if i != last {
+      let moved = hash_key(&self.entries[last].0);
+      self.unlink(moved, last);
+      self.slots.entry(moved).or_default().push(i);
+    }
+    Some(self.entries.swap_remove(i).1)
+  }
+
+  fn unlink(&mut self, hash: u64, i: usize) {
+    if let Some(slot) = self.slots.get_mut(&hash) {
+      slot.retain(|&j| j != i);
+      if slot.is_empty() {
+        self.slots.remove(&hash);
+      }
+    }
+  }
+}
+
+pub fn new_hash_table(table: HashTable) -> Object {
+  Object::HashTable(Rc::new(RefCell::new(table)))
+}
+
+fn table_arg(
+  obj: &Object,
+) -> Result<&Rc<RefCell<HashTable>>, EvalError> {
+  match obj {
+    Object::HashTable(t) => Ok(t),
+    _ => Err(format!("{} is not a hash table", obj).into()),
+  }
+}
+
+pub fn eval_make_hash_table(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 0, 0)?;
+  Ok(new_hash_table(HashTable::default()))
+}
+
+pub fn eval_is_hash_table(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 1)?;
+  Ok(Object::Bool(matches!(list[1], Object::HashTable(_))))
+}
+
+pub fn eval_hash_table_set(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 3, 3)?;
+  let mut t = table_arg(&list[1])?.borrow_mut();
+  if t.insert(list[2].clone(), list[3].clone()) {
+    charge_list(2, env)?;
+  }
+  Ok(Object::Void)
+}
+
+// Without a default, a missing key is an error.
+pub fn eval_hash_table_ref(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 2, 3)?;
+  let t = table_arg(&list[1])?.borrow();
+  match (t.get(&list[2]), list.get(3)) {
+    (Some(val), _) => Ok(val.clone()),
+    (None, Some(default)) => Ok(default.clone()),
+    (None, None) => {
+      Err(format!("Key {} not found", list[2]).into())
+    }
+  }
+}
+
+pub fn eval_hash_table_delete(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 2, 2)?;
+  table_arg(&list[1])?.borrow_mut().remove(&list[2]);
+  Ok(Object::Void)
+}
+
+pub fn eval_hash_table_contains(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 2, 2)?;
+  let t = table_arg(&list[1])?.borrow();
+  Ok(Object::Bool(t.get(&list[2]).is_some()))
+}
+
+pub fn eval_hash_table_count(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 1)?;
+  let t = table_arg(&list[1])?.borrow();
+  Ok(Object::Integer(t.len() as i64))
+}
+
+// hash-table-keys, hash-table-values and hash-table->alist,
+// which make an item of the list from each entry.
+pub fn eval_hash_table_list(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+  item: fn(&Object, &Object) -> Object,
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 1)?;
+  let t = table_arg(&list[1])?.borrow();
+  charge_list(t.len(), env)?;
+  let items = t.entries().iter().map(|(k, v)| item(k, v));
+  Ok(Object::ListData(Rc::new(items.collect())))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::eval::eval;
+
+  #[test]
+  fn test_hash_table_procedures() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define t (make-hash-table))
+            (hash-table-set! t \"a\" 1)
+            (hash-table-set! t (list 1 2) 2)
+            (hash-table-set! t 'c 3)
+            (hash-table-set! t \"a\" 4)
+            (hash-table-delete! t 'c)
+            (define total (make-vector 1 0))
+            (hash-table-walk t
+                (lambda (k v)
+                    (vector-set! total 0 (+ (vector-ref total 0) v))))
+            (list
+                (hash-table-ref t \"a\")
+                (hash-table-ref t (list 1 2))
+                (hash-table-ref t 'c 0)
+                (hash-table-contains? t 'c)
+                (hash-table-count t)
+                (hash-table-keys t)
+                (hash-table->alist {x 1})
+                (hash-table-ref {(1 2) #(3)} '(1 2))
+                (vector-ref total 0)))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result.to_string(),
+      "(4 2 0 false 2 (a (1 2)) ((x 1)) #(3) 6)"
+    );
+    assert!(
+      eval("(hash-table-ref {} 1)", &mut env).is_err()
+    );
+    assert!(
+      eval("(hash-table-count {1})", &mut env).is_err()
+    );
+  }
+
+  #[test]
+  fn test_vector_keys() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define t (make-hash-table))
+            (define v (vector 1 (vector 2)))
+            (define w (vector 1))
+            (vector-set! w 0 w)
+            (hash-table-set! t v 1)
+            (hash-table-set! t (bytevector 1 2) 2)
+            (hash-table-set! t w 3)
+            (list
+                (hash-table-ref t (vector 1 (vector 2)) 0)
+                (hash-table-ref t (bytevector 1 2) 0)
+                (hash-table-ref t (vector (vector w)) 0)
+                (hash-table-ref t (vector 1 2) 0)))
+        ";
+    assert_eq!(
+      eval(program, &mut env).unwrap().to_string(),
+      "(1 2 3 0)"
+    );
+  }
+
+  #[test]
+  fn test_remove_keeps_slots() {
+    let mut t = HashTable::default();
+    for n in 0..100 {
+      t.insert(Object::Integer(n), Object::Integer(n * n));
+    }
+    for n in (0..100).step_by(3) {
+      assert!(t.remove(&Object::Integer(n)).is_some());
+    }
+    assert_eq!(t.len(), 66);
+    for n in 0..100 {
+      let expected =
+        (n % 3 != 0).then_some(Object::Integer(n * n));
+      assert_eq!(
+        t.get(&Object::Integer(n)),
+        expected.as_ref()
+      );
+    }
+    assert_eq!(t.get(&Object::Float(1.0)), None);
+  }
+}
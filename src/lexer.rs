@@ -1,18 +1,258 @@
+use num_bigint::BigInt;
+use num_rational::BigRational;
 use std::collections::HashSet;
 use std::error::Error;
 use std::str::Chars;
 use std::{fmt, vec};
 
+// Special forms, and the procedures that were reserved from
+// the start, are read as keywords.
+pub const KEYWORDS: [&str; 32] = [
+  "define",
+  "list",
+  "print",
+  "lambda",
+  "range",
+  "cons",
+  "car",
+  "cdr",
+  "length",
+  "null?",
+  "begin",
+  "let",
+  "if",
+  "else",
+  "cond",
+  "define-syntax",
+  "let-syntax",
+  "syntax-rules",
+  "quote",
+  "quasiquote",
+  "unquote",
+  "unquote-splicing",
+  "defmacro",
+  "guard",
+  "reset",
+  "shift",
+  "delay",
+  "delay-force",
+  "stream-cons",
+  "do",
+  "while",
+  "define-record-type",
+];
+
+// The builtin procedures. Apart from the ones in KEYWORDS
+// they are read as symbols, and only found when the program
+// doesn't bind the name itself.
+pub const BUILTINS: [&str; 184] = [
+  "list",
+  "print",
+  "range",
+  "cons",
+  "car",
+  "cdr",
+  "length",
+  "null?",
+  "gensym",
+  "macroexpand",
+  "macroexpand-1",
+  "error",
+  "raise",
+  "raise-continuable",
+  "with-exception-handler",
+  "error-object?",
+  "error-object-message",
+  "error-object-irritants",
+  "call/cc",
+  "call-with-current-continuation",
+  "dynamic-wind",
+  "make-generator",
+  "generator-done?",
+  "force",
+  "make-promise",
+  "promise?",
+  "stream-car",
+  "stream-cdr",
+  "stream-null?",
+  "stream-pair?",
+  "stream-take",
+  "stream-range",
+  "for-each",
+  "char?",
+  "char->integer",
+  "integer->char",
+  "char-upcase",
+  "char-downcase",
+  "char-foldcase",
+  "char-alphabetic?",
+  "char-numeric?",
+  "char-whitespace?",
+  "char-upper-case?",
+  "char-lower-case?",
+  "digit-value",
+  "char=?",
+  "char<?",
+  "char>?",
+  "char<=?",
+  "char>=?",
+  "char-ci=?",
+  "char-ci<?",
+  "char-ci>?",
+  "char-ci<=?",
+  "char-ci>=?",
+  "string?",
+  "string-length",
+  "string-ref",
+  "substring",
+  "string-append",
+  "string-split",
+  "string-join",
+  "string-index",
+  "string-contains",
+  "string-upcase",
+  "string-downcase",
+  "string-trim",
+  "string-trim-left",
+  "string-trim-right",
+  "string-replace",
+  "string->list",
+  "list->string",
+  "string->number",
+  "number->string",
+  "string->symbol",
+  "symbol->string",
+  "number?",
+  "complex?",
+  "real?",
+  "rational?",
+  "integer?",
+  "exact-integer?",
+  "exact?",
+  "inexact?",
+  "nan?",
+  "finite?",
+  "infinite?",
+  "zero?",
+  "positive?",
+  "negative?",
+  "odd?",
+  "even?",
+  "exact",
+  "inexact",
+  "exact->inexact",
+  "inexact->exact",
+  "floor",
+  "ceiling",
+  "round",
+  "truncate",
+  "abs",
+  "numerator",
+  "denominator",
+  "sqrt",
+  "exp",
+  "log",
+  "sin",
+  "cos",
+  "tan",
+  "asin",
+  "acos",
+  "atan",
+  "quotient",
+  "remainder",
+  "modulo",
+  "min",
+  "max",
+  "gcd",
+  "lcm",
+  "expt",
+  "eq?",
+  "eqv?",
+  "equal?",
+  "map",
+  "filter",
+  "reduce",
+  "fold-left",
+  "fold-right",
+  "append",
+  "reverse",
+  "list-ref",
+  "list-tail",
+  "member",
+  "assoc",
+  "last",
+  "take",
+  "drop",
+  "sort",
+  "iota",
+  "make-vector",
+  "vector",
+  "vector?",
+  "vector-ref",
+  "vector-set!",
+  "vector-length",
+  "vector-map",
+  "vector->list",
+  "list->vector",
+  "make-hash-table",
+  "hash-table?",
+  "hash-table-set!",
+  "hash-table-ref",
+  "hash-table-delete!",
+  "hash-table-contains?",
+  "hash-table-count",
+  "hash-table-keys",
+  "hash-table-values",
+  "hash-table->alist",
+  "hash-table-walk",
+  "persistent-map",
+  "persistent-vector",
+  "persistent-map?",
+  "persistent-vector?",
+  "dissoc",
+  "conj",
+  "get",
+  "get-in",
+  "assoc-in",
+  "count",
+  "persistent->list",
+  "bytevector",
+  "make-bytevector",
+  "bytevector?",
+  "bytevector-length",
+  "bytevector-u8-ref",
+  "bytevector-u8-set!",
+  "bytevector-copy",
+  "bytevector-append",
+  "utf8->string",
+  "string->utf8",
+  "bytevector-uint-ref",
+  "bytevector-sint-ref",
+  "bytevector-uint-set!",
+  "bytevector-sint-set!",
+];
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
   Integer(i64),
+  BigInt(BigInt),
+  Rational(BigRational),
   Symbol(String),
   LParen,
   RParen,
+  VectorStart,
+  BytevectorStart,
+  LBrace,
+  RBrace,
   Float(f64),
   String(String),
   BinaryOp(String),
   Keyword(String),
+  Char(char),
+  Quote,
+  Quasiquote,
+  Unquote,
+  UnquoteSplicing,
 }
 
 #[derive(Debug)]
@@ -28,6 +268,23 @@ impl fmt::Display for TokenError {
   }
 }
 
+fn read_rational(
+  numer: &str,
+  denom: &str,
+) -> Result<BigRational, TokenError> {
+  let invalid = || TokenError {
+    err: format!("Invalid rational {}/{}", numer, denom),
+  };
+  let numer: BigInt =
+    numer.parse().map_err(|_| invalid())?;
+  let denom: BigInt =
+    denom.parse().map_err(|_| invalid())?;
+  if denom == BigInt::from(0) {
+    return Err(invalid());
+  }
+  Ok(BigRational::new(numer, denom))
+}
+
 struct Tokenizer<'a> {
   input: Chars<'a>,
   current_char: Option<char>,
@@ -40,13 +297,8 @@ impl<'a> Tokenizer<'a> {
     let mut chars = input.chars();
     let current_char = chars.next();
 
-    let keywords: HashSet<&str> = vec![
-      "define", "list", "print", "lambda", "range", "cons",
-      "car", "cdr", "length", "null?", "begin", "let",
-      "if", "else", "cond",
-    ]
-    .into_iter()
-    .collect::<HashSet<&str>>();
+    let keywords: HashSet<&str> =
+      KEYWORDS.into_iter().collect();
 
     let binary_ops: HashSet<char> = vec![
       '+', '-', '*', '/', '%', '<', '>', '=', '|', '&',
@@ -82,7 +334,10 @@ impl<'a> Tokenizer<'a> {
       if c.is_whitespace()
         || c == '('
         || c == ')'
+        || c == '{'
+        || c == '}'
         || c == '\''
+        || c == ','
       {
         break;
       }
@@ -92,10 +347,18 @@ impl<'a> Tokenizer<'a> {
     symbol
   }
 
+  // A slash followed by a digit continues the number as a
+  // rational, like 1/3.
   fn read_number(&mut self) -> String {
     let mut number = String::new();
     while let Some(c) = self.current_char {
-      if !c.is_numeric() && c != '.' {
+      let slash = c == '/'
+        && self
+          .input
+          .clone()
+          .next()
+          .is_some_and(|d| d.is_ascii_digit());
+      if !c.is_numeric() && c != '.' && !slash {
         break;
       }
       number.push(c);
@@ -104,6 +367,46 @@ impl<'a> Tokenizer<'a> {
     number
   }
 
+  // The character after #\ is taken as it is, so that #\(
+  // and #\  work, anything longer is a character name.
+  fn read_char(&mut self) -> Result<char, TokenError> {
+    let mut name = String::new();
+    if let Some(c) = self.current_char {
+      name.push(c);
+      self.advance();
+    }
+    while let Some(c) = self.current_char {
+      if !c.is_alphanumeric() {
+        break;
+      }
+      name.push(c);
+      self.advance();
+    }
+
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+      return Ok(c);
+    }
+    let c = match name.as_str() {
+      "space" => Some(' '),
+      "newline" => Some('\n'),
+      "tab" => Some('\t'),
+      "return" => Some('\r'),
+      "null" => Some('\0'),
+      "alarm" => Some('\u{7}'),
+      "backspace" => Some('\u{8}'),
+      "delete" => Some('\u{7f}'),
+      "escape" => Some('\u{1b}'),
+      _ => name
+        .strip_prefix('x')
+        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+        .and_then(char::from_u32),
+    };
+    c.ok_or_else(|| TokenError {
+      err: format!("Unknown character #\\{}", name),
+    })
+  }
+
   fn read_string(&mut self) -> String {
     let mut string = String::new();
     self.advance(); // Skip the opening quote
@@ -118,10 +421,16 @@ impl<'a> Tokenizer<'a> {
     string
   }
 
-  pub fn next_token(&mut self) -> Option<Token> {
+  pub fn next_token(
+    &mut self,
+  ) -> Result<Option<Token>, TokenError> {
     self.eat_whitespace();
 
-    match self.current_char? {
+    let c = match self.current_char {
+      Some(c) => c,
+      None => return Ok(None),
+    };
+    let token = match c {
       '(' => {
         self.advance();
         Some(Token::LParen)
@@ -130,17 +439,77 @@ impl<'a> Tokenizer<'a> {
         self.advance();
         Some(Token::RParen)
       }
+      '{' => {
+        self.advance();
+        Some(Token::LBrace)
+      }
+      '}' => {
+        self.advance();
+        Some(Token::RBrace)
+      }
+      '\'' => {
+        self.advance();
+        Some(Token::Quote)
+      }
+      '`' => {
+        self.advance();
+        Some(Token::Quasiquote)
+      }
+      ',' => {
+        if self.advance() == Some('@') {
+          self.advance();
+          Some(Token::UnquoteSplicing)
+        } else {
+          Some(Token::Unquote)
+        }
+      }
       '"' => Some(Token::String(self.read_string())),
+      '#' => {
+        let next = self.advance();
+        if next == Some('\\') {
+          self.advance();
+          Some(Token::Char(self.read_char()?))
+        } else if next == Some('(') {
+          self.advance();
+          Some(Token::VectorStart)
+        } else if next == Some('u')
+          && self.input.as_str().starts_with("8(")
+        {
+          self.advance();
+          self.advance();
+          self.advance();
+          Some(Token::BytevectorStart)
+        } else {
+          Some(Token::Symbol(format!(
+            "#{}",
+            self.read_symbol()
+          )))
+        }
+      }
       c if c.is_numeric() => {
         let val = self.read_number();
-        if val.contains('.') {
-          Some(Token::Float(val.parse().unwrap()))
+        let invalid = || TokenError {
+          err: format!("Invalid number {}", val),
+        };
+        if let Some((numer, denom)) = val.split_once('/') {
+          Some(Token::Rational(read_rational(
+            numer, denom,
+          )?))
+        } else if val.contains('.') {
+          Some(Token::Float(
+            val.parse().map_err(|_| invalid())?,
+          ))
+        } else if let Ok(n) = val.parse() {
+          Some(Token::Integer(n))
         } else {
-          Some(Token::Integer(val.parse().unwrap()))
+          Some(Token::BigInt(
+            val.parse().map_err(|_| invalid())?,
+          ))
         }
       }
       c if c.is_alphabetic()
-        || self.binary_ops.contains(&c) =>
+        || self.binary_ops.contains(&c)
+        || "_.!?$:^~".contains(c) =>
       {
         let sym = self.read_symbol();
         if self.keywords.contains(sym.as_str()) {
@@ -155,7 +524,8 @@ impl<'a> Tokenizer<'a> {
         }
       }
       _ => None,
-    }
+    };
+    Ok(token)
   }
 }
 
@@ -164,7 +534,7 @@ pub fn tokenize(
 ) -> Result<Vec<Token>, TokenError> {
   let mut tokenizer = Tokenizer::new(input);
   let mut tokens = Vec::new();
-  while let Some(token) = tokenizer.next_token() {
+  while let Some(token) = tokenizer.next_token()? {
     tokens.push(token);
   }
 
@@ -190,6 +560,61 @@ mod tests {
     );
   }
 
+  #[test]
+  fn test_ellipsis_and_wildcard() {
+    let tokens = tokenize("(_ a ...)").unwrap_or(vec![]);
+    assert_eq!(
+      tokens,
+      vec![
+        Token::LParen,
+        Token::Symbol("_".to_string()),
+        Token::Symbol("a".to_string()),
+        Token::Symbol("...".to_string()),
+        Token::RParen,
+      ]
+    );
+  }
+
+  #[test]
+  fn test_quasiquote() {
+    let tokens =
+      tokenize("`(a ,b ,@c 'd)").unwrap_or(vec![]);
+    assert_eq!(
+      tokens,
+      vec![
+        Token::Quasiquote,
+        Token::LParen,
+        Token::Symbol("a".to_string()),
+        Token::Unquote,
+        Token::Symbol("b".to_string()),
+        Token::UnquoteSplicing,
+        Token::Symbol("c".to_string()),
+        Token::Quote,
+        Token::Symbol("d".to_string()),
+        Token::RParen,
+      ]
+    );
+  }
+
+  #[test]
+  fn test_chars() {
+    let tokens =
+      tokenize("(#\\a #\\( #\\space #\\x41 #t)").unwrap();
+    assert_eq!(
+      tokens,
+      vec![
+        Token::LParen,
+        Token::Char('a'),
+        Token::Char('('),
+        Token::Char(' '),
+        Token::Char('A'),
+        Token::Symbol("#t".to_string()),
+        Token::RParen,
+      ]
+    );
+    assert!(tokenize("#\\bogus").is_err());
+  }
+
   #[test]
   fn test_area_of_a_circle() {
     let program = "
@@ -1,24 +1,106 @@
+mod bytevectors;
+mod chars;
+mod compile;
 mod env;
+mod equality;
 mod eval;
+mod gc;
+mod hashtables;
 mod lexer;
+mod lists;
+mod macros;
+mod numbers;
 mod object;
 mod parser;
+mod persistent;
+mod records;
+mod strings;
+mod symbols;
+mod vectors;
 
 use object::Object;
 use std::cell::RefCell;
 use std::rc::Rc;
 
+pub use gc::{gc, GcStats};
+
 pub fn lisp_rs_eval(input: &str) -> String {
   let mut env = Rc::new(RefCell::new(env::Env::new()));
-  let val = eval::eval(input, &mut env);
+  gc::track(&env);
+  let res = result_to_string(eval::eval(input, &mut env));
+  drop(env);
+  gc::gc();
+  res
+}
+
+// Evaluate untrusted input, aborting with an error once the
+// lists, strings and environments it creates add up to more
+// than `max_bytes`.
+pub fn lisp_rs_eval_with_quota(
+  input: &str,
+  max_bytes: usize,
+) -> String {
+  let mut env =
+    Rc::new(RefCell::new(env::Env::with_quota(max_bytes)));
+  gc::track(&env);
+  let res = result_to_string(eval::eval(input, &mut env));
+  drop(env);
+  gc::gc();
+  res
+}
+
+// Evaluate input with `name` bound to a bytevector holding
+// `data`, for scripts that pick apart binary input.
+pub fn lisp_rs_eval_with_bytes(
+  input: &str,
+  name: &str,
+  data: &[u8],
+) -> String {
+  let mut env = Rc::new(RefCell::new(env::Env::new()));
+  gc::track(&env);
+  env.borrow_mut().set(
+    symbols::Symbol::new(name),
+    bytevectors::new_bytevector(data.to_vec()),
+  );
+  let res = result_to_string(eval::eval(input, &mut env));
+  drop(env);
+  gc::gc();
+  res
+}
+
+// Evaluate input that produces a bytevector and return its
+// bytes. Any other result is returned as the error, as
+// `lisp_rs_eval` would print it.
+pub fn lisp_rs_eval_to_bytes(
+  input: &str,
+) -> Result<Vec<u8>, String> {
+  let mut env = Rc::new(RefCell::new(env::Env::new()));
+  gc::track(&env);
+  let res = match eval::eval(input, &mut env) {
+    Ok(Object::Bytevector(b)) => Ok(b.borrow().clone()),
+    val => Err(result_to_string(val)),
+  };
+  drop(env);
+  gc::gc();
+  res
+}
+
+fn result_to_string(
+  val: Result<Object, eval::EvalError>,
+) -> String {
   match val {
     Ok(Object::Void) => "".to_string(),
     Ok(Object::Integer(n)) => n.to_string(),
+    Ok(Object::BigInt(n)) => n.to_string(),
+    Ok(Object::Rational(r)) => r.to_string(),
     Ok(Object::Bool(b)) => b.to_string(),
-    Ok(Object::Symbol(s)) => s.to_string(),
+    Ok(Object::Char(c)) => c.to_string(),
+    Ok(Object::Symbol(s)) | Ok(Object::Local(s, _, _)) => {
+      s.to_string()
+    }
     Ok(Object::Lambda(params, body, _)) => {
       let mut res = "Lambda(".to_string();
-      for param in params {
+      for param in params.iter() {
         res.push_str(&format!("{} ", param));
       }
       res.push(')');
@@ -53,6 +135,20 @@ pub fn lisp_rs_eval(input: &str) -> String {
     Ok(Object::Keyword(s)) => s.to_string(),
     Ok(Object::BinaryOp(s)) => s.to_string(),
     Ok(Object::Float(n)) => n.to_string(),
+    Ok(obj @ Object::Error(_, _))
+    | Ok(obj @ Object::Continuation(_))
+    | Ok(obj @ Object::Generator(_))
+    | Ok(obj @ Object::Yield(_))
+    | Ok(obj @ Object::Promise(_))
+    | Ok(obj @ Object::Vector(_))
+    | Ok(obj @ Object::HashTable(_))
+    | Ok(obj @ Object::PersistentMap(_))
+    | Ok(obj @ Object::PersistentVector(_))
+    | Ok(obj @ Object::Bytevector(_))
+    | Ok(obj @ Object::Record(_))
+    | Ok(obj @ Object::RecordType(_))
+    | Ok(obj @ Object::RecordProcedure(_))
+    | Ok(obj @ Object::Builtin(_, _)) => obj.to_string(),
     Err(e) => e.to_string(),
   }
 }
@@ -0,0 +1,396 @@
+use crate::env::*;
+use crate::equality::is_equal;
+use crate::eval::{alloc_vec, charge_list, EvalError};
+use crate::numbers::eval_number_op;
+use crate::object::*;
+use crate::strings::{check_args, index_arg};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub fn list_arg(
+  obj: &Object,
+) -> Result<&[Object], EvalError> {
+  match obj {
+    Object::ListData(list) => Ok(list),
+    _ => Err(format!("{} is not a list", obj).into()),
+  }
+}
+
+fn new_list(
+  items: Vec<Object>,
+  env: &Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  charge_list(items.len(), env)?;
+  Ok(Object::ListData(Rc::new(items)))
+}
+
+// Whether a procedure given to member, assoc or sort said
+// yes. As with filter, anything but #f counts, while if and
+// cond take nothing but a boolean.
+pub fn is_true(obj: &Object) -> bool {
+  *obj != Object::Bool(false)
+}
+
+pub fn eval_append(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  let mut items = Vec::new();
+  for obj in list[1..].iter() {
+    items.extend(list_arg(obj)?.iter().cloned());
+  }
+  new_list(items, env)
+}
+
+pub fn eval_reverse(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 1)?;
+  let items = list_arg(&list[1])?.iter().rev().cloned();
+  new_list(items.collect(), env)
+}
+
+pub fn eval_last(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 1)?;
+  match list_arg(&list[1])?.last() {
+    Some(obj) => Ok(obj.clone()),
+    None => Err("last of an empty list".to_string().into()),
+  }
+}
+
+pub fn eval_list_ref(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 2, 2)?;
+  let items = list_arg(&list[1])?;
+  let k = index_arg(&list[2])?;
+  match items.get(k) {
+    Some(obj) => Ok(obj.clone()),
+    None => Err(
+      format!(
+        "Index {} out of range for list of length {}",
+        k,
+        items.len()
+      )
+      .into(),
+    ),
+  }
+}
+
+// list-tail and drop keep the elements from k on, take the
+// ones before it.
+pub fn eval_split(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+  take: bool,
+) -> Result<Object, EvalError> {
+  check_args(list, 2, 2)?;
+  let items = list_arg(&list[1])?;
+  let k = index_arg(&list[2])?;
+  if k > items.len() {
+    return Err(
+      format!(
+        "Index {} out of range for list of length {}",
+        k,
+        items.len()
+      )
+      .into(),
+    );
+  }
+  let (front, back) = items.split_at(k);
+  let kept = if take { front } else { back };
+  new_list(kept.to_vec(), env)
+}
+
+// member returns the rest of the list from the first element
+// equal to x, assoc the first pair whose key is, or #f. With
+// a procedure to compare with they run on the machine, as
+// the procedure may yield or capture a continuation.
+pub fn eval_member(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+  assoc: bool,
+) -> Result<Object, EvalError> {
+  check_args(list, 2, 2)?;
+  let x = &list[1];
+  let items = list_arg(&list[2])?;
+  for (i, item) in items.iter().enumerate() {
+    let key = match item {
+      Object::ListData(pair)
+        if assoc && !pair.is_empty() =>
+      {
+        &pair[0]
+      }
+      _ if assoc => {
+        return Err(
+          format!("{} is not an association list", list[2])
+            .into(),
+        )
+      }
+      _ => item,
+    };
+    let found = is_equal(x, key);
+    if found && assoc {
+      return Ok(item.clone());
+    }
+    if found {
+      return new_list(items[i..].to_vec(), env);
+    }
+  }
+  Ok(Object::Bool(false))
+}
+
+// (iota count start step), start and step default to 0 and
+// 1 and can be any numbers.
+pub fn eval_iota(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 3)?;
+  let count = index_arg(&list[1])?;
+  let start =
+    list.get(2).cloned().unwrap_or(Object::Integer(0));
+  let step =
+    list.get(3).cloned().unwrap_or(Object::Integer(1));
+  charge_list(count, env)?;
+
+  let mut items = alloc_vec(count)?;
+  let mut n = start;
+  for _ in 0..count {
+    let next = eval_number_op("+", &n, &step, env)
+      .ok_or_else(|| {
+        format!("Invalid arguments for iota {} {}", n, step)
+      })??;
+    items.push(n);
+    n = next;
+  }
+  Ok(Object::ListData(Rc::new(items)))
+}
+
+// A stable bottom-up merge sort that hands out one
+// comparison at a time, so that the machine can make the
+// calls to less? like any other call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeSort {
+  items: Vec<Object>,
+  merged: Vec<Object>,
+  width: usize,
+  // The runs being merged are items[front..mid] and
+  // items[back..end].
+  front: usize,
+  mid: usize,
+  back: usize,
+  end: usize,
+}
+
+impl MergeSort {
+  pub fn new(items: Vec<Object>) -> MergeSort {
+    let len = items.len();
+    MergeSort {
+      merged: Vec::with_capacity(len),
+      items,
+      width: 1,
+      front: 0,
+      mid: len.min(1),
+      back: len.min(1),
+      end: len.min(2),
+    }
+  }
+
+  // The pair to call less? with next, or None once the items
+  // are sorted. The first of the pair is from the back run
+  // and is taken only when it is strictly less, so that
+  // equal elements keep their order.
+  pub fn next(&mut self) -> Option<(Object, Object)> {
+    let len = self.items.len();
+    loop {
+      if self.front < self.mid && self.back < self.end {
+        return Some((
+          self.items[self.back].clone(),
+          self.items[self.front].clone(),
+        ));
+      }
+      self.merged.extend_from_slice(
+        &self.items[self.front..self.mid],
+      );
+      self.merged.extend_from_slice(
+        &self.items[self.back..self.end],
+      );
+      let mut lo = self.end;
+      if lo >= len {
+        self.items = std::mem::replace(
+          &mut self.merged,
+          Vec::with_capacity(len),
+        );
+        self.width *= 2;
+        if self.width >= len {
+          return None;
+        }
+        lo = 0;
+      }
+      self.front = lo;
+      self.mid = len.min(lo + self.width);
+      self.back = self.mid;
+      self.end = len.min(lo + 2 * self.width);
+    }
+  }
+
+  pub fn take(&mut self, back_is_less: bool) {
+    let next = if back_is_less {
+      &mut self.back
+    } else {
+      &mut self.front
+    };
+    self.merged.push(self.items[*next].clone());
+    *next += 1;
+  }
+
+  pub fn objects(&self) -> impl Iterator<Item = &Object> {
+    self.items.iter().chain(self.merged.iter())
+  }
+
+  pub fn into_sorted(self) -> Vec<Object> {
+    self.items
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::env::*;
+  use crate::eval::eval;
+  use crate::object::*;
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  fn integers(ns: &[i64]) -> Object {
+    Object::ListData(Rc::new(
+      ns.iter().map(|n| Object::Integer(*n)).collect(),
+    ))
+  }
+
+  #[test]
+  fn test_list_procedures() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (list
+            (append (list 1 2) (list) (list 3))
+            (reverse (list 1 2 3))
+            (list-ref (list 1 2 3) 1)
+            (list-tail (list 1 2 3) 1)
+            (take (list 1 2 3) 2)
+            (drop (list 1 2 3) 2)
+            (last (list 1 2 3))
+            (member 2 (list 1 2 3))
+            (member 5 (list 1 2 3))
+            (assoc \"b\" (list (list \"a\" 1) (list \"b\" 2)))
+            (iota 4 1))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result,
+      Object::ListData(Rc::new(vec![
+        integers(&[1, 2, 3]),
+        integers(&[3, 2, 1]),
+        Object::Integer(2),
+        integers(&[2, 3]),
+        integers(&[1, 2]),
+        integers(&[3]),
+        Object::Integer(3),
+        integers(&[2, 3]),
+        Object::Bool(false),
+        Object::ListData(Rc::new(vec![
+          Object::String("b".into()),
+          Object::Integer(2),
+        ])),
+        integers(&[1, 2, 3, 4]),
+      ]))
+    );
+    assert!(
+      eval("(list-ref (list 1) 1)", &mut env).is_err()
+    );
+    assert!(eval("(take (list 1) 2)", &mut env).is_err());
+    assert!(
+      eval("(iota 1000000000000000000)", &mut env).is_err()
+    );
+  }
+
+  #[test]
+  fn test_sort() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (list
+            (sort (list 5 3 9 1 3) (lambda (a b) (< a b)))
+            (sort (list (list 2 \"a\") (list 1 \"b\") (list 2 \"c\"))
+                  (lambda (a b) (< (car a) (car b)))))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result.to_string(),
+      "((1 3 3 5 9) ((1 b) (2 a) (2 c)))"
+    );
+
+    let program = "
+        (list
+            (equal? (sort (reverse (iota 37)) <) (iota 37))
+            (sort (list 5 2 8 2 9 1 5 5 3 7 0) <)
+            (sort (list) <)
+            (sort (list 1) <))
+        ";
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result.to_string(),
+      "(true (0 1 2 2 3 5 5 5 7 8 9) () (1))"
+    );
+    assert!(
+      eval("(sort (list 2 1) car)", &mut env).is_err()
+    );
+  }
+
+  #[test]
+  fn test_builtins_as_values() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (list
+            (map car (list (list 1 2) (list 3 4)))
+            (sort (list 5 3 9 1) <)
+            (fold-left + 0 (list 1 2 3))
+            (map abs (list (- 0 1) 2))
+            ((if #t reverse length) (list 1 2))
+            (list car +))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result.to_string(),
+      "((1 3) (1 3 5 9) 6 (1 2) (2 1) \
+       (#<procedure car> #<procedure +>))"
+    );
+    assert!(eval("(map if (list 1))", &mut env).is_err());
+  }
+
+  #[test]
+  fn test_shadowing_builtins() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    for program in
+      ["(define last 3)", "(define (take l n) (list n))"]
+    {
+      eval(program, &mut env).unwrap();
+    }
+    let program = "
+        (list
+            last
+            (take (list 1 2) 1)
+            (let ((iota 4) (sort (lambda (l) (+ l 1)))) (sort iota))
+            ((lambda (filter) (+ filter 0)) 5)
+            (drop (list 1 2) 1))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result.to_string(), "(3 (1) 5 5 (2))");
+  }
+}
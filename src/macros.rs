@@ -0,0 +1,1143 @@
+use crate::env::*;
+use crate::eval::{eval_obj, extend_env};
+use crate::object::*;
+use crate::symbols::{self, Symbol};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::thread::LocalKey;
+
+// Macros are expanded before the code runs. syntax-rules
+// keeps its templates apart from the code around a use: the
+// names a template binds with lambda or let are renamed to
+// uninterned symbols, which nothing the caller writes can be
+// equal to, and its free identifiers are replaced by aliases
+// that only the environment the macro is defined in resolves,
+// so a caller binding the same names doesn't change what the
+// expansion refers to. Names a template defines are left as
+// they are, for the caller to use.
+#[derive(Debug, PartialEq)]
+pub enum Macro {
+  SyntaxRules(Vec<Object>, Vec<(Object, Object)>),
+  Procedural(
+    Vec<Symbol>,
+    Option<Symbol>,
+    Rc<Vec<Object>>,
+    Rc<RefCell<Env>>,
+  ),
+}
+
+#[derive(Debug, Clone)]
+enum Binding {
+  One(Object),
+  Many(Vec<Binding>),
+}
+
+type Bindings = HashMap<Symbol, Binding>;
+
+thread_local! {
+  static GENSYM_COUNTER: Cell<usize> = const { Cell::new(0) };
+  // How many macro uses are being expanded, each inside the
+  // expansion of the one before.
+  static EXPANDING: Cell<usize> = const { Cell::new(0) };
+  // How many defmacro bodies are running, each expanding a
+  // macro use from inside the one before. Each runs on a
+  // machine of its own, so they take far more of the Rust
+  // stack.
+  static RUNNING: Cell<usize> = const { Cell::new(0) };
+}
+
+// Macros that expand into uses of themselves forever are
+// stopped here, well before the Rust stack runs out.
+const MAX_EXPANSION_DEPTH: usize = 1000;
+const MAX_RUNNING_MACROS: usize = 32;
+
+fn nested<T>(
+  counter: &'static LocalKey<Cell<usize>>,
+  limit: usize,
+  f: impl FnOnce() -> Result<T, String>,
+) -> Result<T, String> {
+  let depth = counter.with(Cell::get);
+  if depth >= limit {
+    return Err("Macro expansion too deep".to_string());
+  }
+  counter.with(|counter| counter.set(depth + 1));
+  let result = f();
+  counter.with(|counter| counter.set(depth));
+  result
+}
+
+pub fn gensym(prefix: &str) -> Symbol {
+  GENSYM_COUNTER.with(|counter| {
+    let n = counter.get() + 1;
+    counter.set(n);
+    Symbol::uninterned(&format!("{}%{}", prefix, n))
+  })
+}
+
+// Source code is parsed into List, while the lists that
+// programs build at runtime are ListData. Quoted code and
+// the arguments and results of defmacro cross between the
+// two.
+pub fn to_data(obj: &Object) -> Object {
+  match obj {
+    Object::List(list) => Object::ListData(Rc::new(
+      list.iter().map(to_data).collect(),
+    )),
+    _ => obj.clone(),
+  }
+}
+
+pub fn to_code(obj: &Object) -> Object {
+  match obj {
+    Object::ListData(list) => Object::List(Rc::new(
+      list.iter().map(to_code).collect(),
+    )),
+    _ => obj.clone(),
+  }
+}
+
+fn is_ellipsis(obj: &Object) -> bool {
+  matches!(obj, Object::Symbol(s) if s == "...")
+}
+
+fn parse_syntax_rules(
+  spec: &Object,
+  env: &Rc<RefCell<Env>>,
+) -> Result<Macro, String> {
+  let list = match spec {
+    Object::List(list) => list,
+    _ => {
+      return Err(format!("Invalid syntax-rules {}", spec))
+    }
+  };
+  if list.len() < 2
+    || list[0]
+      != Object::Keyword("syntax-rules".to_string())
+  {
+    return Err(format!("Invalid syntax-rules {}", spec));
+  }
+
+  let literals = match &list[1] {
+    Object::List(literals) => literals.to_vec(),
+    _ => {
+      return Err(format!(
+        "Invalid syntax-rules literals {}",
+        list[1]
+      ))
+    }
+  };
+
+  let mut rules = Vec::new();
+  let mut aliases = HashMap::new();
+  for rule in list[2..].iter() {
+    match rule {
+      Object::List(rule)
+        if rule.len() == 2
+          && matches!(rule[0], Object::List(_)) =>
+      {
+        // The keyword position of the pattern is never
+        // matched, so it binds no pattern variable.
+        let mut vars = Vec::new();
+        if let Object::List(pats) = &rule[0] {
+          for pat in pats.iter().skip(1) {
+            pattern_vars(pat, &literals, &mut vars);
+          }
+        }
+        let mut keep: HashSet<Symbol> =
+          vars.into_iter().collect();
+        template_defines(&rule[1], &mut keep);
+        let tmpl =
+          alias_free(&rule[1], &keep, false, &mut aliases);
+        rules.push((rule[0].clone(), tmpl));
+      }
+      _ => {
+        return Err(format!(
+          "Invalid syntax-rules rule {}",
+          rule
+        ))
+      }
+    }
+  }
+  for (name, alias) in aliases {
+    env.borrow_mut().set_alias(alias, name);
+  }
+  Ok(Macro::SyntaxRules(literals, rules))
+}
+
+// Names that the template defines.
+fn template_defines(
+  tmpl: &Object,
+  names: &mut HashSet<Symbol>,
+) {
+  let list = match tmpl {
+    Object::List(list) => list,
+    _ => return,
+  };
+  match (list.first(), list.get(1)) {
+    (Some(Object::Keyword(k)), Some(Object::Symbol(s)))
+      if k == "define" || k == "define-syntax" =>
+    {
+      names.insert(*s);
+    }
+    (Some(Object::Keyword(k)), Some(Object::List(l)))
+      if k == "define" =>
+    {
+      if let Some(Object::Symbol(s)) = l.first() {
+        names.insert(*s);
+      }
+    }
+    _ => {}
+  }
+  for obj in list.iter() {
+    template_defines(obj, names);
+  }
+}
+
+// Replace the free identifiers of a template, the ones not
+// in keep, by their aliases. Quoted data is left alone, as
+// are the names in a define-record-type.
+fn alias_free(
+  tmpl: &Object,
+  keep: &HashSet<Symbol>,
+  quasiquoted: bool,
+  aliases: &mut HashMap<Symbol, Symbol>,
+) -> Object {
+  match tmpl {
+    Object::Symbol(s)
+      if !quasiquoted
+        && !keep.contains(s)
+        && !is_ellipsis(tmpl)
+        && !matches!(
+          *s,
+          symbols::TRUE | symbols::FALSE | symbols::NIL
+        ) =>
+    {
+      let alias = aliases
+        .entry(*s)
+        .or_insert_with(|| Symbol::alias(*s));
+      Object::Symbol(*alias)
+    }
+    Object::List(list) => {
+      let quasiquoted = match list.first() {
+        Some(Object::Keyword(k))
+          if k == "quote" || k == "define-record-type" =>
+        {
+          return tmpl.clone()
+        }
+        Some(Object::Keyword(k)) if k == "quasiquote" => {
+          true
+        }
+        Some(Object::Keyword(k))
+          if k == "unquote" || k == "unquote-splicing" =>
+        {
+          false
+        }
+        _ => quasiquoted,
+      };
+      Object::List(Rc::new(
+        list
+          .iter()
+          .map(|obj| {
+            alias_free(obj, keep, quasiquoted, aliases)
+          })
+          .collect(),
+      ))
+    }
+    _ => tmpl.clone(),
+  }
+}
+
+fn pattern_vars(
+  pat: &Object,
+  literals: &[Object],
+  vars: &mut Vec<Symbol>,
+) {
+  match pat {
+    Object::Symbol(s)
+      if s != "_"
+        && s != "..."
+        && !literals.contains(pat) =>
+    {
+      vars.push(*s)
+    }
+    Object::List(list) => {
+      for p in list.iter() {
+        pattern_vars(p, literals, vars);
+      }
+    }
+    _ => {}
+  }
+}
+
+fn match_pattern(
+  pat: &Object,
+  form: &Object,
+  literals: &[Object],
+  bindings: &mut Bindings,
+) -> bool {
+  match pat {
+    Object::Symbol(s) if s == "_" => true,
+    Object::Symbol(_) if literals.contains(pat) => {
+      pat == form
+    }
+    Object::Symbol(s) => {
+      bindings.insert(*s, Binding::One(form.clone()));
+      true
+    }
+    Object::List(pats) => {
+      let forms = match form {
+        Object::List(forms) => forms,
+        _ => return false,
+      };
+      match pats.iter().position(is_ellipsis) {
+        None => {
+          pats.len() == forms.len()
+            && pats.iter().zip(forms.iter()).all(
+              |(p, f)| {
+                match_pattern(p, f, literals, bindings)
+              },
+            )
+        }
+        Some(0) => false,
+        Some(e) => {
+          let before = &pats[..e - 1];
+          let repeated = &pats[e - 1];
+          let after = &pats[e + 1..];
+          if forms.len() < before.len() + after.len() {
+            return false;
+          }
+          let tail = forms.len() - after.len();
+
+          for (p, f) in before.iter().zip(forms.iter()) {
+            if !match_pattern(p, f, literals, bindings) {
+              return false;
+            }
+          }
+
+          let mut vars = Vec::new();
+          pattern_vars(repeated, literals, &mut vars);
+          let mut matches: Vec<Vec<Binding>> =
+            vec![Vec::new(); vars.len()];
+          for f in forms[before.len()..tail].iter() {
+            let mut b = Bindings::new();
+            if !match_pattern(repeated, f, literals, &mut b)
+            {
+              return false;
+            }
+            for (i, var) in vars.iter().enumerate() {
+              matches[i].push(b.remove(var).unwrap());
+            }
+          }
+          for (var, m) in vars.into_iter().zip(matches) {
+            bindings.insert(var, Binding::Many(m));
+          }
+
+          after.iter().zip(forms[tail..].iter()).all(
+            |(p, f)| {
+              match_pattern(p, f, literals, bindings)
+            },
+          )
+        }
+      }
+    }
+    _ => pat == form,
+  }
+}
+
+fn expand_template(
+  tmpl: &Object,
+  bindings: &Bindings,
+) -> Result<Object, String> {
+  match tmpl {
+    Object::Symbol(s) => match bindings.get(s) {
+      Some(Binding::One(obj)) => Ok(obj.clone()),
+      Some(Binding::Many(_)) => Err(format!(
+        "Pattern variable {} used without ellipsis",
+        s
+      )),
+      None => Ok(tmpl.clone()),
+    },
+    Object::List(list) => {
+      let mut new_list = Vec::new();
+      let mut i = 0;
+      while i < list.len() {
+        let item = &list[i];
+        if i + 1 < list.len() && is_ellipsis(&list[i + 1]) {
+          let mut vars = Vec::new();
+          pattern_vars(item, &[], &mut vars);
+          let seqs: Vec<(&Symbol, &Vec<Binding>)> = vars
+            .iter()
+            .filter_map(|v| match bindings.get(v) {
+              Some(Binding::Many(seq)) => Some((v, seq)),
+              _ => None,
+            })
+            .collect();
+          if seqs.is_empty() {
+            return Err(format!(
+              "No pattern variables before ellipsis in {}",
+              tmpl
+            ));
+          }
+          let n = seqs[0].1.len();
+          if seqs.iter().any(|(_, seq)| seq.len() != n) {
+            return Err(format!(
+              "Mismatched ellipsis lengths in {}",
+              tmpl
+            ));
+          }
+          for k in 0..n {
+            let mut b = bindings.clone();
+            for (var, seq) in seqs.iter() {
+              b.insert(**var, seq[k].clone());
+            }
+            new_list.push(expand_template(item, &b)?);
+          }
+          i += 2;
+        } else {
+          new_list.push(expand_template(item, bindings)?);
+          i += 1;
+        }
+      }
+      Ok(Object::List(Rc::new(new_list)))
+    }
+    _ => Ok(tmpl.clone()),
+  }
+}
+
+// Names that the template itself binds with lambda or let.
+fn template_binders(
+  tmpl: &Object,
+  vars: &[Symbol],
+  binders: &mut HashSet<Symbol>,
+) {
+  let list = match tmpl {
+    Object::List(list) => list,
+    _ => return,
+  };
+  let mut add = |obj: &Object| {
+    if let Object::Symbol(s) = obj {
+      if *s != *"..." && !vars.contains(s) {
+        binders.insert(*s);
+      }
+    }
+  };
+  match (list.first(), list.get(1)) {
+    (
+      Some(Object::Keyword(k)),
+      Some(Object::List(params)),
+    ) if k == "lambda" => {
+      params.iter().for_each(&mut add);
+    }
+    (
+      Some(Object::Keyword(k)),
+      Some(Object::List(bindings)),
+    ) if k == "let" => {
+      for binding in bindings.iter() {
+        if let Object::List(binding) = binding {
+          if let Some(name) = binding.first() {
+            add(name);
+          }
+        }
+      }
+    }
+    _ => {}
+  }
+  for obj in list.iter() {
+    template_binders(obj, vars, binders);
+  }
+}
+
+fn rename(
+  tmpl: &Object,
+  names: &HashMap<Symbol, Symbol>,
+) -> Object {
+  match tmpl {
+    Object::Symbol(s) => match names.get(s) {
+      Some(name) => Object::Symbol(*name),
+      None => tmpl.clone(),
+    },
+    Object::List(list) => Object::List(Rc::new(
+      list.iter().map(|obj| rename(obj, names)).collect(),
+    )),
+    _ => tmpl.clone(),
+  }
+}
+
+fn expand_syntax_rules(
+  literals: &[Object],
+  rules: &[(Object, Object)],
+  form: &Object,
+) -> Result<Object, String> {
+  let forms = match form {
+    Object::List(forms) => forms,
+    _ => return Err(format!("Invalid macro use {}", form)),
+  };
+  for (pat, tmpl) in rules {
+    let pats = match pat {
+      Object::List(pats) if !pats.is_empty() => pats,
+      _ => continue,
+    };
+    // The keyword position of the pattern is never matched.
+    let pat = Object::List(Rc::new(pats[1..].to_vec()));
+    let form = Object::List(Rc::new(forms[1..].to_vec()));
+    let mut bindings = Bindings::new();
+    if !match_pattern(&pat, &form, literals, &mut bindings)
+    {
+      continue;
+    }
+
+    // Variables bound by the template get fresh names so
+    // that they cannot capture identifiers passed in by the
+    // caller.
+    let mut vars = Vec::new();
+    pattern_vars(&pat, literals, &mut vars);
+    let mut binders = HashSet::new();
+    template_binders(tmpl, &vars, &mut binders);
+    let names = binders
+      .into_iter()
+      .map(|name| {
+        let fresh = gensym(&name.name());
+        (name, fresh)
+      })
+      .collect::<HashMap<_, _>>();
+    return expand_template(
+      &rename(tmpl, &names),
+      &bindings,
+    );
+  }
+  Err(format!("No syntax-rules pattern matches {}", form))
+}
+
+fn expand_procedural(
+  params: &[Symbol],
+  rest: &Option<Symbol>,
+  body: &[Object],
+  env: &Rc<RefCell<Env>>,
+  form: &Object,
+) -> Result<Object, String> {
+  let args = match form {
+    Object::List(forms) => &forms[1..],
+    _ => return Err(format!("Invalid macro use {}", form)),
+  };
+  if args.len() < params.len()
+    || (rest.is_none() && args.len() != params.len())
+  {
+    return Err(format!(
+      "Invalid number of arguments for macro {}",
+      form
+    ));
+  }
+
+  let mut macro_env = extend_env(env)?;
+  for (param, arg) in params.iter().zip(args.iter()) {
+    macro_env.borrow_mut().set(*param, to_data(arg));
+  }
+  if let Some(rest) = rest {
+    let rest_args =
+      args[params.len()..].iter().map(to_data);
+    macro_env.borrow_mut().set(
+      *rest,
+      Object::ListData(Rc::new(rest_args.collect())),
+    );
+  }
+
+  let result =
+    nested(&RUNNING, MAX_RUNNING_MACROS, || {
+      let mut result = Object::Void;
+      for obj in body.iter() {
+        result = eval_obj(obj, &mut macro_env)
+          .map_err(|e| e.to_string())?;
+      }
+      Ok(result)
+    })?;
+  Ok(to_code(&result))
+}
+
+fn expand_macro(
+  m: &Macro,
+  form: &Object,
+) -> Result<Object, String> {
+  match m {
+    Macro::SyntaxRules(literals, rules) => {
+      expand_syntax_rules(literals, rules, form)
+    }
+    Macro::Procedural(params, rest, body, env) => {
+      expand_procedural(params, rest, body, env, form)
+    }
+  }
+}
+
+// Expand `form` once if it is a macro use. The flag tells
+// whether anything was expanded.
+pub fn macroexpand_1(
+  form: &Object,
+  env: &Rc<RefCell<Env>>,
+) -> Result<(Object, bool), String> {
+  if let Object::List(list) = form {
+    if let Some(Object::Symbol(s)) = list.first() {
+      let m = env.borrow().get_macro(*s);
+      if let Some(m) = m {
+        let expanded =
+          nested(&EXPANDING, MAX_EXPANSION_DEPTH, || {
+            expand_macro(&m, form)
+          })?;
+        return Ok((expanded, true));
+      }
+    }
+  }
+  Ok((form.clone(), false))
+}
+
+pub fn macroexpand(
+  form: &Object,
+  env: &Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+  let mut form = form.clone();
+  for _ in 0..MAX_EXPANSION_DEPTH {
+    let (expanded, changed) = macroexpand_1(&form, env)?;
+    if !changed {
+      return Ok(expanded);
+    }
+    form = expanded;
+  }
+  Err("Macro expansion too deep".to_string())
+}
+
+// The macro is defined in env, and the free identifiers of
+// its templates refer to the bindings in alias_env.
+fn eval_define_syntax(
+  list: &[Object],
+  env: &Rc<RefCell<Env>>,
+  alias_env: &Rc<RefCell<Env>>,
+) -> Result<(), String> {
+  if list.len() != 3 {
+    return Err(
+      "Invalid number of arguments for define-syntax"
+        .to_string(),
+    );
+  }
+  let name = match &list[1] {
+    Object::Symbol(name) => name,
+    _ => {
+      return Err(format!(
+        "Invalid define-syntax {}",
+        list[1]
+      ))
+    }
+  };
+  let m = parse_syntax_rules(&list[2], alias_env)?;
+  env.borrow_mut().set_macro(*name, m);
+  Ok(())
+}
+
+fn eval_defmacro(
+  list: &[Object],
+  env: &Rc<RefCell<Env>>,
+) -> Result<(), String> {
+  if list.len() < 4 {
+    return Err(
+      "Invalid number of arguments for defmacro"
+        .to_string(),
+    );
+  }
+  let name = match &list[1] {
+    Object::Symbol(name) => name,
+    _ => {
+      return Err(format!("Invalid defmacro {}", list[1]))
+    }
+  };
+
+  let mut params = Vec::new();
+  let mut rest = None;
+  let param_list = match &list[2] {
+    Object::List(param_list) => param_list,
+    _ => {
+      return Err(format!(
+        "Invalid defmacro parameters {}",
+        list[2]
+      ))
+    }
+  };
+  let mut iter = param_list.iter();
+  while let Some(param) = iter.next() {
+    match param {
+      Object::Symbol(s) => params.push(*s),
+      Object::BinaryOp(s)
+        if s == "&rest" || s == "&body" =>
+      {
+        match (iter.next(), iter.next()) {
+          (Some(Object::Symbol(s)), None) => {
+            rest = Some(*s)
+          }
+          _ => {
+            return Err(format!(
+              "Invalid defmacro parameters {}",
+              list[2]
+            ))
+          }
+        }
+      }
+      _ => {
+        return Err(format!(
+          "Invalid defmacro parameter {}",
+          param
+        ))
+      }
+    }
+  }
+
+  let mut body = Vec::new();
+  for obj in list[3..].iter() {
+    body.push(expand(obj, env)?);
+  }
+  env.borrow_mut().set_macro(
+    *name,
+    Macro::Procedural(
+      params,
+      rest,
+      Rc::new(body),
+      env.clone(),
+    ),
+  );
+  Ok(())
+}
+
+fn expand_let_syntax(
+  list: &[Object],
+  env: &Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+  if list.len() < 3 {
+    return Err(
+      "Invalid number of arguments for let-syntax"
+        .to_string(),
+    );
+  }
+  let bindings = match &list[1] {
+    Object::List(bindings) => bindings,
+    _ => {
+      return Err(
+        "Invalid bindings for let-syntax".to_string(),
+      )
+    }
+  };
+
+  // The macros are only seen in the body, and their templates
+  // refer to the bindings around the let-syntax.
+  let scope =
+    Rc::new(RefCell::new(Env::extend(env.clone())));
+  for binding in bindings.iter() {
+    match binding {
+      Object::List(binding) if binding.len() == 2 => {
+        eval_define_syntax(
+          &[
+            Object::Void,
+            binding[0].clone(),
+            binding[1].clone(),
+          ],
+          &scope,
+          env,
+        )?;
+      }
+      _ => {
+        return Err(format!(
+          "Invalid binding for let-syntax {}",
+          binding
+        ))
+      }
+    }
+  }
+
+  let mut body = vec![Object::Keyword("begin".to_string())];
+  for obj in list[2..].iter() {
+    body.push(expand(obj, &scope)?);
+  }
+  Ok(Object::List(Rc::new(body)))
+}
+
+// Only the unquoted parts of a quasiquote template are
+// code.
+fn expand_quasiquote(
+  obj: &Object,
+  env: &Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+  let list = match obj {
+    Object::List(list) => list,
+    _ => return Ok(obj.clone()),
+  };
+  match list.first() {
+    Some(Object::Keyword(k))
+      if (k == "unquote" || k == "unquote-splicing")
+        && list.len() == 2 =>
+    {
+      Ok(Object::List(Rc::new(vec![
+        list[0].clone(),
+        expand(&list[1], env)?,
+      ])))
+    }
+    _ => {
+      let mut new_list = Vec::with_capacity(list.len());
+      for obj in list.iter() {
+        new_list.push(expand_quasiquote(obj, env)?);
+      }
+      Ok(Object::List(Rc::new(new_list)))
+    }
+  }
+}
+
+// Expand every macro use in `obj`, registering the macros
+// defined along the way with `define-syntax` in `env`.
+pub fn expand(
+  obj: &Object,
+  env: &Rc<RefCell<Env>>,
+) -> Result<Object, String> {
+  let list = match obj {
+    Object::List(list) => list,
+    _ => return Ok(obj.clone()),
+  };
+
+  match list.first() {
+    Some(Object::Keyword(k)) if k == "quote" => {
+      return Ok(obj.clone());
+    }
+    Some(Object::Keyword(k)) if k == "quasiquote" => {
+      return expand_quasiquote(obj, env);
+    }
+    Some(Object::Keyword(k)) if k == "defmacro" => {
+      eval_defmacro(list, env)?;
+      return Ok(Object::Void);
+    }
+    Some(Object::Keyword(k)) if k == "define-syntax" => {
+      eval_define_syntax(list, env, env)?;
+      return Ok(Object::Void);
+    }
+    Some(Object::Keyword(k)) if k == "let-syntax" => {
+      return expand_let_syntax(list, env);
+    }
+    Some(Object::Symbol(s)) => {
+      let m = env.borrow().get_macro(*s);
+      if let Some(m) = m {
+        return nested(
+          &EXPANDING,
+          MAX_EXPANSION_DEPTH,
+          || {
+            let form = expand_macro(&m, obj)?;
+            expand(&form, env)
+          },
+        );
+      }
+    }
+    _ => {}
+  }
+
+  let mut new_list = Vec::with_capacity(list.len());
+  for obj in list.iter() {
+    new_list.push(expand(obj, env)?);
+  }
+  Ok(Object::List(Rc::new(new_list)))
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::env::*;
+  use crate::eval::eval;
+  use crate::object::*;
+  use crate::symbols::Symbol;
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  #[test]
+  fn test_unless() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define-syntax unless
+                (syntax-rules ()
+                    ((_ c body ...) (if c (list) (begin body ...)))))
+            (unless (> 1 2) (define x 10) (* x 2))
+        )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(20));
+  }
+
+  #[test]
+  fn test_ellipsis_pairs() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define-syntax my-let
+                (syntax-rules ()
+                    ((_ ((name val) ...) body ...)
+                        ((lambda (name ...) body ...) val ...))))
+            (my-let ((a 1) (b 2) (c 3)) (list a b c))
+        )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result,
+      Object::ListData(Rc::new(vec![
+        Object::Integer(1),
+        Object::Integer(2),
+        Object::Integer(3),
+      ]))
+    );
+  }
+
+  #[test]
+  fn test_recursive_macro_and_literals() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define-syntax my-cond
+                (syntax-rules (else)
+                    ((_ (else e)) e)
+                    ((_ (c e) rest ...) (if c e (my-cond rest ...)))))
+            (my-cond ((> 1 2) 1) ((< 1 2) 2) (else 3))
+        )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(2));
+  }
+
+  #[test]
+  fn test_expansion_too_deep() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = |expr| {
+      format!(
+        "(begin
+            (define-syntax loop
+                (syntax-rules () ((_ x) (loop x))))
+            {})",
+        expr
+      )
+    };
+    for expr in ["(loop 1)", "(macroexpand '(loop 1))"] {
+      assert_eq!(
+        eval(&program(expr), &mut env)
+          .err()
+          .unwrap()
+          .to_string(),
+        "Macro expansion too deep"
+      );
+    }
+    assert_eq!(
+      eval(&program("(macroexpand-1 '(loop 1))"), &mut env)
+        .unwrap()
+        .to_string(),
+      "(loop 1)"
+    );
+  }
+
+  #[test]
+  fn test_hygiene() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define-syntax my-or
+                (syntax-rules ()
+                    ((_ e) e)
+                    ((_ e r ...) (let ((t e)) (if t t (my-or r ...))))))
+            (define t 5)
+            (my-or (> 1 2) (< t 10))
+        )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Bool(true));
+  }
+
+  #[test]
+  fn test_renamed_binders_are_uninterned() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define-syntax my-or
+                (syntax-rules ()
+                    ((_ e) e)
+                    ((_ e r ...) (let ((t e)) (if t t (my-or r ...))))))
+            (define expanded (macroexpand '(my-or #f x)))
+            (define fresh (car (car (car (cdr expanded)))))
+            (define g (gensym))
+            (list
+                (eq? fresh (string->symbol (symbol->string fresh)))
+                (eq? g (string->symbol (symbol->string g)))
+                (eq? g g)))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result.to_string(), "(false false true)");
+  }
+
+  #[test]
+  fn test_free_identifiers_refer_to_definition() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define (helper x) (* x 2))
+            (define-syntax twice
+                (syntax-rules () ((_ e) (helper e))))
+            (define-syntax my-append
+                (syntax-rules ()
+                    ((_ e) (append e '(append)))))
+            (define-syntax def-const
+                (syntax-rules ()
+                    ((_ name e) (define (name) (list e)))))
+            (def-const three 3)
+            (define (add x)
+                (begin
+                    (define-syntax plus-x
+                        (syntax-rules () ((_ e) (+ e x))))
+                    (plus-x 1)))
+            (list
+                (twice 5)
+                (let ((helper (lambda (x) (+ x 1)))) (twice 5))
+                (let ((append vector)) (my-append '(1)))
+                (three)
+                (add 4)))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result.to_string(),
+      "(10 10 (1 append) (3) 5)"
+    );
+  }
+
+  #[test]
+  fn test_defmacro() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (defmacro my-unless (c &rest body)
+                `(if ,c (list) (begin ,@body)))
+            (my-unless (> 1 2) (define x 10) (* x 2))
+        )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(20));
+  }
+
+  #[test]
+  fn test_defmacro_too_deep() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    for program in [
+      "(begin (defmacro m (x) (list 'm x)) (m 1))",
+      "(begin
+           (defmacro m (x) (list 'm x))
+           (macroexpand '(m 1)))",
+      "(begin
+           (defmacro m (x) (macroexpand (list 'm x)))
+           (m 1))",
+    ] {
+      assert_eq!(
+        eval(program, &mut env).err().unwrap().to_string(),
+        "Macro expansion too deep"
+      );
+    }
+  }
+
+  #[test]
+  fn test_defmacro_charges_quota() {
+    // Each expansion binds x in an environment of its own.
+    let program = format!(
+      "(begin (defmacro id (x) x) {}1{})",
+      "(id ".repeat(200),
+      ")".repeat(200)
+    );
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    assert_eq!(
+      eval(&program, &mut env),
+      Ok(Object::Integer(1))
+    );
+    let mut env = Rc::new(RefCell::new(Env::with_quota(
+      100 * size_of::<Env>(),
+    )));
+    assert!(eval(&program, &mut env)
+      .err()
+      .unwrap()
+      .to_string()
+      .starts_with("Resource limit exceeded"));
+  }
+
+  #[test]
+  fn test_defmacro_calls_earlier_define() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define (wrap x) (list 'list x x))
+            (defmacro twice (x) (wrap x))
+            (twice (+ 1 2)))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result.to_string(), "(3 3)");
+
+    // The forms after a continuation are run again when it
+    // is called.
+    let program = "
+        (begin
+            (define k (call/cc (lambda (c) (list c))))
+            (if (number? k) (list k) ((car k) 5)))
+        ";
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result.to_string(), "(5)");
+  }
+
+  #[test]
+  fn test_defmacro_gensym() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (defmacro square (x)
+                (let ((g (gensym)))
+                    `(let ((,g ,x)) (* ,g ,g))))
+            (define g 3)
+            (square (+ g 1))
+        )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(16));
+  }
+
+  #[test]
+  fn test_macroexpand() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (defmacro my-if (c a b) `(cond (,c ,a) (else ,b)))
+            (defmacro my-when (c x) `(my-if ,c ,x (list)))
+            (list
+                (macroexpand-1 '(my-when (> 2 1) 5))
+                (macroexpand '(my-when (> 2 1) 5)))
+        )
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result.to_string(),
+      "((my-if (> 2 1) 5 (list)) (cond ((> 2 1) 5) (else (list))))"
+    );
+  }
+
+  #[test]
+  fn test_let_syntax() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (let-syntax ((double (syntax-rules () ((_ x) (* 2 x)))))
+            (double 21))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result, Object::Integer(42));
+    assert!(env
+      .borrow()
+      .get_macro(Symbol::new("double"))
+      .is_none());
+  }
+}
@@ -1,8 +1,22 @@
+mod bytevectors;
+mod chars;
+mod compile;
 mod env;
+mod equality;
 mod eval;
+mod gc;
+mod hashtables;
 mod lexer;
+mod lists;
+mod macros;
+mod numbers;
 mod object;
 mod parser;
+mod persistent;
+mod records;
+mod strings;
+mod symbols;
+mod vectors;
 
 use linefeed::{Interface, ReadResult};
 use object::Object;
@@ -11,9 +25,29 @@ use std::rc::Rc;
 
 const PROMPT: &str = "lisp-rs> ";
 
+// `lisp-rs --max-bytes N` ends the session once the code it
+// runs has allocated about N bytes.
+fn max_bytes() -> Result<Option<usize>, String> {
+  let args: Vec<String> =
+    std::env::args().skip(1).collect();
+  match args.as_slice() {
+    [] => Ok(None),
+    [flag, n] if flag == "--max-bytes" => n
+      .parse()
+      .map(Some)
+      .map_err(|_| format!("Invalid byte count {}", n)),
+    _ => Err("Usage: lisp-rs [--max-bytes N]".to_string()),
+  }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
   let reader = Interface::new(PROMPT).unwrap();
-  let mut env = Rc::new(RefCell::new(env::Env::new()));
+  let env = match max_bytes()? {
+    Some(limit) => env::Env::with_quota(limit),
+    None => env::Env::new(),
+  };
+  let mut env = Rc::new(RefCell::new(env));
+  gc::track(&env);
 
   reader
     .set_prompt(format!("{}", PROMPT).as_ref())
@@ -33,7 +67,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
       Object::Symbol(s) => println!("{}", s),
       Object::Lambda(params, body, _) => {
         println!("Lambda(");
-        for param in params {
+        for param in params.iter() {
           println!("{} ", param);
         }
         println!(")");
@@ -43,6 +77,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
       }
       _ => println!("{}", val),
     }
+    gc::gc();
   }
 
   println!("Good bye");
@@ -0,0 +1,891 @@
+use crate::env::*;
+use crate::eval::EvalError;
+use crate::object::*;
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{Signed, ToPrimitive, Zero};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+// Integers are kept as i64 whenever they fit, and as bignums
+// only when they do not.
+pub fn normalize(n: BigInt) -> Object {
+  match n.to_i64() {
+    Some(n) => Object::Integer(n),
+    None => Object::BigInt(n),
+  }
+}
+
+// Likewise a rational with a denominator of 1 is an integer.
+pub fn normalize_ratio(r: BigRational) -> Object {
+  if r.is_integer() {
+    normalize(r.to_integer())
+  } else {
+    Object::Rational(r)
+  }
+}
+
+pub fn to_bigint(obj: &Object) -> Option<BigInt> {
+  match obj {
+    Object::Integer(n) => Some(BigInt::from(*n)),
+    Object::BigInt(n) => Some(n.clone()),
+    _ => None,
+  }
+}
+
+pub fn to_rational(obj: &Object) -> Option<BigRational> {
+  match obj {
+    Object::Rational(r) => Some(r.clone()),
+    _ => to_bigint(obj).map(BigRational::from_integer),
+  }
+}
+
+pub fn to_f64(obj: &Object) -> Option<f64> {
+  match obj {
+    Object::Integer(n) => Some(*n as f64),
+    Object::BigInt(n) => n.to_f64(),
+    Object::Rational(r) => r.to_f64(),
+    Object::Float(f) => Some(*f),
+    _ => None,
+  }
+}
+
+fn is_number(obj: &Object) -> bool {
+  is_exact(obj) || matches!(obj, Object::Float(_))
+}
+
+fn is_exact(obj: &Object) -> bool {
+  matches!(
+    obj,
+    Object::Integer(_)
+      | Object::BigInt(_)
+      | Object::Rational(_)
+  )
+}
+
+fn number_arg(obj: &Object) -> Result<&Object, EvalError> {
+  if is_number(obj) {
+    Ok(obj)
+  } else {
+    Err(format!("{} is not a number", obj).into())
+  }
+}
+
+// Both operands of a numeric operator, converted to the
+// representation of the more general one.
+enum Operands {
+  Small(i64, i64),
+  Big(BigInt, BigInt),
+  Ratio(BigRational, BigRational),
+  Float(f64, f64),
+}
+
+fn operands(
+  left: &Object,
+  right: &Object,
+) -> Option<Operands> {
+  match (left, right) {
+    (Object::Integer(l), Object::Integer(r)) => {
+      Some(Operands::Small(*l, *r))
+    }
+    (Object::Float(_), _) | (_, Object::Float(_)) => {
+      Some(Operands::Float(to_f64(left)?, to_f64(right)?))
+    }
+    (Object::Rational(_), _) | (_, Object::Rational(_)) => {
+      Some(Operands::Ratio(
+        to_rational(left)?,
+        to_rational(right)?,
+      ))
+    }
+    _ => Some(Operands::Big(
+      to_bigint(left)?,
+      to_bigint(right)?,
+    )),
+  }
+}
+
+// Apply an arithmetic or comparison operator to two numbers,
+// or return None when it does not apply to them. Dividing
+// exact numbers by an exact zero is an error, as soon as a
+// float is involved it follows IEEE 754 and gives an infinity
+// or NaN instead. Bignums and rationals can grow without
+// bound, so like expt their results are charged to the quota
+// up front, at the size of both operands.
+pub fn eval_number_op(
+  op: &str,
+  left: &Object,
+  right: &Object,
+  env: &Rc<RefCell<Env>>,
+) -> Option<Result<Object, EvalError>> {
+  if !["+", "-", "*", "/", "%", "<", ">", "=", "!="]
+    .contains(&op)
+  {
+    return None;
+  }
+  let operands = operands(left, right)?;
+  let zero = match &operands {
+    Operands::Small(_, r) => *r == 0,
+    Operands::Big(_, r) => r.is_zero(),
+    Operands::Ratio(_, r) => r.is_zero(),
+    Operands::Float(..) => false,
+  };
+  if zero && (op == "/" || op == "%") {
+    return Some(Err(
+      "Division by zero".to_string().into(),
+    ));
+  }
+  let bits = match &operands {
+    Operands::Big(l, r) => l.bits() + r.bits(),
+    Operands::Ratio(l, r) => {
+      l.numer().bits()
+        + l.denom().bits()
+        + r.numer().bits()
+        + r.denom().bits()
+    }
+    _ => 0,
+  };
+  if bits > 0 && ["+", "-", "*", "/", "%"].contains(&op) {
+    if let Err(e) = env.borrow().charge((bits / 8) as usize)
+    {
+      return Some(Err(e.into()));
+    }
+  }
+  Some(match operands {
+    Operands::Small(l, r) => small_op(op, l, r),
+    Operands::Big(l, r) => Ok(big_op(op, &l, &r)),
+    Operands::Ratio(l, r) => Ok(ratio_op(op, &l, &r)),
+    Operands::Float(l, r) => Ok(float_op(op, l, r)),
+  })
+}
+
+// Order two numbers, None when either is not a number or NaN.
+fn compare_numbers(
+  left: &Object,
+  right: &Object,
+) -> Option<Ordering> {
+  match operands(left, right)? {
+    Operands::Small(l, r) => Some(l.cmp(&r)),
+    Operands::Big(l, r) => Some(l.cmp(&r)),
+    Operands::Ratio(l, r) => Some(l.cmp(&r)),
+    Operands::Float(l, r) => l.partial_cmp(&r),
+  }
+}
+
+fn compare(op: &str, ordering: Ordering) -> Object {
+  Object::Bool(match op {
+    "<" => ordering == Ordering::Less,
+    ">" => ordering == Ordering::Greater,
+    "=" => ordering == Ordering::Equal,
+    _ => ordering != Ordering::Equal,
+  })
+}
+
+fn small_op(
+  op: &str,
+  l: i64,
+  r: i64,
+) -> Result<Object, EvalError> {
+  let result = match op {
+    "+" => l.checked_add(r),
+    "-" => l.checked_sub(r),
+    "*" => l.checked_mul(r),
+    // Only exact quotients stay integers.
+    "/" => match l.checked_rem(r) {
+      Some(0) => l.checked_div(r),
+      _ => None,
+    },
+    "%" => l.checked_rem(r),
+    _ => return Ok(compare(op, l.cmp(&r))),
+  };
+  match result {
+    Some(n) => Ok(Object::Integer(n)),
+    // Overflowed, redo it with bignums.
+    None => {
+      Ok(big_op(op, &BigInt::from(l), &BigInt::from(r)))
+    }
+  }
+}
+
+fn big_op(op: &str, l: &BigInt, r: &BigInt) -> Object {
+  match op {
+    "+" => normalize(l + r),
+    "-" => normalize(l - r),
+    "*" => normalize(l * r),
+    "/" => normalize_ratio(BigRational::new(
+      l.clone(),
+      r.clone(),
+    )),
+    "%" => normalize(l % r),
+    _ => compare(op, l.cmp(r)),
+  }
+}
+
+fn ratio_op(
+  op: &str,
+  l: &BigRational,
+  r: &BigRational,
+) -> Object {
+  match op {
+    "+" => normalize_ratio(l + r),
+    "-" => normalize_ratio(l - r),
+    "*" => normalize_ratio(l * r),
+    "/" => normalize_ratio(l / r),
+    "%" => normalize_ratio(l % r),
+    _ => compare(op, l.cmp(r)),
+  }
+}
+
+fn float_op(op: &str, l: f64, r: f64) -> Object {
+  match op {
+    "+" => Object::Float(l + r),
+    "-" => Object::Float(l - r),
+    "*" => Object::Float(l * r),
+    "/" => Object::Float(l / r),
+    "%" => Object::Float(l % r),
+    "<" => Object::Bool(l < r),
+    ">" => Object::Bool(l > r),
+    "=" => Object::Bool(l == r),
+    _ => Object::Bool(l != r),
+  }
+}
+
+// The procedures taking a single number, and log and atan
+// which take an optional second one.
+pub fn eval_number_procedure(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  let name = list[0].to_string();
+  let max = match name.as_str() {
+    "log" | "atan" => 3,
+    _ => 2,
+  };
+  if list.len() < 2 || list.len() > max {
+    return Err(
+      format!("Invalid number of arguments for {}", name)
+        .into(),
+    );
+  }
+
+  let x = &list[1];
+  let result = match name.as_str() {
+    "number?" | "complex?" | "real?" => {
+      Object::Bool(is_number(x))
+    }
+    "rational?" => Object::Bool(match x {
+      Object::Float(f) => f.is_finite(),
+      _ => is_exact(x),
+    }),
+    "integer?" => Object::Bool(match x {
+      Object::Float(f) => f.fract() == 0.0,
+      Object::Integer(_) | Object::BigInt(_) => true,
+      _ => false,
+    }),
+    "exact-integer?" => Object::Bool(matches!(
+      x,
+      Object::Integer(_) | Object::BigInt(_)
+    )),
+    "exact?" => Object::Bool(is_exact(number_arg(x)?)),
+    "inexact?" => Object::Bool(!is_exact(number_arg(x)?)),
+    "nan?" | "finite?" | "infinite?" => {
+      let f = to_f64(number_arg(x)?).unwrap_or(0.0);
+      Object::Bool(match name.as_str() {
+        "nan?" => f.is_nan(),
+        "finite?" => is_exact(x) || f.is_finite(),
+        _ => !is_exact(x) && f.is_infinite(),
+      })
+    }
+    "zero?" | "positive?" | "negative?" => {
+      let sign = compare_numbers(
+        number_arg(x)?,
+        &Object::Integer(0),
+      );
+      Object::Bool(match name.as_str() {
+        "zero?" => sign == Some(Ordering::Equal),
+        "positive?" => sign == Some(Ordering::Greater),
+        _ => sign == Some(Ordering::Less),
+      })
+    }
+    "odd?" | "even?" => {
+      let odd = match x {
+        Object::Integer(n) => n % 2 != 0,
+        Object::BigInt(n) => n.is_odd(),
+        Object::Float(f) if f.fract() == 0.0 => {
+          f % 2.0 != 0.0
+        }
+        _ => {
+          return Err(
+            format!("{} is not an integer", x).into(),
+          )
+        }
+      };
+      Object::Bool(odd == (name == "odd?"))
+    }
+    "exact" | "inexact->exact" => exact(number_arg(x)?)?,
+    "inexact" | "exact->inexact" => {
+      Object::Float(to_f64(number_arg(x)?).unwrap())
+    }
+    "floor" | "ceiling" | "round" | "truncate" => {
+      round(&name, number_arg(x)?)
+    }
+    "abs" => abs(number_arg(x)?),
+    "numerator" | "denominator" => {
+      let r = match exact(number_arg(x)?)? {
+        Object::Rational(r) => r,
+        n => to_rational(&n).unwrap(),
+      };
+      let n = match name.as_str() {
+        "numerator" => r.numer().clone(),
+        _ => r.denom().clone(),
+      };
+      match x {
+        Object::Float(_) => {
+          Object::Float(n.to_f64().unwrap())
+        }
+        _ => normalize(n),
+      }
+    }
+    "sqrt" => sqrt(number_arg(x)?),
+    _ => {
+      let f = to_f64(number_arg(x)?).unwrap();
+      let y = match list.get(2) {
+        Some(y) => Some(to_f64(number_arg(y)?).unwrap()),
+        None => None,
+      };
+      Object::Float(match (name.as_str(), y) {
+        ("exp", _) => f.exp(),
+        ("log", None) => f.ln(),
+        ("log", Some(base)) => f.ln() / base.ln(),
+        ("sin", _) => f.sin(),
+        ("cos", _) => f.cos(),
+        ("tan", _) => f.tan(),
+        ("asin", _) => f.asin(),
+        ("acos", _) => f.acos(),
+        ("atan", None) => f.atan(),
+        ("atan", Some(x)) => f.atan2(x),
+        _ => {
+          return Err(
+            format!("Unknown keyword: {}", name).into(),
+          )
+        }
+      })
+    }
+  };
+  Ok(result)
+}
+
+// Floats become the exact value of their binary fraction, so
+// (exact 0.5) is 1/2.
+fn exact(x: &Object) -> Result<Object, EvalError> {
+  match x {
+    Object::Float(f) => BigRational::from_float(*f)
+      .map(normalize_ratio)
+      .ok_or_else(|| {
+        format!("{} has no exact representation", f).into()
+      }),
+    _ => Ok(x.clone()),
+  }
+}
+
+fn round(name: &str, x: &Object) -> Object {
+  match x {
+    Object::Float(f) => Object::Float(match name {
+      "floor" => f.floor(),
+      "ceiling" => f.ceil(),
+      "round" => f.round_ties_even(),
+      _ => f.trunc(),
+    }),
+    Object::Rational(r) => normalize(
+      match name {
+        "floor" => r.floor(),
+        "ceiling" => r.ceil(),
+        "round" => round_ties_even(r),
+        _ => r.trunc(),
+      }
+      .to_integer(),
+    ),
+    _ => x.clone(),
+  }
+}
+
+// Scheme rounds halves to even, BigRational::round away from
+// zero.
+fn round_ties_even(r: &BigRational) -> BigRational {
+  let floor = r.floor();
+  let half = BigRational::new(1.into(), 2.into());
+  match (r - &floor).cmp(&half) {
+    Ordering::Less => floor,
+    Ordering::Greater => floor + BigInt::from(1),
+    Ordering::Equal if floor.to_integer().is_even() => {
+      floor
+    }
+    Ordering::Equal => floor + BigInt::from(1),
+  }
+}
+
+fn abs(x: &Object) -> Object {
+  match x {
+    Object::Integer(n) => match n.checked_abs() {
+      Some(n) => Object::Integer(n),
+      None => normalize(BigInt::from(*n).abs()),
+    },
+    Object::BigInt(n) => normalize(n.abs()),
+    Object::Rational(r) => Object::Rational(r.abs()),
+    Object::Float(f) => Object::Float(f.abs()),
+    _ => x.clone(),
+  }
+}
+
+// Exact for the squares of exact numbers, otherwise a float,
+// which is NaN for negative numbers.
+fn sqrt(x: &Object) -> Object {
+  if let Some(r) =
+    to_rational(x).filter(|r| !r.is_negative())
+  {
+    let numer = r.numer().sqrt();
+    let denom = r.denom().sqrt();
+    let root = BigRational::new(numer, denom);
+    if &root * &root == r {
+      return normalize_ratio(root);
+    }
+  }
+  Object::Float(to_f64(x).unwrap().sqrt())
+}
+
+// quotient, remainder and modulo, on exact integers or on
+// floats with integral values.
+pub fn eval_integer_division(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  let name = list[0].to_string();
+  if list.len() != 3 {
+    return Err(
+      format!("Invalid number of arguments for {}", name)
+        .into(),
+    );
+  }
+
+  for obj in list[1..].iter() {
+    let integral = match obj {
+      Object::Integer(_) | Object::BigInt(_) => true,
+      Object::Float(f) => f.fract() == 0.0,
+      _ => false,
+    };
+    if !integral {
+      return Err(
+        format!("{} is not an integer", obj).into(),
+      );
+    }
+  }
+  if compare_numbers(&list[2], &Object::Integer(0))
+    == Some(Ordering::Equal)
+  {
+    return Err(
+      format!("Division by zero in {}", name).into(),
+    );
+  }
+
+  let result = match operands(&list[1], &list[2]).unwrap() {
+    Operands::Small(l, r) => {
+      let n = match name.as_str() {
+        "quotient" => l.checked_div(r),
+        "remainder" => l.checked_rem(r),
+        _ => l.checked_rem(r).map(|m| {
+          if m != 0 && (m < 0) != (r < 0) {
+            m + r
+          } else {
+            m
+          }
+        }),
+      };
+      match n {
+        Some(n) => Object::Integer(n),
+        // Only i64::MIN by -1 overflows.
+        None => {
+          let (l, r) = (BigInt::from(l), BigInt::from(r));
+          big_division(&name, &l, &r)
+        }
+      }
+    }
+    Operands::Big(l, r) => big_division(&name, &l, &r),
+    Operands::Float(l, r) => {
+      Object::Float(match name.as_str() {
+        "quotient" => (l / r).trunc(),
+        "remainder" => l % r,
+        _ => {
+          let m = l % r;
+          if m != 0.0 && (m < 0.0) != (r < 0.0) {
+            m + r
+          } else {
+            m
+          }
+        }
+      })
+    }
+    Operands::Ratio(..) => unreachable!(),
+  };
+  Ok(result)
+}
+
+fn big_division(
+  name: &str,
+  l: &BigInt,
+  r: &BigInt,
+) -> Object {
+  normalize(match name {
+    "quotient" => l / r,
+    "remainder" => l % r,
+    _ => l.mod_floor(r),
+  })
+}
+
+// min and max return an inexact number when any argument is
+// inexact.
+pub fn eval_min_max(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  let name = list[0].to_string();
+  if list.len() < 2 {
+    return Err(
+      format!("Invalid number of arguments for {}", name)
+        .into(),
+    );
+  }
+
+  let wanted = match name.as_str() {
+    "min" => Ordering::Less,
+    _ => Ordering::Greater,
+  };
+  let mut result = number_arg(&list[1])?;
+  let mut inexact = !is_exact(result);
+  for obj in list[2..].iter() {
+    inexact |= !is_exact(number_arg(obj)?);
+    match compare_numbers(obj, result) {
+      Some(ordering) if ordering == wanted => result = obj,
+      Some(_) => {}
+      None => return Ok(Object::Float(f64::NAN)),
+    }
+  }
+  if inexact {
+    Ok(Object::Float(to_f64(result).unwrap()))
+  } else {
+    Ok(result.clone())
+  }
+}
+
+pub fn eval_gcd_lcm(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  let name = list[0].to_string();
+  let mut result = match name.as_str() {
+    "gcd" => BigInt::zero(),
+    _ => BigInt::from(1),
+  };
+  for obj in list[1..].iter() {
+    let n = to_bigint(obj).ok_or_else(|| {
+      format!("{} is not an exact integer", obj)
+    })?;
+    result = match name.as_str() {
+      "gcd" => result.gcd(&n),
+      _ => result.lcm(&n),
+    };
+  }
+  Ok(normalize(result))
+}
+
+// Exact bases raised to exact integer powers stay exact, the
+// size of the result is charged to the quota up front.
+pub fn eval_expt(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  if list.len() != 3 {
+    return Err(
+      "Invalid number of arguments for expt"
+        .to_string()
+        .into(),
+    );
+  }
+  let base = number_arg(&list[1])?;
+  let power = number_arg(&list[2])?;
+
+  if let (Some(b), Object::Integer(e)) =
+    (to_rational(base), power)
+  {
+    let e = i32::try_from(*e).map_err(|_| {
+      format!("Exponent {} is too large", e)
+    })?;
+    if b.is_zero() && e < 0 {
+      return Err(
+        "Division by zero in expt".to_string().into(),
+      );
+    }
+    let bits = b.numer().bits().max(b.denom().bits());
+    let bytes =
+      bits.saturating_mul(e.unsigned_abs() as u64);
+    env.borrow().charge((bytes / 8) as usize)?;
+    return Ok(normalize_ratio(b.pow(e)));
+  }
+
+  let b = to_f64(base).unwrap();
+  Ok(Object::Float(match power {
+    Object::Integer(e) if i32::try_from(*e).is_ok() => {
+      b.powi(*e as i32)
+    }
+    _ => b.powf(to_f64(power).unwrap()),
+  }))
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::env::*;
+  use crate::eval::eval;
+  use crate::object::*;
+  use num_bigint::BigInt;
+  use num_rational::BigRational;
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  #[test]
+  fn test_factorial_promotes() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define (fact n) (if (< n 2) 1 (* n (fact (- n 1)))))
+            (fact 30))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result,
+      Object::BigInt(
+        "265252859812191058636308480000000"
+          .parse()
+          .unwrap()
+      )
+    );
+  }
+
+  #[test]
+  fn test_demotes_to_integer() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (list
+            (- (+ 9223372036854775807 1) 1)
+            (/ 100000000000000000000 10000000000)
+            (> 100000000000000000000 5)
+            (* 100000000000000000000 0.5))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result,
+      Object::ListData(Rc::new(vec![
+        Object::Integer(i64::MAX),
+        Object::Integer(10000000000),
+        Object::Bool(true),
+        Object::Float(5e19),
+      ]))
+    );
+    assert_eq!(
+      eval("(- 0 9223372036854775808)", &mut env).unwrap(),
+      Object::Integer(i64::MIN)
+    );
+    assert_eq!(
+      eval("(* 4294967296 4294967296)", &mut env).unwrap(),
+      Object::BigInt(BigInt::from(1u64 << 32) << 32)
+    );
+    assert_eq!(
+      eval(
+        "(number->string (string->number \"-1_0\"))",
+        &mut env
+      )
+      .unwrap_err()
+      .to_string(),
+      "Cannot convert false in radix 10"
+    );
+    assert_eq!(
+      eval(
+        "(number->string (string->number \"10000000000000000000\") 16)",
+        &mut env
+      )
+      .unwrap(),
+      Object::String("8ac7230489e80000".into())
+    );
+  }
+
+  #[test]
+  fn test_division_by_zero() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (list
+            (guard (e (#t (error-object-message e))) (/ 1 0))
+            (guard (e (#t (error-object-message e))) (% 1 0))
+            (guard (e (#t (error-object-message e))) (/ 1/2 0))
+            (guard (e (#t (error-object-message e)))
+                (quotient 100000000000000000000 0))
+            (/ (- 0 9223372036854775808) (- 0 1))
+            (/ 1.0 0)
+            (/ (- 0 1) 0.0))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    let message = Object::String("Division by zero".into());
+    assert_eq!(
+      result,
+      Object::ListData(Rc::new(vec![
+        message.clone(),
+        message.clone(),
+        message,
+        Object::String(
+          "Division by zero in quotient".into()
+        ),
+        Object::BigInt(BigInt::from(1u64 << 63)),
+        Object::Float(f64::INFINITY),
+        Object::Float(f64::NEG_INFINITY),
+      ]))
+    );
+    match eval("(% 1 0.0)", &mut env).unwrap() {
+      Object::Float(f) => assert!(f.is_nan()),
+      obj => panic!("Expected NaN, got {}", obj),
+    }
+  }
+
+  #[test]
+  fn test_rationals() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (list
+            (/ 1 3)
+            (+ 1/3 2/3)
+            (* 3/4 2)
+            (< 1/3 0.34)
+            (exact 0.5)
+            (inexact 1/4)
+            (numerator 6/4)
+            (string->number \"-10/4\")
+            (/ 10 (- 0 4)))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    let ratio = |n: i64, d: i64| {
+      Object::Rational(BigRational::new(n.into(), d.into()))
+    };
+    assert_eq!(
+      result,
+      Object::ListData(Rc::new(vec![
+        ratio(1, 3),
+        Object::Integer(1),
+        ratio(3, 2),
+        Object::Bool(true),
+        ratio(1, 2),
+        Object::Float(0.25),
+        Object::Integer(3),
+        ratio(-5, 2),
+        ratio(-5, 2),
+      ]))
+    );
+  }
+
+  #[test]
+  fn test_math_procedures() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (list
+            (quotient (- 0 7) 2)
+            (remainder (- 0 7) 2)
+            (modulo (- 0 7) 2)
+            (round 5/2)
+            (round 3.5)
+            (floor (- 0 7/2))
+            (abs (- 0 9223372036854775808))
+            (max 1 2.0)
+            (gcd 12 (- 0 18))
+            (lcm 4 6)
+            (expt 2/3 3)
+            (expt 2 (- 0 2))
+            (expt 4 0.5)
+            (sqrt 16/9)
+            (sqrt 2)
+            (exact-integer? 5)
+            (integer? 2.0)
+            (odd? (- 0 3)))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    let ratio = |n: i64, d: i64| {
+      Object::Rational(BigRational::new(n.into(), d.into()))
+    };
+    assert_eq!(
+      result,
+      Object::ListData(Rc::new(vec![
+        Object::Integer(-3),
+        Object::Integer(-1),
+        Object::Integer(1),
+        Object::Integer(2),
+        Object::Float(4.0),
+        Object::Integer(-4),
+        Object::BigInt(BigInt::from(1u64 << 63)),
+        Object::Float(2.0),
+        Object::Integer(6),
+        Object::Integer(12),
+        ratio(8, 27),
+        ratio(1, 4),
+        Object::Float(2.0),
+        ratio(4, 3),
+        Object::Float(2f64.sqrt()),
+        Object::Bool(true),
+        Object::Bool(true),
+        Object::Bool(true),
+      ]))
+    );
+    assert_eq!(
+      eval("(atan 1 1)", &mut env).unwrap(),
+      Object::Float(std::f64::consts::FRAC_PI_4)
+    );
+    assert!(eval("(modulo 5 0)", &mut env).is_err());
+  }
+
+  #[test]
+  fn test_shadowing_math_procedures() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    eval("(define (abs x) (* x 10))", &mut env).unwrap();
+    let program = "
+        (list
+            (let ((max 3)) max)
+            (abs 2)
+            (map floor (list 1 2))
+            ((lambda (min round) (+ min round)) 1 2))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result.to_string(), "(3 20 (1 2) 3)");
+  }
+
+  #[test]
+  fn test_bignums_charge_quota() {
+    let program = |x| {
+      format!(
+        "(begin
+            (define (square n x)
+                (if (= n 0) (length (list x)) (square (- n 1) (* x x))))
+            (square 16 {}))",
+        x
+      )
+    };
+    for x in ["3", "2/3"] {
+      let mut env =
+        Rc::new(RefCell::new(Env::with_quota(1 << 14)));
+      assert!(eval(&program(x), &mut env)
+        .unwrap_err()
+        .to_string()
+        .starts_with("Resource limit exceeded"));
+    }
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    assert_eq!(
+      eval(&program("3"), &mut env),
+      Ok(Object::Integer(1))
+    );
+  }
+}
@@ -1,4 +1,11 @@
 use crate::env::*;
+use crate::eval::{Continuation, Generator, Promise};
+use crate::hashtables::HashTable;
+use crate::persistent::*;
+use crate::records::*;
+use crate::symbols::Symbol;
+use num_bigint::BigInt;
+use num_rational::BigRational;
 use std::cell::RefCell;
 use std::fmt;
 use std::rc::Rc;
@@ -9,13 +16,61 @@ pub enum Object {
   Keyword(String),
   BinaryOp(String),
   Integer(i64),
+  BigInt(BigInt),
+  Rational(BigRational),
   Float(f64),
   Bool(bool),
-  String(String),
-  Symbol(String),
-  ListData(Vec<Object>),
-  Lambda(Vec<String>, Rc<Vec<Object>>, Rc<RefCell<Env>>),
+  Char(char),
+  String(Rc<str>),
+  Symbol(Symbol),
+  // A variable the compile pass found in the slot index of
+  // the environment depth levels out.
+  Local(Symbol, usize, usize),
+  ListData(Rc<Vec<Object>>),
+  Lambda(
+    Rc<Vec<Symbol>>,
+    Rc<Vec<Object>>,
+    Rc<RefCell<Env>>,
+  ),
   List(Rc<Vec<Object>>),
+  Error(String, Vec<Object>),
+  Continuation(Rc<Continuation>),
+  Generator(Rc<Generator>),
+  Yield(Rc<Generator>),
+  Promise(Rc<Promise>),
+  Vector(Rc<RefCell<Vec<Object>>>),
+  HashTable(Rc<RefCell<HashTable>>),
+  PersistentMap(PersistentMap),
+  PersistentVector(PersistentVector),
+  Bytevector(Rc<RefCell<Vec<u8>>>),
+  Record(Rc<Record>),
+  RecordType(Rc<RecordType>),
+  RecordProcedure(Rc<RecordProcedure>),
+  // A builtin procedure or operator used as a value, called
+  // with the environment it was referred to from.
+  Builtin(Rc<Object>, Rc<RefCell<Env>>),
+}
+
+thread_local! {
+  // The vectors, hash tables and records being printed.
+  static PRINTING: RefCell<Vec<*const ()>> =
+    const { RefCell::new(Vec::new()) };
+}
+
+// Prints a vector, hash table or record, or #<cycle> where it
+// turns up again inside itself.
+fn print_once(
+  f: &mut fmt::Formatter,
+  ptr: *const (),
+  print: impl FnOnce(&mut fmt::Formatter) -> fmt::Result,
+) -> fmt::Result {
+  if PRINTING.with(|p| p.borrow().contains(&ptr)) {
+    return write!(f, "#<cycle>");
+  }
+  PRINTING.with(|p| p.borrow_mut().push(ptr));
+  let result = print(f);
+  PRINTING.with(|p| p.borrow_mut().pop());
+  result
 }
 
 impl fmt::Display for Object {
@@ -25,13 +80,18 @@ impl fmt::Display for Object {
       Object::Keyword(s) => write!(f, "{}", s),
       Object::BinaryOp(s) => write!(f, "{}", s),
       Object::Integer(n) => write!(f, "{}", n),
+      Object::BigInt(n) => write!(f, "{}", n),
+      Object::Rational(r) => write!(f, "{}", r),
       Object::Float(n) => write!(f, "{}", n),
       Object::Bool(b) => write!(f, "{}", b),
-      Object::Symbol(s) => write!(f, "{}", s),
+      Object::Char(c) => write!(f, "{}", c),
+      Object::Symbol(s) | Object::Local(s, _, _) => {
+        write!(f, "{}", s)
+      }
       Object::String(s) => write!(f, "{}", s),
       Object::Lambda(params, body, _env) => {
         write!(f, "Lambda(")?;
-        for param in params {
+        for param in params.iter() {
           write!(f, "{} ", param)?;
         }
         write!(f, ")")?;
@@ -60,6 +120,97 @@ impl fmt::Display for Object {
         }
         write!(f, ")")
       }
+      Object::Error(message, irritants) => {
+        write!(f, "{}", message)?;
+        for obj in irritants.iter() {
+          write!(f, " {}", obj)?;
+        }
+        Ok(())
+      }
+      Object::Continuation(_) => {
+        write!(f, "#<continuation>")
+      }
+      Object::Generator(_) => write!(f, "#<generator>"),
+      Object::Yield(_) => write!(f, "#<yield>"),
+      Object::Promise(_) => write!(f, "#<promise>"),
+      Object::Vector(items) => {
+        print_once(f, Rc::as_ptr(items).cast(), |f| {
+          write!(f, "#(")?;
+          for (i, obj) in items.borrow().iter().enumerate()
+          {
+            if i > 0 {
+              write!(f, " ")?;
+            }
+            write!(f, "{}", obj)?;
+          }
+          write!(f, ")")
+        })
+      }
+      Object::HashTable(t) => {
+        print_once(f, Rc::as_ptr(t).cast(), |f| {
+          write!(f, "{{")?;
+          for (i, (k, v)) in
+            t.borrow().entries().iter().enumerate()
+          {
+            if i > 0 {
+              write!(f, " ")?;
+            }
+            write!(f, "{} {}", k, v)?;
+          }
+          write!(f, "}}")
+        })
+      }
+      Object::PersistentMap(m) => {
+        write!(f, "#{{")?;
+        for (i, (k, v)) in m.iter().enumerate() {
+          if i > 0 {
+            write!(f, " ")?;
+          }
+          write!(f, "{} {}", k.0, v)?;
+        }
+        write!(f, "}}")
+      }
+      Object::PersistentVector(v) => {
+        write!(f, "[")?;
+        for (i, obj) in v.iter().enumerate() {
+          if i > 0 {
+            write!(f, " ")?;
+          }
+          write!(f, "{}", obj)?;
+        }
+        write!(f, "]")
+      }
+      Object::Bytevector(b) => {
+        write!(f, "#u8(")?;
+        for (i, byte) in b.borrow().iter().enumerate() {
+          if i > 0 {
+            write!(f, " ")?;
+          }
+          write!(f, "{}", byte)?;
+        }
+        write!(f, ")")
+      }
+      Object::Record(r) => {
+        print_once(f, Rc::as_ptr(r).cast(), |f| {
+          write!(f, "#<{}", r.rtd.display_name())?;
+          let fields = r.fields.borrow();
+          for (name, obj) in
+            r.rtd.fields.iter().zip(fields.iter())
+          {
+            write!(f, " {}: {}", name, obj)?;
+          }
+          write!(f, ">")
+        })
+      }
+      Object::RecordType(rtd) => {
+        write!(f, "#<record-type {}>", rtd.display_name())
+      }
+      Object::RecordProcedure(p) => {
+        write!(f, "#<procedure {}>", p.name)
+      }
+      Object::Builtin(op, _) => {
+        write!(f, "#<procedure {}>", op)
+      }
     }
   }
 }
@@ -1,5 +1,11 @@
+use crate::bytevectors::*;
+use crate::hashtables::*;
 use crate::lexer::*;
+use crate::macros::to_data;
+use crate::numbers::normalize_ratio;
 use crate::object::*;
+use crate::symbols::Symbol;
+use std::cell::RefCell;
 use std::error::Error;
 use std::fmt;
 use std::rc::Rc;
@@ -46,32 +52,157 @@ fn parse_list(
 
   let mut list: Vec<Object> = Vec::new();
   while !tokens.is_empty() {
-    let token = tokens.pop();
-    if token.is_none() {
+    if tokens.last() == Some(&Token::RParen) {
+      tokens.pop();
+      return Ok(Object::List(Rc::new(list)));
+    }
+    list.push(parse_datum(tokens)?);
+  }
+
+  Ok(Object::List(Rc::new(list)))
+}
+
+fn parse_datum(
+  tokens: &mut Vec<Token>,
+) -> Result<Object, ParseError> {
+  let token = tokens.pop();
+  if token.is_none() {
+    return Err(ParseError {
+      err: "Did not find enough tokens".to_string(),
+    });
+  }
+  let t = token.unwrap();
+  let quote = match t {
+    Token::Keyword(k) => return Ok(Object::Keyword(k)),
+    Token::BinaryOp(b) => return Ok(Object::BinaryOp(b)),
+    Token::Integer(n) => return Ok(Object::Integer(n)),
+    Token::BigInt(n) => return Ok(Object::BigInt(n)),
+    Token::Rational(r) => return Ok(normalize_ratio(r)),
+    Token::Float(f) => return Ok(Object::Float(f)),
+    Token::String(s) => {
+      return Ok(Object::String(s.into()))
+    }
+    Token::Symbol(s) => {
+      return Ok(Object::Symbol(Symbol::new(&s)))
+    }
+    Token::Char(c) => return Ok(Object::Char(c)),
+    Token::LParen => {
+      tokens.push(Token::LParen);
+      return parse_list(tokens);
+    }
+    Token::VectorStart => return parse_vector(tokens),
+    Token::BytevectorStart => {
+      return parse_bytevector(tokens)
+    }
+    Token::LBrace => return parse_hash_table(tokens),
+    Token::RBrace => {
+      return Err(ParseError {
+        err: "Unexpected RBrace".to_string(),
+      })
+    }
+    Token::RParen => {
       return Err(ParseError {
-        err: "Did not find enough tokens".to_string(),
-      });
+        err: "Unexpected RParen".to_string(),
+      })
     }
-    let t = token.unwrap();
-    match t {
-      Token::Keyword(k) => list.push(Object::Keyword(k)),
-      Token::BinaryOp(b) => list.push(Object::BinaryOp(b)),
-      Token::Integer(n) => list.push(Object::Integer(n)),
-      Token::Float(f) => list.push(Object::Float(f)),
-      Token::String(s) => list.push(Object::String(s)),
-      Token::Symbol(s) => list.push(Object::Symbol(s)),
-      Token::LParen => {
-        tokens.push(Token::LParen);
-        let sub_list = parse_list(tokens)?;
-        list.push(sub_list);
+    Token::Quote => "quote",
+    Token::Quasiquote => "quasiquote",
+    Token::Unquote => "unquote",
+    Token::UnquoteSplicing => "unquote-splicing",
+  };
+
+  // 'x is read as (quote x), and likewise for ` , and ,@
+  let datum = parse_datum(tokens)?;
+  Ok(Object::List(Rc::new(vec![
+    Object::Keyword(quote.to_string()),
+    datum,
+  ])))
+}
+
+// The elements of #( ) are literal data, like those of a
+// quoted list, and the vector evaluates to itself.
+fn parse_vector(
+  tokens: &mut Vec<Token>,
+) -> Result<Object, ParseError> {
+  let mut items = Vec::new();
+  loop {
+    match tokens.last() {
+      Some(Token::RParen) => {
+        tokens.pop();
+        break;
       }
-      Token::RParen => {
-        return Ok(Object::List(Rc::new(list)));
+      Some(_) => items.push(to_data(&parse_datum(tokens)?)),
+      None => {
+        return Err(ParseError {
+          err: "Unterminated vector".to_string(),
+        })
       }
     }
   }
+  Ok(Object::Vector(Rc::new(RefCell::new(items))))
+}
 
-  Ok(Object::List(Rc::new(list)))
+// #u8( ) holds integers from 0 to 255.
+fn parse_bytevector(
+  tokens: &mut Vec<Token>,
+) -> Result<Object, ParseError> {
+  let mut bytes = Vec::new();
+  loop {
+    match tokens.pop() {
+      Some(Token::RParen) => break,
+      Some(Token::Integer(n)) if (0..=255).contains(&n) => {
+        bytes.push(n as u8)
+      }
+      Some(token) => {
+        return Err(ParseError {
+          err: format!("Invalid byte {:?}", token),
+        })
+      }
+      None => {
+        return Err(ParseError {
+          err: "Unterminated bytevector".to_string(),
+        })
+      }
+    }
+  }
+  Ok(new_bytevector(bytes))
+}
+
+// {k v ...} holds literal data like #( ), a key given twice
+// keeps its last value.
+fn parse_hash_table(
+  tokens: &mut Vec<Token>,
+) -> Result<Object, ParseError> {
+  let mut items = Vec::new();
+  loop {
+    match tokens.last() {
+      Some(Token::RBrace) => {
+        tokens.pop();
+        break;
+      }
+      Some(_) => items.push(to_data(&parse_datum(tokens)?)),
+      None => {
+        return Err(ParseError {
+          err: "Unterminated hash table".to_string(),
+        })
+      }
+    }
+  }
+  if items.len() % 2 != 0 {
+    return Err(ParseError {
+      err: "Hash table literal needs a value for every key"
+        .to_string(),
+    });
+  }
+
+  let mut table = HashTable::default();
+  let mut items = items.into_iter();
+  while let (Some(k), Some(v)) =
+    (items.next(), items.next())
+  {
+    table.insert(k, v);
+  }
+  Ok(new_hash_table(table))
 }
 
 #[cfg(test)]
@@ -91,6 +222,24 @@ mod tests {
     );
   }
 
+  #[test]
+  fn test_quote() {
+    let list = parse("(car '(a 1))").unwrap();
+    assert_eq!(
+      list,
+      Object::List(Rc::new(vec![
+        Object::Keyword("car".to_string()),
+        Object::List(Rc::new(vec![
+          Object::Keyword("quote".to_string()),
+          Object::List(Rc::new(vec![
+            Object::Symbol(Symbol::new("a")),
+            Object::Integer(1),
+          ])),
+        ])),
+      ]))
+    );
+  }
+
   #[test]
   fn test_area_of_a_circle() {
     let program = "(
@@ -104,21 +253,21 @@ mod tests {
       Object::List(Rc::new(vec![
         Object::List(Rc::new(vec![
           Object::Keyword("define".to_string()),
-          Object::Symbol("r".to_string()),
+          Object::Symbol(Symbol::new("r")),
           Object::Integer(10),
         ])),
         Object::List(Rc::new(vec![
           Object::Keyword("define".to_string()),
-          Object::Symbol("pi".to_string()),
+          Object::Symbol(Symbol::new("pi")),
           Object::Integer(314),
         ])),
         Object::List(Rc::new(vec![
           Object::BinaryOp("*".to_string()),
-          Object::Symbol("pi".to_string()),
+          Object::Symbol(Symbol::new("pi")),
           Object::List(Rc::new(vec![
             Object::BinaryOp("*".to_string()),
-            Object::Symbol("r".to_string()),
-            Object::Symbol("r".to_string()),
+            Object::Symbol(Symbol::new("r")),
+            Object::Symbol(Symbol::new("r")),
           ])),
         ])),
       ]))
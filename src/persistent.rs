@@ -0,0 +1,413 @@
+use crate::env::*;
+use crate::equality::is_equal;
+use crate::eval::{charge_list, EvalError};
+use crate::hashtables::hash_key;
+use crate::object::*;
+use crate::strings::{check_args, index_arg};
+use archery::RcK;
+use rpds::{HashTrieMap, Vector};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::hash::{
+  BuildHasherDefault, DefaultHasher, Hash, Hasher,
+};
+use std::rc::Rc;
+
+// Map keys are compared with equal?, like hash table keys.
+#[derive(Debug, Clone)]
+pub struct Key(pub Object);
+
+impl PartialEq for Key {
+  fn eq(&self, other: &Key) -> bool {
+    is_equal(&self.0, &other.0)
+  }
+}
+
+impl Eq for Key {}
+
+impl Hash for Key {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    hash_key(&self.0).hash(state)
+  }
+}
+
+// A fixed hasher keeps the order of the entries the same
+// from one run to the next.
+pub type PersistentMap = HashTrieMap<
+  Key,
+  Object,
+  RcK,
+  BuildHasherDefault<DefaultHasher>,
+>;
+
+pub type PersistentVector = Vector<Object>;
+
+fn not_persistent(obj: &Object) -> EvalError {
+  format!("{} is not a persistent map or vector", obj)
+    .into()
+}
+
+// A vector is indexed by position, and can be extended by
+// setting the index just past its end.
+fn set_in(
+  coll: &Object,
+  key: &Object,
+  val: Object,
+) -> Result<Object, EvalError> {
+  match coll {
+    Object::PersistentMap(m) => Ok(Object::PersistentMap(
+      m.insert(Key(key.clone()), val),
+    )),
+    Object::PersistentVector(v) => {
+      let k = index_arg(key)?;
+      let v = match k.cmp(&v.len()) {
+        Ordering::Less => v.set(k, val),
+        Ordering::Equal => Some(v.push_back(val)),
+        Ordering::Greater => None,
+      };
+      v.map(Object::PersistentVector).ok_or_else(|| {
+        format!("Index {} out of range for assoc", k).into()
+      })
+    }
+    _ => Err(not_persistent(coll)),
+  }
+}
+
+fn get_in(
+  coll: &Object,
+  key: &Object,
+) -> Result<Option<Object>, EvalError> {
+  match coll {
+    Object::PersistentMap(m) => {
+      Ok(m.get(&Key(key.clone())).cloned())
+    }
+    Object::PersistentVector(v) => {
+      Ok(v.get(index_arg(key)?).cloned())
+    }
+    _ => Err(not_persistent(coll)),
+  }
+}
+
+// Maps are made along the path where nothing is there yet.
+fn assoc_in(
+  coll: &Object,
+  path: &[Object],
+  val: Object,
+) -> Result<Object, EvalError> {
+  let (key, rest) = match path.split_first() {
+    Some(split) => split,
+    None => return Ok(val),
+  };
+  let val = if rest.is_empty() {
+    val
+  } else {
+    let inner = get_in(coll, key)?.unwrap_or_else(|| {
+      Object::PersistentMap(PersistentMap::default())
+    });
+    assoc_in(&inner, rest, val)?
+  };
+  set_in(coll, key, val)
+}
+
+fn path_arg(obj: &Object) -> Result<&[Object], EvalError> {
+  match obj {
+    Object::ListData(path) => Ok(path),
+    _ => Err(format!("{} is not a list", obj).into()),
+  }
+}
+
+pub fn eval_persistent_map(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  if list.len().is_multiple_of(2) {
+    return Err(
+      "persistent-map needs a value for every key"
+        .to_string()
+        .into(),
+    );
+  }
+  charge_list(list.len() - 1, env)?;
+  let pairs = list[1..].chunks(2);
+  let m =
+    pairs.map(|kv| (Key(kv[0].clone()), kv[1].clone()));
+  Ok(Object::PersistentMap(m.collect()))
+}
+
+pub fn eval_persistent_vector(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  charge_list(list.len() - 1, env)?;
+  Ok(Object::PersistentVector(
+    list[1..].iter().cloned().collect(),
+  ))
+}
+
+pub fn eval_is_persistent(
+  list: &[Object],
+  map: bool,
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 1)?;
+  let result = matches!(
+    (map, &list[1]),
+    (true, Object::PersistentMap(_))
+      | (false, Object::PersistentVector(_))
+  );
+  Ok(Object::Bool(result))
+}
+
+pub fn is_persistent(obj: Option<&Object>) -> bool {
+  matches!(
+    obj,
+    Some(Object::PersistentMap(_))
+      | Some(Object::PersistentVector(_))
+  )
+}
+
+// (assoc coll k v ...) on a persistent map or vector, the
+// assoc of association lists is kept for everything else.
+// So looking up a persistent map as the key of an
+// association list takes this assoc instead.
+pub fn eval_assoc(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  if !list.len().is_multiple_of(2) {
+    return Err(
+      "Invalid number of arguments for assoc"
+        .to_string()
+        .into(),
+    );
+  }
+  charge_list(list.len() / 2, env)?;
+  let mut coll = list[1].clone();
+  for kv in list[2..].chunks(2) {
+    coll = set_in(&coll, &kv[0], kv[1].clone())?;
+  }
+  Ok(coll)
+}
+
+pub fn eval_dissoc(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 1, usize::MAX)?;
+  match &list[1] {
+    Object::PersistentMap(m) => {
+      let mut m = m.clone();
+      for key in list[2..].iter() {
+        m = m.remove(&Key(key.clone()));
+      }
+      Ok(Object::PersistentMap(m))
+    }
+    obj => {
+      Err(format!("{} is not a persistent map", obj).into())
+    }
+  }
+}
+
+// Elements are added to the end of a vector, and (key value)
+// lists or vectors to a map.
+pub fn eval_conj(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 1, usize::MAX)?;
+  charge_list(list.len() - 2, env)?;
+  let mut coll = list[1].clone();
+  for obj in list[2..].iter() {
+    coll = match (&coll, obj) {
+      (Object::PersistentVector(v), _) => {
+        Object::PersistentVector(v.push_back(obj.clone()))
+      }
+      (Object::PersistentMap(_), Object::ListData(kv))
+        if kv.len() == 2 =>
+      {
+        set_in(&coll, &kv[0], kv[1].clone())?
+      }
+      (
+        Object::PersistentMap(_),
+        Object::PersistentVector(kv),
+      ) if kv.len() == 2 => {
+        set_in(&coll, &kv[0], kv[1].clone())?
+      }
+      (Object::PersistentMap(_), _) => {
+        return Err(
+          format!("{} is not a key and a value", obj)
+            .into(),
+        )
+      }
+      _ => return Err(not_persistent(&coll)),
+    };
+  }
+  Ok(coll)
+}
+
+// get and get-in fail on a missing key unless they are given
+// a default, like hash-table-ref.
+pub fn eval_get(
+  list: &[Object],
+  in_path: bool,
+) -> Result<Object, EvalError> {
+  check_args(list, 2, 3)?;
+  let path = if in_path {
+    path_arg(&list[2])?
+  } else {
+    &list[2..3]
+  };
+  let mut coll = list[1].clone();
+  for key in path.iter() {
+    coll = match (get_in(&coll, key)?, list.get(3)) {
+      (Some(obj), _) => obj,
+      (None, Some(default)) => return Ok(default.clone()),
+      (None, None) => {
+        return Err(format!("Key {} not found", key).into())
+      }
+    };
+  }
+  Ok(coll)
+}
+
+pub fn eval_assoc_in(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 3, 3)?;
+  let path = path_arg(&list[2])?;
+  charge_list(path.len(), env)?;
+  assoc_in(&list[1], path, list[3].clone())
+}
+
+pub fn eval_count(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 1)?;
+  let n = match &list[1] {
+    Object::PersistentMap(m) => m.size(),
+    Object::PersistentVector(v) => v.len(),
+    obj => return Err(not_persistent(obj)),
+  };
+  Ok(Object::Integer(n as i64))
+}
+
+// A vector becomes the list of its elements and a map the
+// list of its (key value) entries.
+pub fn eval_persistent_to_list(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 1)?;
+  let items: Vec<Object> = match &list[1] {
+    Object::PersistentMap(m) => m
+      .iter()
+      .map(|(k, v)| {
+        Object::ListData(Rc::new(vec![
+          k.0.clone(),
+          v.clone(),
+        ]))
+      })
+      .collect(),
+    Object::PersistentVector(v) => {
+      v.iter().cloned().collect()
+    }
+    obj => return Err(not_persistent(obj)),
+  };
+  charge_list(items.len(), env)?;
+  Ok(Object::ListData(Rc::new(items)))
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::env::*;
+  use crate::eval::eval;
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  #[test]
+  fn test_persistent_updates() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define config
+                (persistent-map \"db\" (persistent-map \"port\" 5432)))
+            (define updated
+                (assoc-in config (list \"db\" \"port\") 6543))
+            (define v (persistent-vector 1 2))
+            (list
+                (get-in config (list \"db\" \"port\"))
+                (get-in updated (list \"db\" \"port\"))
+                (get-in updated (list \"cache\" \"size\") 0)
+                (get-in (assoc-in config (list \"cache\" \"size\") 8)
+                        (list \"cache\" \"size\"))
+                (count (dissoc updated \"db\"))
+                (conj v 3)
+                v
+                (assoc v 0 'a 2 'c)
+                (get (conj (persistent-map) (list 'k 1)) 'k)
+                (persistent->list (persistent-map 'k 1))
+                (equal? (persistent-vector 1 2) v)
+                (equal? config updated)
+                (persistent-vector? v)
+                (assoc 2 (list (list 1 'a) (list 2 'b)))))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result.to_string(),
+      "(5432 6543 0 8 0 [1 2 3] [1 2] [a 2 c] 1 ((k 1)) true \
+       false true (2 b))"
+    );
+    assert!(
+      eval("(get (persistent-map) 1)", &mut env).is_err()
+    );
+    assert!(eval(
+      "(assoc (persistent-vector) 1 1)",
+      &mut env
+    )
+    .is_err());
+    assert!(eval("(persistent-map 1)", &mut env).is_err());
+  }
+
+  #[test]
+  fn test_names_are_not_reserved() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    for program in
+      ["(define count 5)", "(define (f get) (+ get 1))"]
+    {
+      eval(program, &mut env).unwrap();
+    }
+    let program = "
+        (list
+            count
+            (f 1)
+            (let ((conj 2) (dissoc 3)) (+ conj dissoc))
+            (get (assoc (persistent-map 1 2) 3 4) 3)
+            (assoc 3 (list (list 1 2) (list 3 4)))
+            (assoc 3 (list (list 1 2) (list 4 5)) =))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result.to_string(), "(5 2 5 4 (3 4) false)");
+  }
+
+  #[test]
+  fn test_old_versions_are_kept() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define versions
+                (fold-left
+                    (lambda (vs n) (cons (conj (car vs) n) vs))
+                    (list (persistent-vector))
+                    (iota 1000)))
+            (list
+                (count (car versions))
+                (count (list-ref versions 500))
+                (get (car versions) 999)
+                (get (list-ref versions 1) 997)))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result.to_string(), "(1000 500 999 997)");
+  }
+}
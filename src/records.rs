@@ -0,0 +1,319 @@
+use crate::env::*;
+use crate::eval::EvalError;
+use crate::object::*;
+use crate::symbols::Symbol;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[derive(Debug, PartialEq)]
+pub struct RecordType {
+  pub name: String,
+  pub fields: Vec<String>,
+}
+
+impl RecordType {
+  // <point> is printed as point.
+  pub fn display_name(&self) -> &str {
+    let name =
+      self.name.strip_prefix('<').unwrap_or(&self.name);
+    name.strip_suffix('>').unwrap_or(name)
+  }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Record {
+  pub rtd: Rc<RecordType>,
+  pub fields: RefCell<Vec<Object>>,
+}
+
+// The constructor holds the field index of each of its
+// arguments, the accessors and modifiers the index of their
+// field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordOp {
+  Construct(Vec<usize>),
+  Predicate,
+  Access(usize),
+  Modify(usize),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RecordProcedure {
+  pub name: String,
+  pub rtd: Rc<RecordType>,
+  pub op: RecordOp,
+}
+
+fn name_arg(obj: &Object) -> Result<String, EvalError> {
+  match obj {
+    Object::Symbol(s) => Ok(s.to_string()),
+    _ => Err(
+      format!("Invalid name in define-record-type {}", obj)
+        .into(),
+    ),
+  }
+}
+
+fn names_arg(
+  obj: &Object,
+) -> Result<Vec<String>, EvalError> {
+  match obj {
+    Object::List(names) => {
+      names.iter().map(name_arg).collect()
+    }
+    _ => Err(
+      format!("Invalid spec in define-record-type {}", obj)
+        .into(),
+    ),
+  }
+}
+
+// (define-record-type <point> (make-point x y) point?
+//   (x point-x set-point-x!) (y point-y))
+// binds the type, the constructor, the predicate and the
+// accessors and modifiers of the fields.
+pub fn eval_define_record_type(
+  list: &[Object],
+  env: &Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  if list.len() < 4 {
+    return Err(
+      "Invalid number of arguments for define-record-type"
+        .to_string()
+        .into(),
+    );
+  }
+  // <point> is read as an operator, as it starts with <.
+  let type_name = match &list[1] {
+    Object::BinaryOp(s) => s.clone(),
+    obj => name_arg(obj)?,
+  };
+  let constructor = names_arg(&list[2])?;
+  let predicate = name_arg(&list[3])?;
+  let specs = list[4..]
+    .iter()
+    .map(names_arg)
+    .collect::<Result<Vec<_>, _>>()?;
+
+  let fields: Vec<String> = specs
+    .iter()
+    .filter_map(|s| s.first().cloned())
+    .collect();
+  for (i, field) in fields.iter().enumerate() {
+    if fields[..i].contains(field) {
+      return Err(
+        format!(
+          "Duplicate field {} in define-record-type {}",
+          field, type_name
+        )
+        .into(),
+      );
+    }
+  }
+  let rtd = Rc::new(RecordType {
+    name: type_name.clone(),
+    fields,
+  });
+  let field_index = |name: &str| {
+    rtd.fields.iter().position(|f| f == name).ok_or_else(
+      || {
+        format!("{} is not a field of {}", name, type_name)
+      },
+    )
+  };
+
+  let mut procedures = Vec::new();
+  if let Some((name, args)) = constructor.split_first() {
+    let indices = args
+      .iter()
+      .map(|arg| field_index(arg))
+      .collect::<Result<_, _>>()?;
+    procedures
+      .push((name.clone(), RecordOp::Construct(indices)));
+  }
+  procedures.push((predicate, RecordOp::Predicate));
+  for (i, spec) in specs.iter().enumerate() {
+    match spec.as_slice() {
+      [_, accessor] => procedures
+        .push((accessor.clone(), RecordOp::Access(i))),
+      [_, accessor, modifier] => {
+        procedures
+          .push((accessor.clone(), RecordOp::Access(i)));
+        procedures
+          .push((modifier.clone(), RecordOp::Modify(i)));
+      }
+      _ => {
+        return Err(
+          format!(
+            "Invalid field spec in define-record-type {}",
+            list[4 + i]
+          )
+          .into(),
+        )
+      }
+    }
+  }
+
+  let mut env = env.borrow_mut();
+  env.set(
+    Symbol::new(&type_name),
+    Object::RecordType(rtd.clone()),
+  );
+  for (name, op) in procedures {
+    let procedure = RecordProcedure {
+      name: name.clone(),
+      rtd: rtd.clone(),
+      op,
+    };
+    env.set(
+      Symbol::new(&name),
+      Object::RecordProcedure(Rc::new(procedure)),
+    );
+  }
+  Ok(Object::Void)
+}
+
+pub fn apply_record_procedure(
+  p: &RecordProcedure,
+  args: Vec<Object>,
+) -> Result<Object, EvalError> {
+  let arity = match &p.op {
+    RecordOp::Construct(indices) => indices.len(),
+    RecordOp::Predicate | RecordOp::Access(_) => 1,
+    RecordOp::Modify(_) => 2,
+  };
+  if args.len() != arity {
+    return Err(
+      format!("Invalid number of arguments for {}", p.name)
+        .into(),
+    );
+  }
+
+  if let RecordOp::Construct(indices) = &p.op {
+    let mut fields = vec![Object::Void; p.rtd.fields.len()];
+    for (i, arg) in indices.iter().zip(args) {
+      fields[*i] = arg;
+    }
+    let record = Record {
+      rtd: p.rtd.clone(),
+      fields: RefCell::new(fields),
+    };
+    return Ok(Object::Record(Rc::new(record)));
+  }
+
+  let record = match &args[0] {
+    Object::Record(r) if Rc::ptr_eq(&r.rtd, &p.rtd) => r,
+    _ if p.op == RecordOp::Predicate => {
+      return Ok(Object::Bool(false))
+    }
+    obj => {
+      return Err(
+        format!(
+          "{} is not a {}",
+          obj,
+          p.rtd.display_name()
+        )
+        .into(),
+      )
+    }
+  };
+
+  match &p.op {
+    RecordOp::Access(i) => {
+      Ok(record.fields.borrow()[*i].clone())
+    }
+    RecordOp::Modify(i) => {
+      record.fields.borrow_mut()[*i] = args[1].clone();
+      Ok(Object::Void)
+    }
+    _ => Ok(Object::Bool(true)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::env::*;
+  use crate::eval::eval;
+  use crate::symbols::Symbol;
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  #[test]
+  fn test_define_record_type() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let types = [
+      "(define-record-type <point>
+           (make-point x y)
+           point?
+           (x point-x set-point-x!)
+           (y point-y))",
+      "(define-record-type account
+           (make-account owner)
+           account?
+           (owner account-owner)
+           (balance account-balance set-account-balance!))",
+    ];
+    for program in types {
+      eval(program, &mut env).unwrap();
+    }
+    let program = "
+        (begin
+            (define p (make-point 1 2))
+            (define a (make-account \"ann\"))
+            (set-point-x! p 10)
+            (set-account-balance! a 5)
+            (list
+                p
+                (point-x p)
+                (point-y p)
+                (point? p)
+                (point? a)
+                (point? (list 1 2))
+                (map point-y (list p (make-point 3 4)))
+                (account-balance a)
+                a))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result.to_string(),
+      "(#<point x: 10 y: 2> 10 2 true false false (2 4) 5 \
+       #<account owner: ann balance: 5>)"
+    );
+    for program in [
+      "(point-x (make-account \"bob\"))",
+      "(make-point 1)",
+      "(set-point-x! (make-point 1 2))",
+      "(define-record-type t (make-t z) t? (x t-x))",
+    ] {
+      assert!(
+        eval(program, &mut env).is_err(),
+        "{}",
+        program
+      );
+    }
+  }
+
+  #[test]
+  fn test_duplicate_fields() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (define-record-type pair
+            (make-pair a b)
+            pair?
+            (a pair-a)
+            (b pair-b)
+            (a pair-first))
+        ";
+
+    let err = eval(program, &mut env).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "Duplicate field a in define-record-type pair"
+    );
+    assert!(env
+      .borrow()
+      .get(Symbol::new("make-pair"))
+      .is_none());
+  }
+}
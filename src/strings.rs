@@ -0,0 +1,544 @@
+use crate::env::*;
+use crate::eval::EvalError;
+use crate::numbers::{normalize, normalize_ratio};
+use crate::object::*;
+use crate::symbols::Symbol;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::Signed;
+use std::cell::RefCell;
+use std::mem::size_of;
+use std::rc::Rc;
+
+pub fn check_args(
+  list: &[Object],
+  min: usize,
+  max: usize,
+) -> Result<(), EvalError> {
+  let n = list.len() - 1;
+  if n < min || n > max {
+    return Err(
+      format!(
+        "Invalid number of arguments for {}",
+        list[0]
+      )
+      .into(),
+    );
+  }
+  Ok(())
+}
+
+pub fn string_arg(obj: &Object) -> Result<&str, EvalError> {
+  match obj {
+    Object::String(s) => Ok(s),
+    _ => Err(format!("{} is not a string", obj).into()),
+  }
+}
+
+pub fn index_arg(obj: &Object) -> Result<usize, EvalError> {
+  match obj {
+    Object::Integer(n) if *n >= 0 => Ok(*n as usize),
+    _ => Err(format!("Invalid index {}", obj).into()),
+  }
+}
+
+fn new_string(
+  s: String,
+  env: &Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  env.borrow().charge(s.len())?;
+  Ok(Object::String(s.into()))
+}
+
+// Indices count chars, not bytes.
+fn char_range(
+  s: &str,
+  start: usize,
+  end: usize,
+) -> Result<&str, EvalError> {
+  let len = s.chars().count();
+  if start > end || end > len {
+    return Err(
+      format!(
+        "Invalid range {} {} for string of length {}",
+        start, end, len
+      )
+      .into(),
+    );
+  }
+  let offset = |i: usize| {
+    s.char_indices().nth(i).map_or(s.len(), |(b, _)| b)
+  };
+  Ok(&s[offset(start)..offset(end)])
+}
+
+fn char_index(s: &str, byte: usize) -> Object {
+  Object::Integer(s[..byte].chars().count() as i64)
+}
+
+pub fn eval_is_string(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 1)?;
+  Ok(Object::Bool(matches!(list[1], Object::String(_))))
+}
+
+pub fn eval_string_length(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 1)?;
+  let s = string_arg(&list[1])?;
+  Ok(Object::Integer(s.chars().count() as i64))
+}
+
+pub fn eval_string_ref(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 2, 2)?;
+  let s = string_arg(&list[1])?;
+  let k = index_arg(&list[2])?;
+  match s.chars().nth(k) {
+    Some(c) => Ok(Object::Char(c)),
+    None => Err(
+      format!("Index {} out of range for {:?}", k, s)
+        .into(),
+    ),
+  }
+}
+
+pub fn eval_substring(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 2, 3)?;
+  let s = string_arg(&list[1])?;
+  let start = index_arg(&list[2])?;
+  let end = match list.get(3) {
+    Some(end) => index_arg(end)?,
+    None => s.chars().count(),
+  };
+  new_string(char_range(s, start, end)?.to_string(), env)
+}
+
+pub fn eval_string_append(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  let mut result = String::new();
+  for obj in list[1..].iter() {
+    result.push_str(string_arg(obj)?);
+  }
+  new_string(result, env)
+}
+
+// Split on a string or char separator, or on runs of
+// whitespace when there is none.
+pub fn eval_string_split(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 2)?;
+  let s = string_arg(&list[1])?;
+  let parts: Vec<&str> = match list.get(2) {
+    None => s.split_whitespace().collect(),
+    Some(Object::Char(c)) => s.split(*c).collect(),
+    Some(Object::String(sep)) if !sep.is_empty() => {
+      s.split(&**sep).collect()
+    }
+    Some(obj) => {
+      return Err(
+        format!("Invalid separator {}", obj).into(),
+      )
+    }
+  };
+
+  let mut items = Vec::new();
+  for part in parts {
+    items.push(new_string(part.to_string(), env)?);
+  }
+  Ok(Object::ListData(Rc::new(items)))
+}
+
+pub fn eval_string_join(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 2)?;
+  let items = match &list[1] {
+    Object::ListData(items) => items,
+    obj => {
+      return Err(format!("{} is not a list", obj).into())
+    }
+  };
+  let sep = match list.get(2) {
+    Some(sep) => string_arg(sep)?,
+    None => " ",
+  };
+
+  let mut parts = Vec::new();
+  for obj in items.iter() {
+    parts.push(string_arg(obj)?);
+  }
+  new_string(parts.join(sep), env)
+}
+
+// The index of the first char equal to the char argument.
+// With a procedure to test the chars with instead it runs on
+// the machine, as the procedure may yield or capture a
+// continuation.
+pub fn eval_string_index(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 2, 2)?;
+  let s = string_arg(&list[1])?;
+  let wanted = match &list[2] {
+    Object::Char(wanted) => *wanted,
+    obj => {
+      return Err(format!("{} is not a char", obj).into())
+    }
+  };
+  match s.chars().position(|c| c == wanted) {
+    Some(i) => Ok(Object::Integer(i as i64)),
+    None => Ok(Object::Bool(false)),
+  }
+}
+
+pub fn eval_string_contains(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 2, 2)?;
+  let s = string_arg(&list[1])?;
+  let needle = string_arg(&list[2])?;
+  match s.find(needle) {
+    Some(byte) => Ok(char_index(s, byte)),
+    None => Ok(Object::Bool(false)),
+  }
+}
+
+pub fn eval_string_case(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 1)?;
+  let s = string_arg(&list[1])?;
+  let result = match list[0].to_string().as_str() {
+    "string-upcase" => s.to_uppercase(),
+    _ => s.to_lowercase(),
+  };
+  new_string(result, env)
+}
+
+pub fn eval_string_trim(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 1)?;
+  let s = string_arg(&list[1])?;
+  let result = match list[0].to_string().as_str() {
+    "string-trim-left" => s.trim_start(),
+    "string-trim-right" => s.trim_end(),
+    _ => s.trim(),
+  };
+  new_string(result.to_string(), env)
+}
+
+pub fn eval_string_replace(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 3, 3)?;
+  let s = string_arg(&list[1])?;
+  let from = string_arg(&list[2])?;
+  let to = string_arg(&list[3])?;
+  if from.is_empty() {
+    return Err(
+      "Cannot replace an empty string".to_string().into(),
+    );
+  }
+  new_string(s.replace(from, to), env)
+}
+
+pub fn eval_string_to_list(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 3)?;
+  let s = string_arg(&list[1])?;
+  let start = match list.get(2) {
+    Some(start) => index_arg(start)?,
+    None => 0,
+  };
+  let end = match list.get(3) {
+    Some(end) => index_arg(end)?,
+    None => s.chars().count(),
+  };
+  let chars: Vec<Object> = char_range(s, start, end)?
+    .chars()
+    .map(Object::Char)
+    .collect();
+  env.borrow().charge(chars.len() * size_of::<Object>())?;
+  Ok(Object::ListData(Rc::new(chars)))
+}
+
+pub fn eval_list_to_string(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 1)?;
+  let items = match &list[1] {
+    Object::ListData(items) => items,
+    obj => {
+      return Err(format!("{} is not a list", obj).into())
+    }
+  };
+  let mut result = String::new();
+  for obj in items.iter() {
+    match obj {
+      Object::Char(c) => result.push(*c),
+      _ => {
+        return Err(format!("{} is not a char", obj).into())
+      }
+    }
+  }
+  new_string(result, env)
+}
+
+fn radix_arg(list: &[Object]) -> Result<u32, EvalError> {
+  match list.get(2) {
+    None => Ok(10),
+    Some(Object::Integer(r @ (2 | 8 | 10 | 16))) => {
+      Ok(*r as u32)
+    }
+    Some(obj) => {
+      Err(format!("Invalid radix {}", obj).into())
+    }
+  }
+}
+
+// The bignum parser also skips underscores, so check the
+// digits first.
+fn parse_integer(s: &str, radix: u32) -> Option<BigInt> {
+  let digits = s.strip_prefix(['+', '-']).unwrap_or(s);
+  if digits.is_empty()
+    || !digits.chars().all(|c| c.is_digit(radix))
+  {
+    return None;
+  }
+  BigInt::parse_bytes(s.as_bytes(), radix)
+}
+
+// Returns #f for text that is not a number in the radix.
+pub fn eval_string_to_number(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 2)?;
+  let s = string_arg(&list[1])?;
+  let radix = radix_arg(list)?;
+  if let Ok(n) = i64::from_str_radix(s, radix) {
+    return Ok(Object::Integer(n));
+  }
+  if let Some(n) = parse_integer(s, radix) {
+    return Ok(normalize(n));
+  }
+  if let Some((numer, denom)) = s.split_once('/') {
+    let numer = parse_integer(numer, radix);
+    let denom = parse_integer(denom, radix).filter(|d| {
+      d.is_positive() && !denom.starts_with('+')
+    });
+    if let (Some(numer), Some(denom)) = (numer, denom) {
+      return Ok(normalize_ratio(BigRational::new(
+        numer, denom,
+      )));
+    }
+  }
+  // Rust also reads names like "inf" and "NaN" as floats,
+  // only take digits, a point and an exponent.
+  let float_chars =
+    |c: char| c.is_ascii_digit() || "+-.eE".contains(c);
+  if radix == 10 && s.chars().all(float_chars) {
+    if let Ok(f) = s.parse::<f64>() {
+      return Ok(Object::Float(f));
+    }
+  }
+  Ok(Object::Bool(false))
+}
+
+pub fn eval_number_to_string(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 2)?;
+  let radix = radix_arg(list)?;
+  let result = match (&list[1], radix) {
+    (Object::Integer(n), _) => {
+      let sign = if *n < 0 { "-" } else { "" };
+      let n = n.unsigned_abs();
+      match radix {
+        2 => format!("{}{:b}", sign, n),
+        8 => format!("{}{:o}", sign, n),
+        16 => format!("{}{:x}", sign, n),
+        _ => format!("{}{}", sign, n),
+      }
+    }
+    (Object::BigInt(n), _) => n.to_str_radix(radix),
+    (Object::Rational(r), _) => format!(
+      "{}/{}",
+      r.numer().to_str_radix(radix),
+      r.denom().to_str_radix(radix)
+    ),
+    (Object::Float(f), 10) => f.to_string(),
+    (obj, _) => {
+      return Err(
+        format!(
+          "Cannot convert {} in radix {}",
+          obj, radix
+        )
+        .into(),
+      )
+    }
+  };
+  new_string(result, env)
+}
+
+// Interned names are never freed, so the quota is charged
+// for the name even when the symbol already exists.
+pub fn eval_string_to_symbol(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 1)?;
+  let s = string_arg(&list[1])?;
+  env.borrow().charge(s.len())?;
+  Ok(Object::Symbol(Symbol::new(s)))
+}
+
+pub fn eval_symbol_to_string(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 1)?;
+  match &list[1] {
+    Object::Symbol(s) => new_string(s.to_string(), env),
+    obj => Err(format!("{} is not a symbol", obj).into()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::env::*;
+  use crate::eval::eval;
+  use crate::object::*;
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  fn strings(items: &[&str]) -> Object {
+    Object::ListData(Rc::new(
+      items
+        .iter()
+        .map(|s| Object::String((*s).into()))
+        .collect(),
+    ))
+  }
+
+  #[test]
+  fn test_unicode_indices() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (list
+            (string-length \"héllo wörld\")
+            (substring \"héllo wörld\" 1 4)
+            (string-ref \"héllo\" 1)
+            (string-contains \"héllo wörld\" \"wö\")
+            (string-index \"héllo\" #\\l)
+            (string-index \"abc\" (lambda (c) (char=? c #\\z))))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result,
+      Object::ListData(Rc::new(vec![
+        Object::Integer(11),
+        Object::String("éll".into()),
+        Object::Char('é'),
+        Object::Integer(6),
+        Object::Integer(2),
+        Object::Bool(false),
+      ]))
+    );
+  }
+
+  #[test]
+  fn test_split_join_replace() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (list
+            (string-split \"  a b\tc \")
+            (string-split \"a,b,,c\" #\\,)
+            (string-join (list \"x\" \"y\" \"z\") \"-\")
+            (string-replace \"a-b-c\" \"-\" \"+\")
+            (string-trim \"  hi  \")
+            (string-upcase \"straße\"))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result,
+      Object::ListData(Rc::new(vec![
+        strings(&["a", "b", "c"]),
+        strings(&["a", "b", "", "c"]),
+        Object::String("x-y-z".into()),
+        Object::String("a+b+c".into()),
+        Object::String("hi".into()),
+        Object::String("STRASSE".into()),
+      ]))
+    );
+  }
+
+  #[test]
+  fn test_number_conversion() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (list
+            (string->number \"ff\" 16)
+            (string->number \"2.5\")
+            (string->number \"nan\")
+            (number->string 255 2)
+            (number->string (- 0 255) 16)
+            (list->string (string->list \"abc\" 1)))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result,
+      Object::ListData(Rc::new(vec![
+        Object::Integer(255),
+        Object::Float(2.5),
+        Object::Bool(false),
+        Object::String("11111111".into()),
+        Object::String("-ff".into()),
+        Object::String("bc".into()),
+      ]))
+    );
+  }
+
+  #[test]
+  fn test_symbol_conversion() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (list
+            (eq? (string->symbol \"abc\") 'abc)
+            (eq? (string->symbol \"abc\") 'abd)
+            (symbol->string 'abc)
+            (string->symbol (string-append \"a\" \"bc\")))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result.to_string(), "(true false abc abc)");
+    assert!(
+      eval("(symbol->string \"abc\")", &mut env).is_err()
+    );
+    assert!(
+      eval("(string->symbol 'abc)", &mut env).is_err()
+    );
+  }
+}
@@ -0,0 +1,156 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+// Symbols are interned, so that comparing and hashing them,
+// as every variable lookup does, works on a number instead
+// of the whole name, and copying code copies no strings.
+// Names are never freed, there are only as many as the
+// programs and gensym make.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+// The symbols the evaluator looks for get the first ids.
+const RESERVED: [&str; 3] = ["#t", "#f", "#nil"];
+pub const TRUE: Symbol = Symbol(0);
+pub const FALSE: Symbol = Symbol(1);
+pub const NIL: Symbol = Symbol(2);
+
+struct Interner {
+  names: Vec<Rc<str>>,
+  ids: HashMap<Rc<str>, u32>,
+  originals: HashMap<u32, Symbol>,
+}
+
+impl Interner {
+  fn new() -> Self {
+    let names: Vec<Rc<str>> =
+      RESERVED.iter().map(|name| Rc::from(*name)).collect();
+    let ids = names
+      .iter()
+      .enumerate()
+      .map(|(id, name)| (name.clone(), id as u32))
+      .collect();
+    Interner {
+      names,
+      ids,
+      originals: HashMap::new(),
+    }
+  }
+}
+
+thread_local! {
+  static SYMBOLS: RefCell<Interner> =
+    RefCell::new(Interner::new());
+}
+
+impl Symbol {
+  pub fn new(name: &str) -> Symbol {
+    SYMBOLS.with(|symbols| {
+      let mut symbols = symbols.borrow_mut();
+      if let Some(id) = symbols.ids.get(name) {
+        return Symbol(*id);
+      }
+      let id = symbols.names.len() as u32;
+      let name: Rc<str> = Rc::from(name);
+      symbols.names.push(name.clone());
+      symbols.ids.insert(name, id);
+      Symbol(id)
+    })
+  }
+
+  // A symbol that is not in the table, so that no other
+  // symbol equals it, even one read or made later with the
+  // same name. gensym makes these.
+  pub fn uninterned(name: &str) -> Symbol {
+    SYMBOLS.with(|symbols| {
+      let mut symbols = symbols.borrow_mut();
+      let id = symbols.names.len() as u32;
+      symbols.names.push(Rc::from(name));
+      Symbol(id)
+    })
+  }
+
+  // An uninterned symbol standing for `of` in the templates
+  // of a macro. The environment the macro is defined in
+  // looks it up as `of`.
+  pub fn alias(of: Symbol) -> Symbol {
+    let alias = Symbol::uninterned(&of.name());
+    let original = of.original();
+    SYMBOLS.with(|symbols| {
+      symbols
+        .borrow_mut()
+        .originals
+        .insert(alias.0, original)
+    });
+    alias
+  }
+
+  // The symbol an alias was first made for, or the symbol
+  // itself.
+  pub fn original(&self) -> Symbol {
+    SYMBOLS.with(|symbols| {
+      match symbols.borrow().originals.get(&self.0) {
+        Some(original) => *original,
+        None => *self,
+      }
+    })
+  }
+
+  pub fn name(&self) -> Rc<str> {
+    SYMBOLS.with(|symbols| {
+      symbols.borrow().names[self.0 as usize].clone()
+    })
+  }
+}
+
+impl PartialEq<str> for Symbol {
+  fn eq(&self, other: &str) -> bool {
+    *self.name() == *other
+  }
+}
+
+impl fmt::Display for Symbol {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.name())
+  }
+}
+
+impl fmt::Debug for Symbol {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Symbol({:?})", self.name())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_interning() {
+    let a = Symbol::new("apple");
+    assert_eq!(a, Symbol::new("apple"));
+    assert_ne!(a, Symbol::new("banana"));
+    assert_eq!(&*a.name(), "apple");
+    assert!(a == *"apple");
+    assert_eq!(
+      format!("{} {:?}", a, a),
+      "apple Symbol(\"apple\")"
+    );
+    assert_eq!(Symbol::new("#nil"), NIL);
+
+    let fresh = Symbol::uninterned("apple");
+    assert_ne!(fresh, a);
+    assert_ne!(fresh, Symbol::uninterned("apple"));
+    assert_eq!(Symbol::new("apple"), a);
+    assert_eq!(fresh.to_string(), "apple");
+
+    let alias = Symbol::alias(a);
+    assert_ne!(alias, a);
+    assert_eq!(alias.original(), a);
+    assert_eq!(Symbol::alias(alias).original(), a);
+    assert_eq!(a.original(), a);
+    assert_eq!(alias.to_string(), "apple");
+  }
+}
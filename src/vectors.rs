@@ -0,0 +1,201 @@
+use crate::env::*;
+use crate::eval::{alloc_vec, charge_list, EvalError};
+use crate::object::*;
+use crate::strings::{check_args, index_arg};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub fn new_vector(items: Vec<Object>) -> Object {
+  Object::Vector(Rc::new(RefCell::new(items)))
+}
+
+fn vector_arg(
+  obj: &Object,
+) -> Result<&Rc<RefCell<Vec<Object>>>, EvalError> {
+  match obj {
+    Object::Vector(v) => Ok(v),
+    _ => Err(format!("{} is not a vector", obj).into()),
+  }
+}
+
+fn out_of_range(k: usize, len: usize) -> EvalError {
+  format!(
+    "Index {} out of range for vector of length {}",
+    k, len
+  )
+  .into()
+}
+
+pub fn eval_make_vector(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 2)?;
+  let k = index_arg(&list[1])?;
+  charge_list(k, env)?;
+  let fill =
+    list.get(2).cloned().unwrap_or(Object::Bool(false));
+  let mut items = alloc_vec(k)?;
+  items.resize(k, fill);
+  Ok(new_vector(items))
+}
+
+pub fn eval_vector(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  charge_list(list.len() - 1, env)?;
+  Ok(new_vector(list[1..].to_vec()))
+}
+
+pub fn eval_is_vector(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 1)?;
+  Ok(Object::Bool(matches!(list[1], Object::Vector(_))))
+}
+
+pub fn eval_vector_length(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 1)?;
+  let v = vector_arg(&list[1])?;
+  Ok(Object::Integer(v.borrow().len() as i64))
+}
+
+pub fn eval_vector_ref(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 2, 2)?;
+  let v = vector_arg(&list[1])?.borrow();
+  let k = index_arg(&list[2])?;
+  v.get(k).cloned().ok_or_else(|| out_of_range(k, v.len()))
+}
+
+// Every holder of the vector sees the change.
+pub fn eval_vector_set(
+  list: &[Object],
+) -> Result<Object, EvalError> {
+  check_args(list, 3, 3)?;
+  let mut v = vector_arg(&list[1])?.borrow_mut();
+  let k = index_arg(&list[2])?;
+  let len = v.len();
+  match v.get_mut(k) {
+    Some(slot) => *slot = list[3].clone(),
+    None => return Err(out_of_range(k, len)),
+  }
+  Ok(Object::Void)
+}
+
+pub fn eval_vector_to_list(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 1)?;
+  let items = vector_arg(&list[1])?.borrow().clone();
+  charge_list(items.len(), env)?;
+  Ok(Object::ListData(Rc::new(items)))
+}
+
+pub fn eval_list_to_vector(
+  list: &[Object],
+  env: &mut Rc<RefCell<Env>>,
+) -> Result<Object, EvalError> {
+  check_args(list, 1, 1)?;
+  match &list[1] {
+    Object::ListData(items) => {
+      charge_list(items.len(), env)?;
+      Ok(new_vector(items.to_vec()))
+    }
+    obj => Err(format!("{} is not a list", obj).into()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::env::*;
+  use crate::eval::eval;
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  #[test]
+  fn test_vector_procedures() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define v (make-vector 3 0))
+            (define w v)
+            (vector-set! v 1 'x)
+            (list
+                w
+                (vector-ref #(1 (2 3) \"a\") 1)
+                (vector-length (vector 1 2))
+                (vector-map (lambda (x y) (* x y)) #(1 2 3) #(4 5))
+                (vector->list #(1 2))
+                (list->vector (list 1 2))
+                (vector? #())))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result.to_string(),
+      "(#(0 x 0) (2 3) 2 #(4 10) (1 2) #(1 2) true)"
+    );
+    assert!(eval("(vector-ref #(1) 1)", &mut env).is_err());
+    assert!(
+      eval("(vector-set! #() 0 1)", &mut env).is_err()
+    );
+    assert!(eval(
+      "(make-vector 1000000000000000000 0)",
+      &mut env
+    )
+    .is_err());
+  }
+
+  #[test]
+  fn test_vector_loop() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define fib (make-vector 60 0))
+            (vector-set! fib 1 1)
+            (do ((i 2 (+ i 1))) ((= i 60) (vector-ref fib 59))
+                (vector-set! fib i
+                    (+ (vector-ref fib (- i 1))
+                       (vector-ref fib (- i 2))))))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(result.to_string(), "956722026041");
+  }
+
+  #[test]
+  fn test_vector_holding_itself() {
+    let mut env = Rc::new(RefCell::new(Env::new()));
+    let program = "
+        (begin
+            (define v (vector 1 2))
+            (define w (vector 1 2))
+            (define t (make-hash-table))
+            (vector-set! v 0 v)
+            (vector-set! w 0 w)
+            (hash-table-set! t v t)
+            (list
+                v
+                t
+                (equal? v v)
+                (equal? v w)
+                (equal? v (vector v 3))
+                (hash-table-ref t v 0)))
+        ";
+
+    let result = eval(program, &mut env).unwrap();
+    assert_eq!(
+      result.to_string(),
+      "(#(#<cycle> 2) {#(#<cycle> 2) #<cycle>} true true \
+       false {#(#<cycle> 2) #<cycle>})"
+    );
+    assert!(eval("(if v 1 2)", &mut env).is_err());
+    assert!(eval("(v 1)", &mut env).is_err());
+  }
+}
@@ -0,0 +1,21 @@
+use lisp_rs::{
+  lisp_rs_eval_to_bytes, lisp_rs_eval_with_bytes,
+};
+
+#[test]
+fn test_embedding_api() {
+  let res = lisp_rs_eval_with_bytes(
+    "(bytevector-uint-ref packet 1 'little 2)",
+    "packet",
+    &[9, 0x34, 0x12],
+  );
+  assert_eq!(res, "4660");
+  assert_eq!(
+    lisp_rs_eval_to_bytes("(string->utf8 \"ok\")"),
+    Ok(b"ok".to_vec())
+  );
+  assert_eq!(
+    lisp_rs_eval_to_bytes("(+ 1 2)"),
+    Err("3".to_string())
+  );
+}